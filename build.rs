@@ -20,6 +20,18 @@ const COPIES: &[CopySet] = &[
         old: "config/sus.rs",
         new: "src/bin/sus/config.rs",
     },
+    CopySet {
+        old: "config/sus-visudo.rs",
+        new: "src/bin/sus-visudo/config.rs",
+    },
+    CopySet {
+        old: "config/sus-cvtsudoers.rs",
+        new: "src/bin/sus-cvtsudoers/config.rs",
+    },
+    CopySet {
+        old: "config/sus-replay.rs",
+        new: "src/bin/sus-replay/config.rs",
+    },
 ];
 
 fn main() -> Result<(), std::io::Error> {
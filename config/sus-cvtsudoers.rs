@@ -0,0 +1,21 @@
+//! Configuration variables for `sus-cvtsudoers`
+//!
+//! This file defines the configuration constants for the `sus-cvtsudoers`
+//! program. These will be compiled into the final binary.
+//!
+//! There isn't anything to configure here yet. The kernel's configuration is
+//! pulled in since the kernel's modules are compiled into this binary too.
+//!
+//! Make sure to edit this file in `config/`. This file is copied to the `src/`
+//! directory as part of the build process. Any changes made there will be
+//! ignored by `cargo build`.
+
+#![allow(dead_code)]
+
+// This file is copied next to `main.rs`, so the path is relative to there
+// Don't let `rustfmt` try to follow it from `config/`
+#[allow(clippy::deprecated_cfg_attr)]
+#[cfg_attr(rustfmt, rustfmt::skip)]
+#[path = "../sus-kernel/config.rs"]
+mod kernel;
+pub use kernel::*;
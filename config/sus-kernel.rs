@@ -12,8 +12,14 @@
 use crate::executable;
 use crate::executable::factory::AutoExecutableFactory;
 use crate::executable::run::Runner;
+#[cfg(feature = "log")]
+use crate::log::{ExitLogger, SandboxLogger, SessionLogger, SubcommandLogger};
 use crate::permission;
+use crate::permission::authenticate::Authenticator;
 use crate::permission::factory::AutoPermissionFactory;
+use crate::permission::verify::AutoVerifiersFactory;
+use crate::setting;
+use crate::setting::factory::AutoSettingsFactory;
 
 #[cfg(feature = "log")]
 use crate::log;
@@ -44,17 +50,218 @@ pub const CURRENT_PERMISSION_FACTORY: AutoPermissionFactory = permission::factor
 /// [eb]: executable::Executable
 pub const REQUESTED_PERMISSION_FACTORY: AutoPermissionFactory =
     permission::factory::from_commandline;
+/// The method to use to find the [Settings][st] the user asked for
+///
+/// These are taken from the user, but each one is checked against the policy
+/// before it's used.
+///
+/// [st]: setting::Settings
+pub const SETTINGS_FACTORY: AutoSettingsFactory = setting::factory::from_commandline;
+
+/// The method to use to create the [Verifier][vf]s to check requests with
+///
+/// By default, the policy at [SUDOER_PATH] is checked by a separate,
+/// unprivileged process. See [permission::verify::helper].
+///
+/// [vf]: permission::verify::Verifier
+pub const VERIFIERS_FACTORY: AutoVerifiersFactory = permission::verify::helper::from_helper;
+/// The method to make sure the user is who they say they are
+///
+/// This is only called once a [Verifier][vf] allows the request, and only if
+/// the policy turns on `authenticate`. See
+/// [permission::authenticate::from_password].
+///
+/// [vf]: permission::verify::Verifier
+pub const AUTHENTICATOR: Authenticator = permission::authenticate::from_password;
+
+/// The prompt to ask for the password with, if the user doesn't give one
+///
+/// Used by [permission::authenticate]. The user can give another with
+/// `sus -p`. Either can have the escapes described in
+/// [permission::authenticate::prompt::expand].
+pub const DEFAULT_PASSPROMPT: &str = "[sus] password for %p: ";
+/// How many times the user can get their password wrong
+///
+/// Used by [permission::authenticate].
+pub const PASSWD_TRIES: u32 = 3;
+/// How many seconds to wait after a wrong password
+///
+/// Used by [permission::authenticate]. This slows down guessing.
+pub const PASSWD_FAIL_DELAY: u64 = 2;
+/// How many seconds to wait for the password, if the policy doesn't say
+///
+/// Used by [permission::authenticate::prompt]. The policy can change this with
+/// `passwd_timeout`. Zero means to wait forever.
+pub const DEFAULT_PASSWD_TIMEOUT: u64 = 5 * 60;
 
 /// The method to run the [Executable][eb] created
 ///
+/// By default, this looks at the policy to decide which [Runner] to use. See
+/// [executable::run::select].
+///
+/// [eb]: executable::Executable
+pub const RUNNER: Runner = executable::run::select;
+/// The [Runner] to use if the policy doesn't say
+///
+/// Used by [executable::run::select]. The policy can pick a [Runner] with the
+/// `runner` option, either on a command or in `Defaults`. It can be `exec` for
+/// [executable::run::exec], or `supervise` for [executable::run::supervise].
+/// If the policy asks for the session to be recorded, with `log_input` or
+/// `log_output`, or for a new terminal with `use_pty`, [executable::run::pty]
+/// is always used instead. Otherwise, if it sets a `command_timeout`,
+/// [executable::run::supervise] is.
+pub const DEFAULT_RUNNER: Runner = executable::run::exec;
+
+/// How many seconds to wait after `SIGTERM` before sending `SIGKILL`
+///
+/// Used by [executable::run::supervise] when a command runs past its
+/// `command_timeout`. The command gets this long to clean up before it's
+/// killed outright.
+pub const COMMAND_TIMEOUT_GRACE: u32 = 5;
+
+/// Where the cgroup v2 filesystem is mounted
+///
+/// Used by [executable::run::cgroup]. This can also be a subtree delegated to
+/// SUS, as long as nothing runs in it directly.
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// The cgroup under [CGROUP_ROOT] to put commands' cgroups in
+///
+/// Used by [executable::run::cgroup]. It's created if it doesn't exist. Each
+/// [Executable][eb] gets a cgroup named `session-<pid>` under it, where the
+/// PID is the kernel's.
+///
+/// [eb]: executable::Executable
+pub const CGROUP_PARENT: &str = "sus.slice";
+
+/// The pseudo-command for editing files
+///
+/// Used by [executable::run::select]. An [Executable][eb] with this as its
+/// path isn't run. Instead, the files given as its arguments are edited with
+/// [executable::run::edit]. The policy allows it with a `sudoedit` command
+/// listing the files. This must match `KERNEL_SUDOEDIT_PATH` in the
+/// configuration for `sus`.
+///
+/// [eb]: executable::Executable
+pub const SUDOEDIT_PATH: &str = "sudoedit";
+/// The directory to put the copies of files being edited in
+///
+/// Used by [executable::run::edit]. The copies belong to the user, so this has
+/// to be somewhere they can create files.
+pub const SUDOEDIT_TMPDIR: &str = "/var/tmp";
+/// The editor to use if none of `SUDO_EDITOR`, `VISUAL`, or `EDITOR` are set
+///
+/// Used by `sus-visudo` and [executable::run::edit]. It's looked up in the
+/// user's `PATH`.
+pub const DEFAULT_EDITOR: &str = "vi";
+
+/// The lowest file descriptor to close before running the [Executable][eb]
+///
+/// Used by [executable::run::sanitize]. Everything the user had open from here
+/// up is closed, so the [Executable][eb] can't use it. The policy can change
+/// this with `closefrom`, and if it sets `closefrom_override`, the user can
+/// change it with `sus -C`. The standard streams are never closed.
+///
+/// [eb]: executable::Executable
+pub const DEFAULT_CLOSEFROM: i32 = 3;
+/// The umask to combine with the user's before running the
+/// [Executable][eb]
+///
+/// Used by [executable::run::sanitize]. The [Executable][eb] gets the user's
+/// umask with these bits added, so it never creates files more open than this
+/// allows. The policy can change this with `umask`. Like `sudo`, `0777` means
+/// to use the user's umask as-is.
+///
 /// [eb]: executable::Executable
-pub const RUNNER: Runner = executable::run::exec;
+pub const DEFAULT_UMASK: u32 = 0o022;
+
+/// Where to find the limits users get when they log in
+///
+/// Used by [executable::run::limits] when the policy sets `login_rlimits`.
+/// These are read like `pam_limits` reads them. Directories have every file
+/// ending in `.conf` read.
+pub const LOGIN_LIMITS_PATHS: &[&str] = &["/etc/security/limits.conf", "/etc/security/limits.d"];
+
+/// The umask the kernel uses for itself
+///
+/// Used by [crate::harden]. Anything the kernel creates, like the log or the
+/// recordings, gets this umask instead of the user's. The [Executable][eb]
+/// still gets the user's, combined with [DEFAULT_UMASK].
+///
+/// [eb]: executable::Executable
+pub const KERNEL_UMASK: u32 = 0o077;
+
+/// The directory to record sessions to
+///
+/// Used by [executable::run::pty]. Every recorded session gets its own
+/// directory under here. See [executable::run::iolog] for the layout.
+pub const IOLOG_DIR: &str = "/var/log/sus-io";
+/// The mode to create directories under [IOLOG_DIR] with
+///
+/// Recordings can contain anything the user typed, including passwords, so
+/// only `root` should be able to read them.
+pub const IOLOG_DIR_MODE: u32 = 0o700;
+/// The mode to create the files of a recording with
+pub const IOLOG_FILE_MODE: u32 = 0o600;
 
 /// The path to log to
 ///
 /// The path to sudoers file. For readability purpose, this is represented as JSON
 /// and not traditional sudoers file syntax
 pub const SUDOER_PATH: &str = "/etc/sudoers.json";
+/// The UID that must own the sudoers file and every directory containing it
+///
+/// If the owner is anyone else, they could edit the policy to grant
+/// themselves anything. So, the file is refused and every [Request][rq] is
+/// denied.
+///
+/// [rq]: crate::request::Request
+pub const SUDOER_OWNER_UID: u32 = 0;
+/// The mode bits that must not be set on the sudoers file or any directory
+/// containing it
+///
+/// By default, the file may not be writable by its group or by others. As with
+/// [SUDOER_OWNER_UID], a file failing this check is refused.
+pub const SUDOER_FORBIDDEN_MODE: u32 = 0o022;
+/// How deeply includes in the sudoers file may be nested
+///
+/// This is mostly to stop a file that includes itself from looping forever.
+/// It's the same limit `sudo` uses.
+pub const SUDOER_MAX_INCLUDE_DEPTH: usize = 128;
+/// The UID the policy helper runs as
+///
+/// Used by [permission::verify::helper]. The helper parses the policy and
+/// checks requests against it, so it should run as a user that owns nothing.
+pub const POLICY_HELPER_UID: u32 = 65534;
+/// The GID the policy helper runs as
+///
+/// Used by [permission::verify::helper], along with [POLICY_HELPER_UID]. It has
+/// no other groups.
+pub const POLICY_HELPER_GID: u32 = 65534;
+/// The longest message the kernel and the policy helper will take from each
+/// other
+///
+/// Used by [permission::verify::helper]. The longest messages are directory
+/// listings from `Include_Dirs`, so this may need raising if those are huge.
+pub const POLICY_HELPER_MAX_MESSAGE: usize = 64 * 1024;
+/// The most requests the policy helper may make while loading the policy
+///
+/// Used by [permission::verify::helper]. Every file opened, directory listed,
+/// and user or group looked up counts, so this may need raising for policies
+/// that name a lot of users.
+pub const POLICY_HELPER_MAX_REQUESTS: usize = 4096;
+/// The largest policy file the kernel will pass to the policy helper, in bytes
+///
+/// Used by [permission::verify::helper]. The kernel reads each file to check
+/// what the helper says it includes, so this keeps that from taking too long.
+pub const POLICY_HELPER_MAX_FILE: u64 = 1024 * 1024;
+/// Whether to let `root` through if the sudoers file can't be loaded
+///
+/// If the file at [SUDOER_PATH] is missing or malformed, every [Request][rq] is
+/// denied and the failure is logged. Setting this lets `root` still run
+/// commands in that case, which can help with recovering a broken deployment.
+///
+/// [rq]: crate::request::Request
+pub const SUDOER_FAILURE_ALLOW_ROOT: bool = false;
 
 /// How to log incoming [Request][rq]s
 ///
@@ -64,6 +271,42 @@ pub const SUDOER_PATH: &str = "/etc/sudoers.json";
 /// [rq]: crate::request::Request
 #[cfg(feature = "log")]
 pub const LOGGER: Logger = log::to_file;
+/// How to log the way an [Executable][eb] ended
+///
+/// Only [Runner]s that stay around while the [Executable][eb] runs, like
+/// [executable::run::supervise], call this. They become `root` in every ID
+/// before the [Executable][eb] starts, so the user can't kill them to skip it.
+///
+/// [eb]: executable::Executable
+#[cfg(feature = "log")]
+pub const EXIT_LOGGER: ExitLogger = log::exit_to_file;
+/// How to log the ID of a recorded session
+///
+/// [executable::run::pty] calls this before the [Executable][eb] starts. If it
+/// fails, the [Executable][eb] isn't run.
+///
+/// [eb]: executable::Executable
+#[cfg(feature = "log")]
+pub const SESSION_LOGGER: SessionLogger = log::session_to_file;
+/// How to log the sandbox an [Executable][eb] runs in
+///
+/// [executable::run::exec] calls this before the [Executable][eb] starts, if
+/// the policy asks for it to be confined. If it fails, the [Executable][eb]
+/// isn't run.
+///
+/// [eb]: executable::Executable
+#[cfg(feature = "log")]
+pub const SANDBOX_LOGGER: SandboxLogger = log::sandbox_to_file;
+/// How to log what an [Executable][eb] runs
+///
+/// [executable::run::intercept] calls this for everything the
+/// [Executable][eb] tries to run, if the policy sets `log_subcmds`. Anything
+/// that isn't allowed is logged regardless. Failing to log doesn't stop
+/// anything from running, since the [Executable][eb] already is.
+///
+/// [eb]: executable::Executable
+#[cfg(feature = "log")]
+pub const SUBCOMMAND_LOGGER: SubcommandLogger = log::subcommand_to_file;
 
 /// The path to log to
 ///
@@ -147,17 +390,138 @@ macro_rules! LOG_WRITE_FAILURE_MSG {
 }
 #[cfg(feature = "log")]
 pub(crate) use LOG_WRITE_FAILURE_MSG;
+/// The format of the log message when the policy couldn't be checked
+///
+/// This is used instead of [LOG_WRITE_FAILURE_MSG] when the request was denied
+/// because the policy couldn't be loaded, say because it's missing or isn't
+/// valid. The administrator has to know why to fix it, so the error is always
+/// given, whether or not the `log_fail_msg` feature is enabled.
+///
+/// The code provides the same variables as [LOG_WRITE_FAILURE_MSG], and
+/// always provides `failure`.
+#[cfg(feature = "log")]
+macro_rules! LOG_WRITE_POLICY_FAILURE_MSG {
+    () => {
+        "{tstamp_secs}.{tstamp_nanos:0>9} FAILURE Executing {execable}; From {cur_perm}; To {req_perm}; Policy error {failure}\n"
+    };
+}
+#[cfg(feature = "log")]
+pub(crate) use LOG_WRITE_POLICY_FAILURE_MSG;
+/// The format of the log message when an [Executable][eb] ends
+///
+/// This is written by [log::exit_to_file] once a [Runner] like
+/// [executable::run::supervise] sees the [Executable][eb] end. Like the other
+/// messages, it's a macro so that formatting still works.
+///
+/// The code provides the following variables for use
+///   * `tstamp_secs`: The current unix timestamp's whole number part in seconds
+///   * `tstamp_nanos`: The fractional part of the current unix timestamp in
+///     nanoseconds
+///   * `execable`: The [Executable][eb] that ran
+///   * `req_perm`: The [Permissions][pm] it ran as
+///   * `pid`: The PID it ran as
+///   * `status`: How it ended, either its exit status or the signal that
+///     killed it
+///   * `duration_secs`: The whole number part of how long it ran in seconds
+///   * `duration_nanos`: The fractional part of how long it ran in nanoseconds
+///   * `usage`: What it used, if it ran in a cgroup, like
+///     `; Peak memory 1024 bytes; CPU time 0.500000s`, or nothing
+///
+/// [eb]: executable::Executable
+/// [pm]: permission::Permission
+#[cfg(feature = "log")]
+macro_rules! LOG_WRITE_EXIT_MSG {
+    () => {
+        "{tstamp_secs}.{tstamp_nanos:0>9} EXITED Executing {execable}; As {req_perm}; Pid {pid}; With {status}; After {duration_secs}.{duration_nanos:0>9}s{usage}\n"
+    };
+}
+#[cfg(feature = "log")]
+pub(crate) use LOG_WRITE_EXIT_MSG;
+/// The format of the log message when a session starts being recorded
+///
+/// This is written by [log::session_to_file] just before the
+/// [Executable][eb] starts. Like the other messages, it's a macro so that
+/// formatting still works.
+///
+/// The code provides the following variables for use
+///   * `tstamp_secs`: The current unix timestamp's whole number part in seconds
+///   * `tstamp_nanos`: The fractional part of the current unix timestamp in
+///     nanoseconds
+///   * `execable`: The [Executable][eb] being run
+///   * `req_perm`: The [Permissions][pm] it runs as
+///   * `session`: The ID of the session, which names its directory under
+///     [IOLOG_DIR]
+///
+/// [eb]: executable::Executable
+/// [pm]: permission::Permission
+#[cfg(feature = "log")]
+macro_rules! LOG_WRITE_SESSION_MSG {
+    () => {
+        "{tstamp_secs}.{tstamp_nanos:0>9} SESSION Executing {execable}; As {req_perm}; Id {session}\n"
+    };
+}
+#[cfg(feature = "log")]
+pub(crate) use LOG_WRITE_SESSION_MSG;
+/// The format of the log message for the sandbox an [Executable][eb] runs in
+///
+/// This is written by [log::sandbox_to_file] just before the [Executable][eb]
+/// starts. Like the other messages, it's a macro so that formatting still
+/// works.
+///
+/// The code provides the following variables for use
+///   * `tstamp_secs`: The current unix timestamp's whole number part in seconds
+///   * `tstamp_nanos`: The fractional part of the current unix timestamp in
+///     nanoseconds
+///   * `execable`: The [Executable][eb] being run
+///   * `req_perm`: The [Permissions][pm] it runs as
+///   * `sandbox`: What confines it, like
+///     `Landlock ABI 3 reading "/" writing "/var/backups"`
+///
+/// [eb]: executable::Executable
+/// [pm]: permission::Permission
+#[cfg(feature = "log")]
+macro_rules! LOG_WRITE_SANDBOX_MSG {
+    () => {
+        "{tstamp_secs}.{tstamp_nanos:0>9} SANDBOX Executing {execable}; As {req_perm}; With {sandbox}\n"
+    };
+}
+#[cfg(feature = "log")]
+pub(crate) use LOG_WRITE_SANDBOX_MSG;
+/// The format of the log message for something an [Executable][eb] runs
+///
+/// This is written by [log::subcommand_to_file]. Like the other messages, it's
+/// a macro so that formatting still works.
+///
+/// The code provides the following variables for use
+///   * `tstamp_secs`: The current unix timestamp's whole number part in seconds
+///   * `tstamp_nanos`: The fractional part of the current unix timestamp in
+///     nanoseconds
+///   * `status`: `SUBCMD` if it was allowed to run, or `SUBCMD-DENIED` if not
+///   * `execable`: The [Executable][eb] that ran it
+///   * `req_perm`: The [Permissions][pm] it runs as
+///   * `subcmd`: What it tried to run, as an [Executable][eb]
+///
+/// [eb]: executable::Executable
+/// [pm]: permission::Permission
+#[cfg(feature = "log")]
+macro_rules! LOG_WRITE_SUBCOMMAND_MSG {
+    () => {
+        "{tstamp_secs}.{tstamp_nanos:0>9} {status} Executing {subcmd}; Under {execable}; As {req_perm}\n"
+    };
+}
+#[cfg(feature = "log")]
+pub(crate) use LOG_WRITE_SUBCOMMAND_MSG;
 
 /// What command line argument number to look for for the path of the binary to
 /// execute
 ///
 /// Used by [executable::factory::from_commandline]
-pub const EXECUTABLE_COMMANDLINE_PATH_IDX: usize = 4;
+pub const EXECUTABLE_COMMANDLINE_PATH_IDX: usize = 5;
 /// What command line argument number to use as the first parameter to the
 /// program, with subsequent arguments being used in order
 ///
 /// Used by [executable::factory::from_commandline]
-pub const EXECUTABLE_COMMANDLINE_ARG_START_IDX: usize = 5;
+pub const EXECUTABLE_COMMANDLINE_ARG_START_IDX: usize = 6;
 
 /// What command line argument number to look at for the UID
 ///
@@ -172,3 +536,11 @@ pub const PERMISSION_COMMANDLINE_PRIMARY_GID_IDX: usize = 2;
 ///
 /// Used by [permission::factory::from_commandline]
 pub const PERMISSION_COMMANDLINE_SECONDARY_GID_IDX: usize = 3;
+
+/// What command line argument number to look at for a comma separated list of
+/// the [Settings][st] the user asked for
+///
+/// Used by [setting::factory::from_commandline]
+///
+/// [st]: setting::Settings
+pub const SETTINGS_COMMANDLINE_IDX: usize = 4;
@@ -0,0 +1,28 @@
+//! Configuration variables for `sus-replay`
+//!
+//! This file defines the configuration constants for the `sus-replay` program.
+//! These will be compiled into the final binary.
+//!
+//! The directory recordings are read from defaults to the one the kernel
+//! records to, so the kernel's configuration is pulled in.
+//!
+//! Make sure to edit this file in `config/`. This file is copied to the `src/`
+//! directory as part of the build process. Any changes made there will be
+//! ignored by `cargo build`.
+
+#![allow(dead_code)]
+
+// This file is copied next to `main.rs`, so the path is relative to there
+// Don't let `rustfmt` try to follow it from `config/`
+#[allow(clippy::deprecated_cfg_attr)]
+#[cfg_attr(rustfmt, rustfmt::skip)]
+#[path = "../sus-kernel/config.rs"]
+mod kernel;
+pub use kernel::*;
+
+/// The longest to wait between events when replaying, in seconds
+///
+/// Recordings often have long stretches where nothing happens, like while the
+/// user stepped away. Any pause longer than this is cut short. It can be
+/// changed with `-m`, and `0` means no limit.
+pub const DEFAULT_MAX_WAIT: f64 = 0.0;
@@ -0,0 +1,38 @@
+//! Configuration variables for `sus-visudo`
+//!
+//! This file defines the configuration constants for the `sus-visudo` program.
+//! These will be compiled into the final binary.
+//!
+//! The settings describing the policy itself, like where it lives and who must
+//! own it, are taken straight from the kernel's configuration. That way, this
+//! binary checks the policy exactly the way the kernel will read it.
+//!
+//! Make sure to edit this file in `config/`. This file is copied to the `src/`
+//! directory as part of the build process. Any changes made there will be
+//! ignored by `cargo build`.
+
+#![allow(dead_code)]
+
+// This file is copied next to `main.rs`, so the path is relative to there
+// Don't let `rustfmt` try to follow it from `config/`
+#[allow(clippy::deprecated_cfg_attr)]
+#[cfg_attr(rustfmt, rustfmt::skip)]
+#[path = "../sus-kernel/config.rs"]
+mod kernel;
+pub use kernel::*;
+
+/// What to append to the path of the policy to get the temporary file
+///
+/// The temporary file is created in the same directory as the policy. That way,
+/// relative includes resolve the same way, and the final `rename` is atomic.
+pub const TEMP_SUFFIX: &str = ".tmp";
+
+/// The group to give the policy file once it's been edited
+///
+/// The owner is given by [SUDOER_OWNER_UID].
+pub const SUDOER_INSTALL_GID: u32 = 0;
+/// The mode to give the policy file once it's been edited
+///
+/// This must not have any of the bits in [SUDOER_FORBIDDEN_MODE] set, otherwise
+/// the kernel will refuse the file.
+pub const SUDOER_INSTALL_MODE: u32 = 0o440;
@@ -16,10 +16,10 @@ pub const KERNEL_PATH: &str = "/usr/local/bin/sus-kernel";
 
 /// What command line argument number to put the path of the binary to execute
 /// at
-pub const KERNEL_COMMANDLINE_BINARY_IDX: usize = 4;
+pub const KERNEL_COMMANDLINE_BINARY_IDX: usize = 5;
 /// What command line argument number to use for the first parameter to the
 /// program, with subsequent arguments being used in order
-pub const KERNEL_COMMANDLINE_ARG_START_IDX: usize = 5;
+pub const KERNEL_COMMANDLINE_ARG_START_IDX: usize = 6;
 
 /// What command line argument number to put the UID at
 pub const KERNEL_COMMANDLINE_UID_IDX: usize = 1;
@@ -28,3 +28,13 @@ pub const KERNEL_COMMANDLINE_PRIMARY_GID_IDX: usize = 2;
 /// What command line argument number to put a comma separated list of the
 /// Secondary GIDs at
 pub const KERNEL_COMMANDLINE_SECONDARY_GID_IDX: usize = 3;
+/// What command line argument number to put a comma separated list of the
+/// settings the user asked for at, like `closefrom=5`
+pub const KERNEL_COMMANDLINE_SETTINGS_IDX: usize = 4;
+
+/// What to give the kernel as the binary to run for `-e`
+///
+/// This isn't a real binary. It tells the kernel to edit the files given as
+/// arguments instead. It must match `SUDOEDIT_PATH` in the kernel's
+/// configuration.
+pub const KERNEL_SUDOEDIT_PATH: &str = "sudoedit";
@@ -0,0 +1,55 @@
+//! Errors that `sus-cvtsudoers` can report
+//!
+//! Converting can fail when reading or writing files, or when the input isn't
+//! a valid policy. This module defines the [CvtError] type for that.
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// Type for reporting errors in `sus-cvtsudoers`
+#[derive(Debug)]
+pub enum CvtError {
+    /// An IO operation on `path` failed
+    Io { path: String, cause: std::io::Error },
+    /// The input or output JSON was bad
+    Json { cause: serde_json::Error },
+    /// The sudoers text couldn't be parsed
+    Syntax { line: usize, message: String },
+    /// The `-m` option couldn't be parsed
+    BadMatch { string: String },
+}
+
+impl Display for CvtError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CvtError::Io { path, cause } => {
+                write!(f, "Failed to access {} - {}", path, cause)?;
+            }
+            CvtError::Json { cause } => {
+                write!(f, "Bad JSON - {}", cause)?;
+            }
+            CvtError::Syntax { line, message } => {
+                write!(f, "Syntax error on line {} - {}", line, message)?;
+            }
+            CvtError::BadMatch { string } => {
+                write!(
+                    f,
+                    "Bad match `{}` - expected `user=name` or `host=name`",
+                    string
+                )?;
+            }
+        };
+        Ok(())
+    }
+}
+
+impl Error for CvtError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CvtError::Io { cause, .. } => Some(cause),
+            CvtError::Json { cause } => Some(cause),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Filter a [Sudoers] down to the rules that apply to a user or host
+//!
+//! When migrating a large policy, it helps to look at what a single user or a
+//! single machine gets. This module keeps only the rules that match a [Match],
+//! then drops any `User_Alias`es that are no longer used.
+
+use crate::error::CvtError;
+use crate::permission::verify::sudoers_type::{Host, Sudoers, User};
+use crate::permission::verify::ALL;
+
+use std::collections::{BTreeMap, HashSet};
+
+/// What rules to keep
+///
+/// A rule is kept only if it matches everything given. Users match by name,
+/// by being in a group on this machine, or by being in an alias. Hosts match
+/// by name, ignoring case.
+#[derive(Debug, Default)]
+pub struct Match {
+    pub user: Option<String>,
+    pub host: Option<String>,
+}
+
+impl Match {
+    /// Parse a [Match] from a string like `user=alice,host=web1`
+    ///
+    /// This is the same syntax as `cvtsudoers -m`.
+    pub fn parse(s: &str) -> Result<Match, CvtError> {
+        let mut ret = Match::default();
+        for part in s.split(',') {
+            let bad = || CvtError::BadMatch {
+                string: part.to_string(),
+            };
+            let (key, value) = part.split_once('=').ok_or_else(bad)?;
+            match key.trim() {
+                "user" => ret.user = Some(value.trim().to_string()),
+                "host" => ret.host = Some(value.trim().to_string()),
+                _ => return Err(bad()),
+            }
+        }
+        Ok(ret)
+    }
+}
+
+/// Function to remove the rules in `sudoers` that don't match `m`
+pub fn filter(sudoers: &mut Sudoers, m: &Match) {
+    // Find the groups the user is in
+    let groups: HashSet<String> = match &m.user {
+        None => HashSet::new(),
+        Some(u) => users::get_user_by_name(u)
+            .and_then(|user| users::get_user_groups(u, user.primary_group_id()))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|g| g.name().to_string_lossy().to_string())
+            .collect(),
+    };
+
+    // Keep matching rules
+    let aliases = &sudoers.user_aliases;
+    sudoers.user_specs.retain(|spec| {
+        let user_ok = match &m.user {
+            None => true,
+            Some(u) => spec
+                .user_list
+                .iter()
+                .any(|e| user_matches(e, u, &groups, aliases, 0)),
+        };
+        let host_ok = match &m.host {
+            None => true,
+            Some(h) => spec.host_list.iter().any(|e| match e {
+                Host::Hostname(n) => n == ALL || n.eq_ignore_ascii_case(h),
+            }),
+        };
+        user_ok && host_ok
+    });
+
+    // Remove unused aliases
+    let mut used = HashSet::new();
+    for spec in &sudoers.user_specs {
+        mark_used(&spec.user_list, aliases, &mut used, 0);
+    }
+    sudoers.user_aliases.retain(|name, _| used.contains(name));
+}
+
+/// Function to check whether a [User] entry matches the user `u`
+fn user_matches(
+    e: &User,
+    u: &str,
+    groups: &HashSet<String>,
+    aliases: &BTreeMap<String, Vec<User>>,
+    depth: usize,
+) -> bool {
+    match e {
+        User::Username(n) => n == ALL || n == u,
+        User::Usergroup(g) => g == ALL || groups.contains(g),
+        // Don't loop forever on an alias that contains itself
+        User::Useralias(a) => match aliases.get(a) {
+            Some(members) if depth < 64 => members
+                .iter()
+                .any(|m| user_matches(m, u, groups, aliases, depth + 1)),
+            _ => false,
+        },
+    }
+}
+
+/// Function to add every alias referenced by `list` to `used`
+fn mark_used(
+    list: &[User],
+    aliases: &BTreeMap<String, Vec<User>>,
+    used: &mut HashSet<String>,
+    depth: usize,
+) {
+    if depth >= 64 {
+        return;
+    }
+    for e in list {
+        if let User::Useralias(a) = e {
+            if used.insert(a.clone()) {
+                if let Some(members) = aliases.get(a) {
+                    mark_used(members, aliases, used, depth + 1);
+                }
+            }
+        }
+    }
+}
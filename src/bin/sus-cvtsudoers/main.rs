@@ -0,0 +1,130 @@
+//! Convert a policy between sudoers text and JSON
+//!
+//! SUS reads its policy as JSON, but most existing policies are written in the
+//! traditional sudoers text format. This binary is modeled after `cvtsudoers`.
+//! It reads a policy in either format and writes it in either format, so
+//! existing policies can be migrated, and SUS policies can be reviewed by
+//! people used to the text format.
+//!
+//! Not everything in one format can be said in the other. Anything that can't
+//! be converted is dropped, and a warning is printed for it. Nothing is ever
+//! silently changed to mean something else.
+
+mod config;
+mod error;
+mod filter;
+mod option;
+mod text;
+
+// The kernel's modules
+// Much of them goes unused here
+#[allow(dead_code, unused_imports)]
+#[path = "../sus-kernel/executable/mod.rs"]
+mod executable;
+#[allow(dead_code, unused_imports)]
+#[path = "../sus-kernel/log/mod.rs"]
+mod log;
+#[allow(dead_code, unused_imports)]
+#[path = "../sus-kernel/permission/mod.rs"]
+mod permission;
+#[allow(dead_code, unused_imports)]
+#[path = "../sus-kernel/setting/mod.rs"]
+mod setting;
+
+use serde::Serialize;
+use std::fs;
+use std::io::{Read, Write};
+use std::process::exit;
+use structopt::StructOpt;
+
+use error::CvtError;
+use option::{CommandLineOptions, Format};
+use permission::verify::sudoers_type::Sudoers;
+
+/// The entrypoint of the binary
+///
+/// As is standard practice in Rust, most of the work is done by internal
+/// libraries, and this function simply calls into those libraries. It converts
+/// the policy, printing out warnings and errors if there were any.
+fn main() {
+    let opts = CommandLineOptions::from_args();
+
+    match run(&opts) {
+        Ok(warnings) => {
+            for w in warnings {
+                eprintln!("Warning: {}", w);
+            }
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(1);
+        }
+    }
+}
+
+/// Function to do the conversion the user asked for
+///
+/// It returns all the warnings from reading and writing the policy.
+fn run(opts: &CommandLineOptions) -> Result<Vec<text::Warning>, CvtError> {
+    // Read the input
+    let mut input = String::new();
+    let res = if opts.input == "-" {
+        std::io::stdin().read_to_string(&mut input).map(|_| ())
+    } else {
+        fs::read_to_string(&opts.input).map(|s| input = s)
+    };
+    res.map_err(|cause| CvtError::Io {
+        path: opts.input.clone(),
+        cause,
+    })?;
+
+    // Parse it
+    let format = opts.input_format.unwrap_or_else(|| {
+        if input.trim_start().starts_with('{') {
+            Format::Json
+        } else {
+            Format::Sudoers
+        }
+    });
+    let (mut sudoers, mut warnings) = match format {
+        Format::Json => (
+            serde_json::from_str::<Sudoers>(&input).map_err(|cause| CvtError::Json { cause })?,
+            Vec::new(),
+        ),
+        Format::Sudoers => text::parse(&input)?,
+    };
+
+    // Filter it
+    if let Some(m) = &opts.matching {
+        filter::filter(&mut sudoers, m);
+    }
+
+    // Write it out
+    let output = match opts.output_format {
+        Format::Json => {
+            let mut buf = Vec::new();
+            let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+            let mut ser = serde_json::Serializer::with_formatter(&mut buf, formatter);
+            sudoers
+                .serialize(&mut ser)
+                .map_err(|cause| CvtError::Json { cause })?;
+            buf.push(b'\n');
+            buf
+        }
+        Format::Sudoers => {
+            let (s, w) = text::write(&sudoers);
+            warnings.extend(w);
+            s.into_bytes()
+        }
+    };
+    let (path, res) = match &opts.output {
+        None => ("-", std::io::stdout().write_all(&output)),
+        Some(p) => (p.as_str(), fs::write(p, &output)),
+    };
+    res.map_err(|cause| CvtError::Io {
+        path: path.to_string(),
+        cause,
+    })?;
+
+    Ok(warnings)
+}
@@ -0,0 +1,66 @@
+//! Parse command line options from the user
+//!
+//! This module defines a [CommandLineOptions] struct, which is populated from
+//! the command line using the `structopt` library. It's modeled after the
+//! options `cvtsudoers` takes.
+
+use structopt::clap::AppSettings;
+use structopt::StructOpt;
+
+use crate::filter::Match;
+
+/// The formats a policy can be in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// The JSON format SUS reads
+    Json,
+    /// The traditional sudoers text format
+    Sudoers,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "sudoers" => Ok(Format::Sudoers),
+            _ => Err(format!("Unknown format `{}` - use json or sudoers", s)),
+        }
+    }
+}
+
+/// The `sus-cvtsudoers` interface
+///
+/// Convert a policy between the sudoers text format and the JSON format SUS
+/// reads.
+// We have to convince `clap` to not put the version. See:
+// <https://github.com/TeXitoi/structopt/issues/81
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "sus-cvtsudoers",
+    global_settings = &[AppSettings::DisableVersion]
+)]
+pub struct CommandLineOptions {
+    /// The format of the input, either json or sudoers
+    ///
+    /// If not given, input starting with `{` is taken to be JSON, and anything
+    /// else is taken to be sudoers text.
+    #[structopt(short = "i")]
+    pub input_format: Option<Format>,
+
+    /// The format of the output, either json or sudoers
+    #[structopt(short = "f", default_value = "json")]
+    pub output_format: Format,
+
+    /// The file to write the output to, instead of standard output
+    #[structopt(short = "o")]
+    pub output: Option<String>,
+
+    /// Only keep rules matching this, like `user=alice,host=web1`
+    #[structopt(short = "m", parse(try_from_str = Match::parse))]
+    pub matching: Option<Match>,
+
+    /// The file to read the policy from, or `-` for standard input
+    #[structopt(default_value = "-")]
+    pub input: String,
+}
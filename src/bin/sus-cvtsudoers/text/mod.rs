@@ -0,0 +1,57 @@
+//! Module for the traditional sudoers text format
+//!
+//! SUS reads its policy as JSON, but most existing policies are written in the
+//! text format described in `sudoers(5)`. This module can [parse] that format
+//! into a [Sudoers][s], and [write] a [Sudoers][s] back out in it.
+//!
+//! The two formats don't line up exactly. Whenever something can't be
+//! converted, it's dropped and a [Warning] is given instead.
+//!
+//! They also disagree on whether a password is needed. `sudo` asks for one
+//! unless told not to, and SUS only asks if told to. So, a policy with rules
+//! but no `authenticate` in its `Defaults` gets one added, saying what the
+//! format it came from meant. See [sets_authenticate].
+//!
+//! [s]: crate::permission::verify::sudoers_type::Sudoers
+
+pub mod parse;
+pub mod token;
+pub mod write;
+pub use parse::parse;
+pub use write::write;
+
+use crate::permission::verify::sudoers_type::{Option, Sudoers};
+
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// A warning about something that couldn't be converted
+#[derive(Debug)]
+pub struct Warning {
+    /// The line of the input the warning is about, if there is one
+    pub line: std::option::Option<usize>,
+    /// What happened
+    pub message: String,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.line {
+            Some(l) => write!(f, "line {} - {}", l, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Function to check if a [Sudoers] says whether a password is needed
+///
+/// Only `Defaults` count. If there are no rules, there's nothing to ask for a
+/// password for, so it doesn't matter.
+pub fn sets_authenticate(sudoers: &Sudoers) -> bool {
+    sudoers.user_specs.is_empty()
+        || sudoers
+            .defaults
+            .iter()
+            .flat_map(|d| &d.options)
+            .any(|o| matches!(o, Option::Authenticate(_)))
+}
@@ -0,0 +1,1017 @@
+//! Parse the traditional sudoers text format into [Sudoers]
+//!
+//! This handles most of the grammar described in `sudoers(5)`: `Defaults`, the
+//! four kinds of aliases, user specifications with Runas lists, tags, and
+//! multiple host sections, and the include directives.
+//!
+//! Not everything in that grammar can be represented by SUS. Whenever
+//! something is dropped or changed, a [Warning] is recorded. The conversion
+//! always errs on the side of granting less. In particular, anything negated
+//! can't be represented, so the whole rule containing it is dropped rather
+//! than dropping just the negation.
+
+use super::token::{describe, logical_lines, tokenize, Token};
+use super::{sets_authenticate, Warning};
+use crate::error::CvtError;
+use crate::permission::verify::sudoers_type::{
+    CmdSpec, Command, Defaults, Host, Option, Rlimit, RlimitResource, Sudoers, User, UserSpec,
+};
+use crate::permission::verify::ALL;
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+
+/// Function to parse sudoers text
+///
+/// It returns the [Sudoers] that was parsed, along with any [Warning]s about
+/// things that couldn't be represented. It fails with a
+/// [Syntax][CvtError::Syntax] error if the text isn't valid sudoers.
+///
+/// Like `sudo`, rules need a password unless the text says otherwise. If it
+/// doesn't say either way, `authenticate` is added to the start of the
+/// `Defaults`.
+pub fn parse(input: &str) -> Result<(Sudoers, Vec<Warning>), CvtError> {
+    let mut p = Parser::default();
+    for ll in logical_lines(input) {
+        p.line = ll.number;
+        p.parse_line(&ll.text).map_err(|message| CvtError::Syntax {
+            line: ll.number,
+            message,
+        })?;
+    }
+    if !sets_authenticate(&p.sudoers) {
+        p.sudoers.defaults.insert(
+            0,
+            Defaults {
+                options: vec![Option::Authenticate(true)],
+            },
+        );
+    }
+    Ok((p.sudoers, p.warnings))
+}
+
+/// The tags that may precede a command, and the setting each one changes
+///
+/// Tags that change the same setting, like `PASSWD` and `NOPASSWD`, override
+/// each other. The settings are converted to [Option]s by [default_option]. Tags
+/// not in this list are recognized, but ignored with a warning. See
+/// [IGNORED_TAGS].
+const TAGS: &[(&str, &str, bool)] = &[
+    ("NOPASSWD", "authenticate", false),
+    ("PASSWD", "authenticate", true),
+    ("SETENV", "setenv", true),
+    ("NOSETENV", "setenv", false),
+    ("LOG_INPUT", "log_input", true),
+    ("NOLOG_INPUT", "log_input", false),
+    ("LOG_OUTPUT", "log_output", true),
+    ("NOLOG_OUTPUT", "log_output", false),
+    ("NOEXEC", "noexec", true),
+    ("EXEC", "noexec", false),
+    ("INTERCEPT", "intercept", true),
+    ("NOINTERCEPT", "intercept", false),
+    ("LOG_SUBCMDS", "log_subcmds", true),
+    ("NOLOG_SUBCMDS", "log_subcmds", false),
+];
+/// Tags that SUS doesn't support
+const IGNORED_TAGS: &[&str] = &["MAIL", "NOMAIL", "FOLLOW", "NOFOLLOW"];
+/// Options of the form `NAME=value` that may precede a command, and the setting
+/// each one changes
+///
+/// Like tags, these are converted by [default_option]. Options not in this
+/// list are ignored with a warning. See [IGNORED_CMND_OPTIONS].
+const CMND_OPTIONS: &[(&str, &str)] = &[
+    ("TIMEOUT", "command_timeout"),
+    ("CWD", "runcwd"),
+    ("CHROOT", "runchroot"),
+];
+/// Options of the form `NAME=value` that SUS doesn't support
+const IGNORED_CMND_OPTIONS: &[&str] = &[
+    "NOTBEFORE",
+    "NOTAFTER",
+    "ROLE",
+    "TYPE",
+    "APPARMOR_PROFILE",
+    "PRIVS",
+    "LIMITPRIVS",
+];
+/// Digest algorithms that may prefix a command
+const DIGESTS: &[&str] = &["sha224", "sha256", "sha384", "sha512"];
+
+/// Function to convert a `Defaults` entry to an [Option]
+///
+/// It's given the name of the entry and its value. Flags like `env_reset` get
+/// a [Bool][DefaultValue::Bool], and entries like `secure_path=...` get a
+/// [Str][DefaultValue::Str]. It returns [None] if the entry isn't supported.
+fn default_option(name: &str, value: &DefaultValue) -> std::option::Option<Option> {
+    match (name, value) {
+        ("setenv", DefaultValue::Bool(b)) => Some(Option::Setenv(*b)),
+        ("authenticate", DefaultValue::Bool(b)) => Some(Option::Authenticate(*b)),
+        ("env_reset", DefaultValue::Bool(b)) => Some(Option::EnvReset(*b)),
+        ("mail_badpass", DefaultValue::Bool(b)) => Some(Option::MailBadpass(*b)),
+        ("secure_path", DefaultValue::Str(s)) => Some(Option::SecurePath(s.clone())),
+        ("log_input", DefaultValue::Bool(b)) => Some(Option::LogInput(*b)),
+        ("log_output", DefaultValue::Bool(b)) => Some(Option::LogOutput(*b)),
+        ("use_pty", DefaultValue::Bool(b)) => Some(Option::UsePty(*b)),
+        ("noexec", DefaultValue::Bool(b)) => Some(Option::Noexec(*b)),
+        ("intercept", DefaultValue::Bool(b)) => Some(Option::Intercept(*b)),
+        ("log_subcmds", DefaultValue::Bool(b)) => Some(Option::LogSubcmds(*b)),
+        ("closefrom", DefaultValue::Str(s)) => s.parse().ok().map(Option::Closefrom),
+        ("closefrom_override", DefaultValue::Bool(b)) => Some(Option::ClosefromOverride(*b)),
+        ("umask", DefaultValue::Str(s)) => u32::from_str_radix(s, 8)
+            .ok()
+            .filter(|m| *m <= 0o777)
+            .map(Option::Umask),
+        ("command_timeout", DefaultValue::Str(s)) => timeout(s).map(Option::CommandTimeout),
+        ("user_command_timeouts", DefaultValue::Bool(b)) => Some(Option::UserCommandTimeouts(*b)),
+        // sudo counts this in minutes, which can have a fraction
+        ("passwd_timeout", DefaultValue::Str(s)) => s
+            .parse::<f64>()
+            .ok()
+            .filter(|m| m.is_finite() && *m >= 0.0)
+            .map(|m| Option::PasswdTimeout((m * 60.0).round() as u64)),
+        ("pwfeedback", DefaultValue::Bool(b)) => Some(Option::Pwfeedback(*b)),
+        // Paths relative to the user's home aren't supported, and neither is
+        //  letting the user pick the root
+        ("runcwd", DefaultValue::Str(s)) if s == "*" || s.starts_with('/') => {
+            Some(Option::Runcwd(s.clone()))
+        }
+        ("runchroot", DefaultValue::Str(s)) if s.starts_with('/') => {
+            Some(Option::Runchroot(s.clone()))
+        }
+        (n, DefaultValue::Str(s)) if n.starts_with("rlimit_") => {
+            rlimit(&n["rlimit_".len()..], s).map(Option::Rlimit)
+        }
+        _ => None,
+    }
+}
+
+/// Function to parse an `rlimit_` entry
+///
+/// Like sudo, the value is a soft and hard limit separated by a comma, or a
+/// single limit for both. Either can be `infinity`. Keeping the caller's limit
+/// with `user`, or sudo's with `default`, isn't supported.
+fn rlimit(name: &str, value: &str) -> std::option::Option<Rlimit> {
+    let limit = |v: &str| match v {
+        "infinity" => Some(None),
+        v => v.parse().ok().map(Some),
+    };
+    let (soft, hard) = value.split_once(',').unwrap_or((value, value));
+    Some(Rlimit {
+        resource: RlimitResource::from_name(name)?,
+        soft: limit(soft)?,
+        hard: limit(hard)?,
+    })
+}
+
+/// Function to parse a timeout into seconds
+///
+/// Like sudo, it can be a plain number of seconds, or a number of days, hours,
+/// minutes, and seconds, like `1h30m`. Each unit can be given at most once, in
+/// that order.
+fn timeout(s: &str) -> std::option::Option<u64> {
+    if let Ok(n) = s.parse() {
+        return Some(n);
+    }
+    if s.is_empty() {
+        return None;
+    }
+    let mut total: u64 = 0;
+    let mut units = "dhms";
+    let mut rest = s;
+    while !rest.is_empty() {
+        let end = rest.find(|c: char| !c.is_ascii_digit())?;
+        let n: u64 = rest[..end].parse().ok()?;
+        let unit = rest[end..].chars().next()?.to_ascii_lowercase();
+        let pos = units.find(unit)?;
+        let secs = [86400, 3600, 60, 1][4 - units.len() + pos];
+        total = total.checked_add(n.checked_mul(secs)?)?;
+        units = &units[pos + 1..];
+        rest = &rest[end + 1..];
+    }
+    Some(total)
+}
+
+/// The value of an entry in a `Defaults` line, or a setting changed by a tag
+#[derive(Debug, Clone, PartialEq)]
+enum DefaultValue {
+    Bool(bool),
+    Str(String),
+}
+
+/// A member of a list, like a user or a host, that might be negated
+#[derive(Debug, Clone, PartialEq)]
+struct Item {
+    negated: bool,
+    name: String,
+}
+
+/// A command, as written in a user specification or a `Cmnd_Alias`
+#[derive(Debug, Clone, PartialEq)]
+struct Cmnd {
+    negated: bool,
+    /// Whether a digest was given, which SUS can't check
+    digest: bool,
+    /// The words of the command, joined with spaces
+    text: String,
+    /// The words of the command, kept apart in case any have spaces
+    words: Vec<String>,
+}
+
+/// The Runas list of a command
+///
+/// [None] for the users means no Runas list was given, so the default of
+/// `root` is used.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Runas {
+    users: std::option::Option<Vec<Item>>,
+    groups: Vec<Item>,
+}
+
+/// The state of the parser
+///
+/// The aliases SUS can't represent are kept here, so they can be expanded
+/// wherever they're used.
+#[derive(Default)]
+struct Parser {
+    sudoers: Sudoers,
+    warnings: Vec<Warning>,
+    line: usize,
+    host_aliases: HashMap<String, Vec<Item>>,
+    runas_aliases: HashMap<String, Vec<Item>>,
+    cmnd_aliases: HashMap<String, Vec<Cmnd>>,
+}
+
+/// A cursor into a list of [Token]s
+struct Cursor<'a> {
+    toks: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> std::option::Option<&'a Token> {
+        self.toks.get(self.pos)
+    }
+    fn peek_at(&self, off: usize) -> std::option::Option<&'a Token> {
+        self.toks.get(self.pos + off)
+    }
+    fn next(&mut self) -> std::option::Option<&'a Token> {
+        let ret = self.toks.get(self.pos);
+        self.pos += 1;
+        ret
+    }
+    fn at_end(&self) -> bool {
+        self.pos >= self.toks.len()
+    }
+    /// Consume the next token if it's `t`
+    fn eat(&mut self, t: &Token) -> bool {
+        if self.peek() == Some(t) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+    fn expect(&mut self, t: &Token) -> Result<(), String> {
+        if self.eat(t) {
+            Ok(())
+        } else {
+            Err(format!("Expected {}, found {}", t, describe(self.peek())))
+        }
+    }
+    fn word(&mut self) -> Result<&'a str, String> {
+        match self.next() {
+            Some(Token::Word(w)) => Ok(w),
+            t => Err(format!("Expected a word, found {}", describe(t))),
+        }
+    }
+    /// Parse a possibly negated [Item]
+    fn item(&mut self) -> Result<Item, String> {
+        let mut negated = false;
+        while self.eat(&Token::Bang) {
+            negated = !negated;
+        }
+        Ok(Item {
+            negated,
+            name: self.word()?.to_string(),
+        })
+    }
+    /// Parse a comma separated list of [Item]s
+    fn items(&mut self) -> Result<Vec<Item>, String> {
+        let mut ret = vec![self.item()?];
+        while self.eat(&Token::Comma) {
+            ret.push(self.item()?);
+        }
+        Ok(ret)
+    }
+    /// Parse a possibly negated [Cmnd], which runs until the next special
+    /// token
+    fn cmnd(&mut self) -> Result<Cmnd, String> {
+        let mut negated = false;
+        while self.eat(&Token::Bang) {
+            negated = !negated;
+        }
+        // Skip over the digest
+        let mut digest = false;
+        if let (Some(Token::Word(w)), Some(Token::Colon)) = (self.peek(), self.peek_at(1)) {
+            if DIGESTS.contains(&w.as_str()) {
+                self.pos += 2;
+                self.word()?;
+                digest = true;
+            }
+        }
+        // Collect the words
+        let mut words = vec![self.word()?];
+        while let Some(Token::Word(w)) = self.peek() {
+            words.push(w);
+            self.pos += 1;
+        }
+        Ok(Cmnd {
+            negated,
+            digest,
+            text: words.join(" "),
+            words: words.iter().map(|w| w.to_string()).collect(),
+        })
+    }
+    /// Parse a comma separated list of [Cmnd]s
+    fn cmnds(&mut self) -> Result<Vec<Cmnd>, String> {
+        let mut ret = vec![self.cmnd()?];
+        while self.eat(&Token::Comma) {
+            ret.push(self.cmnd()?);
+        }
+        Ok(ret)
+    }
+}
+
+impl Parser {
+    /// Record a [Warning] for the current line
+    fn warn(&mut self, message: String) {
+        self.warnings.push(Warning {
+            line: Some(self.line),
+            message,
+        });
+    }
+
+    /// Parse a single logical line
+    fn parse_line(&mut self, text: &str) -> Result<(), String> {
+        let trimmed = text.trim_start();
+
+        // Handle include directives before comments are stripped
+        for (directive, is_dir) in [
+            ("#includedir", true),
+            ("@includedir", true),
+            ("#include", false),
+            ("@include", false),
+        ] {
+            if let Some(rest) = trimmed.strip_prefix(directive) {
+                if !rest.starts_with(char::is_whitespace) {
+                    continue;
+                }
+                let toks = tokenize(rest)?;
+                let path = match toks.as_slice() {
+                    [Token::Word(p)] => p.clone(),
+                    _ => return Err(format!("Expected a single path after {}", directive)),
+                };
+                if is_dir {
+                    self.sudoers.include_dirs.push(path);
+                } else {
+                    self.sudoers.includes.push(path);
+                }
+                return Ok(());
+            }
+        }
+
+        // Scoped defaults have their scope right after the keyword
+        if let Some(rest) = trimmed.strip_prefix("Defaults") {
+            if rest.starts_with(&[':', '@', '>', '!'][..]) {
+                self.warn("Scoped Defaults are not supported and were dropped".to_string());
+                return Ok(());
+            }
+        }
+
+        let toks = tokenize(text)?;
+        let mut cur = Cursor {
+            toks: &toks,
+            pos: 0,
+        };
+        match cur.peek() {
+            None => Ok(()),
+            Some(Token::Word(w)) if w == "Defaults" => {
+                cur.pos += 1;
+                self.parse_defaults(&mut cur)
+            }
+            Some(Token::Word(w))
+                if matches!(
+                    w.as_str(),
+                    "User_Alias" | "Runas_Alias" | "Host_Alias" | "Cmnd_Alias" | "Cmd_Alias"
+                ) =>
+            {
+                cur.pos += 1;
+                self.parse_alias(w, &mut cur)
+            }
+            Some(_) => self.parse_user_spec(&mut cur),
+        }?;
+
+        if !cur.at_end() {
+            return Err(format!("Unexpected {:?}", cur.peek()));
+        }
+        Ok(())
+    }
+
+    /// Parse the entries of a `Defaults` line
+    fn parse_defaults(&mut self, cur: &mut Cursor) -> Result<(), String> {
+        let mut options = Vec::new();
+        loop {
+            // Get the name, noting whether it's negated
+            let mut negated = false;
+            while cur.eat(&Token::Bang) {
+                negated = !negated;
+            }
+            let mut name = cur.word()?.to_string();
+            // Lists are changed with `+=` and `-=`
+            if let Some(Token::Word(op)) = cur.peek() {
+                if op == "+" || op == "-" {
+                    name.push_str(op);
+                    cur.pos += 1;
+                }
+            }
+
+            // Get the value
+            let value = if cur.eat(&Token::Equals) {
+                if negated {
+                    return Err(format!("Negated Defaults entry {} has a value", name));
+                }
+                DefaultValue::Str(cur.word()?.to_string())
+            } else {
+                DefaultValue::Bool(!negated)
+            };
+
+            // Convert it
+            if name.ends_with('+') || name.ends_with('-') {
+                self.warn(format!(
+                    "Defaults list operation {}= is not supported and was dropped",
+                    name
+                ));
+            } else {
+                match default_option(&name, &value) {
+                    Some(o) => options.push(o),
+                    None => self.warn(format!(
+                        "Defaults entry {} is not supported and was dropped",
+                        name
+                    )),
+                }
+            }
+
+            if !cur.eat(&Token::Comma) {
+                break;
+            }
+        }
+
+        if !options.is_empty() {
+            self.sudoers.defaults.push(Defaults { options });
+        }
+        Ok(())
+    }
+
+    /// Parse the definitions on an alias line
+    ///
+    /// `User_Alias`es are kept as they are. The others can't be represented
+    /// by SUS, so they're remembered and expanded wherever they're used.
+    fn parse_alias(&mut self, kind: &str, cur: &mut Cursor) -> Result<(), String> {
+        loop {
+            let name = cur.word()?.to_string();
+            cur.expect(&Token::Equals)?;
+            match kind {
+                "User_Alias" => {
+                    let mut users = Vec::new();
+                    for i in cur.items()? {
+                        if i.negated {
+                            return Err(format!(
+                                "Negated members of User_Alias {} are not supported",
+                                name
+                            ));
+                        }
+                        users.extend(self.user(&i.name));
+                    }
+                    self.sudoers.user_aliases.insert(name, users);
+                }
+                "Runas_Alias" => {
+                    let members = cur.items()?;
+                    self.runas_aliases.insert(name, members);
+                }
+                "Host_Alias" => {
+                    let members = cur.items()?;
+                    self.host_aliases.insert(name, members);
+                }
+                _ => {
+                    let members = cur.cmnds()?;
+                    self.cmnd_aliases.insert(name, members);
+                }
+            }
+            if !cur.eat(&Token::Colon) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Parse a user specification
+    ///
+    /// Each host section becomes its own [UserSpec], since SUS only allows one
+    /// list of hosts for each.
+    fn parse_user_spec(&mut self, cur: &mut Cursor) -> Result<(), String> {
+        let users = cur.items()?;
+        loop {
+            let hosts = cur.items()?;
+            cur.expect(&Token::Equals)?;
+            let cmnds = self.parse_cmnd_specs(cur)?;
+            self.add_user_spec(&users, &hosts, cmnds);
+            if !cur.eat(&Token::Colon) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Parse the commands for one host section
+    ///
+    /// The Runas list and tags carry over from one command to the next, so
+    /// each command is returned along with the ones that apply to it.
+    #[allow(clippy::type_complexity)]
+    fn parse_cmnd_specs(
+        &mut self,
+        cur: &mut Cursor,
+    ) -> Result<Vec<(Runas, BTreeMap<&'static str, DefaultValue>, Cmnd)>, String> {
+        let mut ret = Vec::new();
+        let mut runas = Runas::default();
+        let mut tags: BTreeMap<&'static str, DefaultValue> = BTreeMap::new();
+        loop {
+            // Runas list
+            // Giving only groups means the user stays the same, so there are
+            //  no users to change to
+            if cur.eat(&Token::LParen) {
+                runas = Runas::default();
+                match cur.peek() {
+                    Some(Token::RParen) => (),
+                    Some(Token::Colon) => runas.users = Some(Vec::new()),
+                    _ => runas.users = Some(cur.items()?),
+                }
+                if cur.eat(&Token::Colon) && cur.peek() != Some(&Token::RParen) {
+                    runas.groups = cur.items()?;
+                }
+                cur.expect(&Token::RParen)?;
+            }
+
+            // Options and tags
+            loop {
+                match (cur.peek(), cur.peek_at(1)) {
+                    (Some(Token::Word(w)), Some(Token::Equals))
+                        if CMND_OPTIONS.iter().any(|(o, _)| o == w) =>
+                    {
+                        let (name, key) = *CMND_OPTIONS.iter().find(|(o, _)| o == w).unwrap();
+                        cur.pos += 2;
+                        let value = DefaultValue::Str(cur.word()?.to_string());
+                        if default_option(key, &value).is_some() {
+                            tags.insert(key, value);
+                        } else {
+                            self.warn(format!("Option {} has a bad value and was dropped", name));
+                        }
+                    }
+                    (Some(Token::Word(w)), Some(Token::Equals))
+                        if IGNORED_CMND_OPTIONS.contains(&w.as_str()) =>
+                    {
+                        cur.pos += 2;
+                        cur.word()?;
+                        self.warn(format!("Option {} is not supported and was dropped", w));
+                    }
+                    (Some(Token::Word(w)), Some(Token::Colon)) => {
+                        if let Some((_, key, value)) = TAGS.iter().find(|(t, _, _)| t == w) {
+                            tags.insert(key, DefaultValue::Bool(*value));
+                        } else if IGNORED_TAGS.contains(&w.as_str()) {
+                            self.warn(format!("Tag {} is not supported and was dropped", w));
+                        } else {
+                            break;
+                        }
+                        cur.pos += 2;
+                    }
+                    _ => break,
+                }
+            }
+
+            ret.push((runas.clone(), tags.clone(), cur.cmnd()?));
+            if !cur.eat(&Token::Comma) {
+                return Ok(ret);
+            }
+        }
+    }
+
+    /// Convert a user list entry to a [User]
+    ///
+    /// Names starting with `%` are groups, and names of `User_Alias`es are
+    /// aliases. Non-Unix groups, netgroups, and numeric IDs can't be looked up
+    /// by the kernel, so they're dropped.
+    fn user(&mut self, name: &str) -> std::option::Option<User> {
+        if name.starts_with("%:") || name.starts_with("%#") || name.starts_with('+') {
+            self.warn(format!("{} is not supported and was dropped", name));
+            None
+        } else if name.starts_with('#') {
+            self.warn(format!(
+                "Numeric ID {} is not supported and was dropped",
+                name
+            ));
+            None
+        } else if let Some(g) = name.strip_prefix('%') {
+            Some(User::Usergroup(g.to_string()))
+        } else if self.sudoers.user_aliases.contains_key(name) {
+            Some(User::Useralias(name.to_string()))
+        } else {
+            Some(User::Username(name.to_string()))
+        }
+    }
+
+    /// Convert a Runas list entry to a [User]
+    ///
+    /// The kernel only understands user names here, so anything else is
+    /// dropped.
+    fn runas_user(&mut self, name: &str) -> std::option::Option<User> {
+        if name.starts_with('%') || name.starts_with('+') || name.starts_with('#') {
+            self.warn(format!(
+                "Runas user {} is not supported and was dropped",
+                name
+            ));
+            None
+        } else {
+            Some(User::Username(name.to_string()))
+        }
+    }
+
+    /// Expand the aliases in a list of [Item]s
+    ///
+    /// A negated alias negates all of its members. It returns [None] if the
+    /// aliases nest too deeply, which can only happen if one contains itself.
+    fn expand(
+        aliases: &HashMap<String, Vec<Item>>,
+        items: &[Item],
+        depth: usize,
+    ) -> std::option::Option<Vec<Item>> {
+        if depth > 64 {
+            return None;
+        }
+        let mut ret = Vec::new();
+        for i in items {
+            match aliases.get(&i.name) {
+                None => ret.push(i.clone()),
+                Some(members) => {
+                    for m in Self::expand(aliases, members, depth + 1)? {
+                        ret.push(Item {
+                            negated: m.negated != i.negated,
+                            name: m.name,
+                        });
+                    }
+                }
+            }
+        }
+        Some(ret)
+    }
+
+    /// Expand the `Cmnd_Alias`es in a list of [Cmnd]s, like [Parser::expand]
+    fn expand_cmnds(&self, cmnds: &[Cmnd], depth: usize) -> std::option::Option<Vec<Cmnd>> {
+        if depth > 64 {
+            return None;
+        }
+        let mut ret = Vec::new();
+        for c in cmnds {
+            match self.cmnd_aliases.get(&c.text) {
+                None => ret.push(c.clone()),
+                Some(members) => {
+                    for m in self.expand_cmnds(members, depth + 1)? {
+                        ret.push(Cmnd {
+                            negated: m.negated != c.negated,
+                            ..m
+                        });
+                    }
+                }
+            }
+        }
+        Some(ret)
+    }
+
+    /// Build a [UserSpec] from the parsed pieces and add it
+    ///
+    /// This is where everything SUS can't represent is dealt with. If there's
+    /// any negation, the entire rule is dropped. Commands with arguments or
+    /// digests are dropped on their own, since that only grants less.
+    #[allow(clippy::type_complexity)]
+    fn add_user_spec(
+        &mut self,
+        users: &[Item],
+        hosts: &[Item],
+        cmnds: Vec<(Runas, BTreeMap<&'static str, DefaultValue>, Cmnd)>,
+    ) {
+        let drop_rule = |p: &mut Parser, what: &str| {
+            p.warn(format!(
+                "Negated {} can't be represented, so the rule was dropped",
+                what
+            ));
+        };
+
+        // Users
+        if users.iter().any(|i| i.negated) {
+            return drop_rule(self, "users");
+        }
+        let user_list: Vec<User> = users.iter().filter_map(|i| self.user(&i.name)).collect();
+        if user_list.is_empty() {
+            return;
+        }
+
+        // Hosts
+        let hosts = match Self::expand(&self.host_aliases, hosts, 0) {
+            None => return self.warn("Host_Alias contains itself".to_string()),
+            Some(h) => h,
+        };
+        if hosts.iter().any(|i| i.negated) {
+            return drop_rule(self, "hosts");
+        }
+        let host_list = hosts.into_iter().map(|i| Host::Hostname(i.name)).collect();
+
+        // Commands
+        // Group consecutive commands with the same Runas and tags
+        let mut cmd_specs: Vec<CmdSpec> = Vec::new();
+        let mut last: std::option::Option<(Runas, BTreeMap<&'static str, DefaultValue>)> = None;
+        for (runas, tags, cmnd) in cmnds {
+            // Expand everything
+            let runas_users = match &runas.users {
+                None => vec![Item {
+                    negated: false,
+                    name: "root".to_string(),
+                }],
+                Some(us) => match Self::expand(&self.runas_aliases, us, 0) {
+                    None => return self.warn("Runas_Alias contains itself".to_string()),
+                    Some(us) => us,
+                },
+            };
+            let runas_groups = match Self::expand(&self.runas_aliases, &runas.groups, 0) {
+                None => return self.warn("Runas_Alias contains itself".to_string()),
+                Some(gs) => gs,
+            };
+            if runas_users.iter().chain(&runas_groups).any(|i| i.negated) {
+                return drop_rule(self, "Runas users or groups");
+            }
+            let expanded = match self.expand_cmnds(&[cmnd], 0) {
+                None => return self.warn("Cmnd_Alias contains itself".to_string()),
+                Some(cs) => cs,
+            };
+            if expanded.iter().any(|c| c.negated) {
+                return drop_rule(self, "commands");
+            }
+
+            // Start a new spec if needed
+            if last.as_ref() != Some(&(runas.clone(), tags.clone())) {
+                cmd_specs.push(CmdSpec {
+                    run_as_users: runas_users
+                        .into_iter()
+                        .filter_map(|i| self.runas_user(&i.name))
+                        .collect(),
+                    run_as_groups: runas_groups
+                        .into_iter()
+                        .map(|i| User::Usergroup(i.name))
+                        .collect(),
+                    options: tags
+                        .iter()
+                        .filter_map(|(k, v)| default_option(k, v))
+                        .collect(),
+                    commands: Vec::new(),
+                });
+                last = Some((runas, tags));
+            }
+
+            // Add the commands
+            for c in expanded {
+                if let Some(cmd) = self.command(&c) {
+                    cmd_specs.last_mut().unwrap().commands.push(cmd);
+                }
+            }
+        }
+        cmd_specs.retain(|c| !c.commands.is_empty());
+        if cmd_specs.is_empty() {
+            return;
+        }
+
+        self.sudoers.user_specs.push(UserSpec {
+            user_list,
+            host_list,
+            cmd_specs,
+            source: String::new(),
+        });
+    }
+
+    /// Convert a single [Cmnd] to a [Command]
+    ///
+    /// SUS only matches on the path of the command. So, if the command
+    /// restricts the arguments in any way, it's dropped.
+    fn command(&mut self, c: &Cmnd) -> std::option::Option<Command> {
+        if c.digest {
+            self.warn(format!(
+                "Command digests are not supported, so {} was dropped",
+                c.text
+            ));
+            return None;
+        }
+        if c.text == ALL {
+            return Some(Command::CmdPath(CString::new(ALL).unwrap()));
+        }
+        // sudoedit takes the files it may edit as its arguments
+        if c.words[0] == "sudoedit" {
+            let files = &c.words[1..];
+            if files.is_empty() || files.iter().any(|f| !f.starts_with('/')) {
+                self.warn(format!(
+                    "sudoedit needs fully qualified files, so {} was dropped",
+                    c.text
+                ));
+                return None;
+            }
+            if files.iter().any(|f| f.contains(&['*', '?', '['][..])) {
+                self.warn(format!(
+                    "Wildcards are not supported, so {} was dropped",
+                    c.text
+                ));
+                return None;
+            }
+            return Some(Command::Sudoedit(files.to_vec()));
+        }
+        if c.text.contains(' ') {
+            self.warn(format!(
+                "Command arguments are not supported, so {} was dropped",
+                c.text
+            ));
+            return None;
+        }
+        if !c.text.starts_with('/') {
+            self.warn(format!(
+                "Commands must be fully qualified, so {} was dropped",
+                c.text
+            ));
+            return None;
+        }
+        // Can't fail since the tokenizer never gives null bytes from a &str
+        //  unless they were in the input
+        match CString::new(c.text.as_str()) {
+            Ok(p) => Some(Command::CmdPath(p)),
+            Err(_) => {
+                self.warn(format!(
+                    "Command {} has a null byte and was dropped",
+                    c.text
+                ));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// Function to parse `input`, returning the JSON and how many warnings
+    /// there were
+    fn convert(input: &str) -> (Value, usize) {
+        let (sudoers, warnings) = parse(input).unwrap();
+        (serde_json::to_value(&sudoers).unwrap(), warnings.len())
+    }
+
+    #[test]
+    fn rules_need_a_password_unless_told_otherwise() {
+        let (v, _) = convert("alice ALL=(root) /usr/bin/id\n");
+        assert_eq!(
+            v["Defaults"],
+            json!([{"Options": [{"authenticate": true}]}])
+        );
+        assert_eq!(v["User_Specs"][0]["Cmnd_Specs"][0]["Options"], json!([]));
+
+        let (v, _) = convert("Defaults !authenticate\nalice ALL=(root) /usr/bin/id\n");
+        assert_eq!(
+            v["Defaults"],
+            json!([{"Options": [{"authenticate": false}]}])
+        );
+
+        // Tags only change the commands they're on
+        let (v, _) = convert("alice ALL=(root) NOPASSWD: /usr/bin/id\n");
+        assert_eq!(
+            v["Defaults"],
+            json!([{"Options": [{"authenticate": true}]}])
+        );
+        assert_eq!(
+            v["User_Specs"][0]["Cmnd_Specs"][0]["Options"],
+            json!([{"authenticate": false}])
+        );
+
+        // Without rules, there's nothing to ask for a password for
+        let (v, _) = convert("Defaults env_reset\n");
+        assert_eq!(v["Defaults"], json!([{"Options": [{"env_reset": true}]}]));
+    }
+
+    #[test]
+    fn tags_carry_over_and_runas_lists_reset() {
+        let (v, warnings) =
+            convert("alice ALL = (bob) NOPASSWD: /bin/ls, /bin/cat, (root) TIMEOUT=1m /bin/id\n");
+        assert_eq!(warnings, 0);
+        assert_eq!(
+            v["User_Specs"][0]["Cmnd_Specs"],
+            json!([
+                {
+                    "runasusers": [{"username": "bob"}],
+                    "Options": [{"authenticate": false}],
+                    "Commands": [{"command": "/bin/ls"}, {"command": "/bin/cat"}]
+                },
+                {
+                    "runasusers": [{"username": "root"}],
+                    "Options": [{"authenticate": false}, {"command_timeout": 60}],
+                    "Commands": [{"command": "/bin/id"}]
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn aliases_are_kept_or_expanded() {
+        let (v, warnings) = convert(
+            "User_Alias ADMINS = alice, %wheel\n\
+             Host_Alias SERVERS = web, db\n\
+             Runas_Alias OPS = root, operator\n\
+             Cmnd_Alias SHELLS = /bin/sh, /bin/bash\n\
+             ADMINS SERVERS = (OPS) SHELLS\n",
+        );
+        assert_eq!(warnings, 0);
+        assert_eq!(
+            v["User_Aliases"],
+            json!({"ADMINS": [{"username": "alice"}, {"usergroup": "wheel"}]})
+        );
+        assert_eq!(
+            v["User_Specs"],
+            json!([{
+                "User_List": [{"useralias": "ADMINS"}],
+                "Host_List": [{"hostname": "web"}, {"hostname": "db"}],
+                "Cmnd_Specs": [{
+                    "runasusers": [{"username": "root"}, {"username": "operator"}],
+                    "Options": [],
+                    "Commands": [{"command": "/bin/sh"}, {"command": "/bin/bash"}]
+                }]
+            }])
+        );
+    }
+
+    #[test]
+    fn negation_drops_the_whole_rule() {
+        for input in [
+            "alice ALL = ALL, !/bin/sh\n",
+            "alice !web = /bin/ls\n",
+            "alice ALL = (ALL, !root) /bin/ls\n",
+            "Cmnd_Alias SHELLS = /bin/sh\nalice ALL = ALL, !SHELLS\n",
+        ] {
+            let (v, warnings) = convert(input);
+            assert_eq!(v["User_Specs"], json!([]), "{}", input);
+            assert_eq!(warnings, 1, "{}", input);
+        }
+    }
+
+    #[test]
+    fn commands_that_cant_be_matched_are_dropped() {
+        let (v, warnings) =
+            convert("alice ALL = /bin/ls -l, sha256:abcd /bin/cat, ls, sudoedit /etc/*, /bin/id\n");
+        assert_eq!(warnings, 4);
+        assert_eq!(
+            v["User_Specs"][0]["Cmnd_Specs"][0]["Commands"],
+            json!([{"command": "/bin/id"}])
+        );
+    }
+
+    #[test]
+    fn includes_and_comments() {
+        let (v, _) = convert(
+            "# A comment\n#include /etc/sus/extra\n@includedir /etc/sus/d\n\
+             alice ALL = /bin/ls # Another\n",
+        );
+        assert_eq!(v["Includes"], json!(["/etc/sus/extra"]));
+        assert_eq!(v["Include_Dirs"], json!(["/etc/sus/d"]));
+        assert_eq!(v["User_Specs"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn bad_syntax_is_an_error() {
+        for input in ["alice ALL /bin/ls\n", "alice ALL = (root /bin/ls\n"] {
+            assert!(matches!(
+                parse(input),
+                Err(CvtError::Syntax { line: 1, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn timeouts_are_parsed() {
+        assert_eq!(timeout("90"), Some(90));
+        assert_eq!(timeout("1h30m"), Some(5400));
+        assert_eq!(timeout("1d2h3m4s"), Some(93784));
+        assert_eq!(timeout("30M"), Some(1800));
+        assert_eq!(timeout("30m1h"), None);
+        assert_eq!(timeout("5x"), None);
+        assert_eq!(timeout(""), None);
+    }
+}
@@ -0,0 +1,192 @@
+//! Split lines of a sudoers file into [Token]s
+//!
+//! The sudoers grammar is mostly made of words separated by a few special
+//! characters. This module handles the low-level details of that, like
+//! backslash escapes, quoted strings, comments, and line continuations. The
+//! parser can then work with a flat list of [Token]s.
+
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// A single token in a sudoers line
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// A run of ordinary characters, with escapes and quotes removed
+    Word(String),
+    /// `,`
+    Comma,
+    /// `:`
+    Colon,
+    /// `=`
+    Equals,
+    /// `(`
+    LParen,
+    /// `)`
+    RParen,
+    /// `!`
+    Bang,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "`{}`", w),
+            Token::Comma => write!(f, "`,`"),
+            Token::Colon => write!(f, "`:`"),
+            Token::Equals => write!(f, "`=`"),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+            Token::Bang => write!(f, "`!`"),
+        }
+    }
+}
+
+/// Function to describe a token that might be missing, for error messages
+pub fn describe(t: Option<&Token>) -> String {
+    match t {
+        Some(t) => t.to_string(),
+        None => "end of line".to_string(),
+    }
+}
+
+/// A line of the file, after joining continuation lines
+#[derive(Debug)]
+pub struct LogicalLine {
+    /// The line number the logical line started on, counting from one
+    pub number: usize,
+    /// The text of the line, without the backslash-newlines
+    pub text: String,
+}
+
+/// Function to join lines ending in a backslash with the line after them
+///
+/// The backslash and newline are replaced with a space, so the words on either
+/// side stay separate. An escaped backslash at the end of a line doesn't count.
+pub fn logical_lines(input: &str) -> Vec<LogicalLine> {
+    let mut ret = Vec::new();
+    let mut current: Option<LogicalLine> = None;
+    for (i, line) in input.lines().enumerate() {
+        // Count the backslashes at the end to see if the last one is escaped
+        let trailing = line.chars().rev().take_while(|c| *c == '\\').count();
+        let (body, continued) = if trailing % 2 == 1 {
+            (&line[..line.len() - 1], true)
+        } else {
+            (line, false)
+        };
+        // Add to the current line, or start a new one
+        let ll = current.get_or_insert_with(|| LogicalLine {
+            number: i + 1,
+            text: String::new(),
+        });
+        ll.text.push_str(body);
+        if continued {
+            ll.text.push(' ');
+        } else {
+            ret.extend(current.take());
+        }
+    }
+    // A continuation on the last line just ends it
+    ret.extend(current.take());
+    ret
+}
+
+/// Function to split a logical line into [Token]s
+///
+/// A `#` starts a comment, unless it's followed by a digit, since `#1000` is a
+/// UID. Text in double quotes is taken literally, except for backslash escapes.
+/// It returns an error message if a quote or escape is left unfinished.
+pub fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut ret = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        // Handle the special characters
+        let special = match c {
+            ',' => Some(Token::Comma),
+            ':' => Some(Token::Colon),
+            '=' => Some(Token::Equals),
+            '(' => Some(Token::LParen),
+            ')' => Some(Token::RParen),
+            '!' => Some(Token::Bang),
+            _ => None,
+        };
+        if let Some(t) = special {
+            ret.extend(word.take().map(Token::Word));
+            ret.push(t);
+            continue;
+        }
+
+        match c {
+            // Whitespace just ends the word
+            c if c.is_whitespace() => {
+                ret.extend(word.take().map(Token::Word));
+            }
+            // Comments end the line, unless they're a UID
+            '#' if word.is_none() && !matches!(chars.peek(), Some(n) if n.is_ascii_digit()) => {
+                break;
+            }
+            // Escapes take the next character literally
+            '\\' => match chars.next() {
+                None => return Err("Backslash at end of line".to_string()),
+                Some(n) => word.get_or_insert_with(String::new).push(n),
+            },
+            // Quotes take everything up to the closing quote
+            '"' => {
+                let w = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        None => return Err("Unterminated quote".to_string()),
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            None => return Err("Unterminated quote".to_string()),
+                            Some(n) => w.push(n),
+                        },
+                        Some(n) => w.push(n),
+                    }
+                }
+            }
+            // Everything else is part of a word
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    ret.extend(word.take().map(Token::Word));
+    Ok(ret)
+}
+
+/// Function to escape a word so that [tokenize] reads it back as one [Word][w]
+///
+/// [w]: Token::Word
+pub fn escape(word: &str) -> String {
+    let mut ret = String::new();
+    for (i, c) in word.chars().enumerate() {
+        let needs_escape = c.is_whitespace()
+            || matches!(c, ',' | ':' | '=' | '(' | ')' | '!' | '\\' | '"')
+            || (c == '#' && i == 0 && !word[1..].starts_with(|n: char| n.is_ascii_digit()));
+        if needs_escape {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+    ret
+}
+
+/// Function to quote a string so that [tokenize] reads it back as one
+/// [Word][w]
+///
+/// This is used for the values of `Defaults`, which often have characters
+/// that would otherwise need escaping.
+///
+/// [w]: Token::Word
+pub fn quote(value: &str) -> String {
+    let mut ret = String::from("\"");
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+    ret.push('"');
+    ret
+}
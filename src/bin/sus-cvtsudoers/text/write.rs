@@ -0,0 +1,373 @@
+//! Write a [Sudoers] out in the traditional sudoers text format
+//!
+//! The output is laid out the way `visudo` would expect: includes first, then
+//! `Defaults`, then `User_Alias`es, then one line for each command
+//! specification. Writing each command specification on its own line means
+//! the Runas list and tags never carry over from one to the next, which keeps
+//! the meaning exactly the same as the JSON.
+
+use super::token::{escape, quote};
+use super::{sets_authenticate, Warning};
+use crate::permission::verify::sudoers_type::{CmdSpec, Command, Host, Option, Sudoers, User};
+
+/// Function to write a [Sudoers] as sudoers text
+///
+/// It returns the text, along with [Warning]s for anything that can't be
+/// written. That's options sudo doesn't have, and options in command
+/// specifications that don't have a tag.
+///
+/// SUS doesn't ask for a password unless told to. If the [Sudoers] doesn't say
+/// either way, `!authenticate` is written first in the `Defaults`, so `sudo`
+/// doesn't ask either.
+pub fn write(sudoers: &Sudoers) -> (String, Vec<Warning>) {
+    let mut out = String::new();
+    let mut warnings = Vec::new();
+
+    // Includes
+    for inc in &sudoers.includes {
+        out.push_str(&format!("@include {}\n", escape(inc)));
+    }
+    for inc in &sudoers.include_dirs {
+        out.push_str(&format!("@includedir {}\n", escape(inc)));
+    }
+    if !sudoers.includes.is_empty() || !sudoers.include_dirs.is_empty() {
+        out.push('\n');
+    }
+
+    // Defaults
+    // Leave out the line entirely if nothing in it can be written
+    let implicit = !sets_authenticate(sudoers);
+    if implicit {
+        out.push_str("Defaults !authenticate\n");
+    }
+    for d in &sudoers.defaults {
+        let mut entries = Vec::new();
+        for o in &d.options {
+            match default_entry(o) {
+                Some(e) => entries.push(e),
+                None => warnings.push(Warning {
+                    line: None,
+                    message: format!("Defaults entry {:?} is not supported and was dropped", o),
+                }),
+            }
+        }
+        if !entries.is_empty() {
+            out.push_str(&format!("Defaults {}\n", entries.join(", ")));
+        }
+    }
+    if implicit || !sudoers.defaults.is_empty() {
+        out.push('\n');
+    }
+
+    // Aliases
+    for (name, users) in &sudoers.user_aliases {
+        out.push_str(&format!(
+            "User_Alias {} = {}\n",
+            escape(name),
+            users_str(users)
+        ));
+    }
+    if !sudoers.user_aliases.is_empty() {
+        out.push('\n');
+    }
+
+    // Rules
+    for spec in &sudoers.user_specs {
+        let hosts: Vec<String> = spec
+            .host_list
+            .iter()
+            .map(|h| match h {
+                Host::Hostname(n) => escape(n),
+            })
+            .collect();
+        for cmd_spec in &spec.cmd_specs {
+            out.push_str(&format!(
+                "{} {} = {}\n",
+                users_str(&spec.user_list),
+                hosts.join(", "),
+                cmd_spec_str(cmd_spec, &mut warnings),
+            ));
+        }
+    }
+
+    (out, warnings)
+}
+
+/// Function to write a single `Defaults` entry
+///
+/// It returns [None] for options that sudo doesn't have.
+fn default_entry(o: &Option) -> std::option::Option<String> {
+    let (name, value) = match o {
+        Option::Setenv(b) => ("setenv", Ok(*b)),
+        Option::Authenticate(b) => ("authenticate", Ok(*b)),
+        Option::EnvReset(b) => ("env_reset", Ok(*b)),
+        Option::MailBadpass(b) => ("mail_badpass", Ok(*b)),
+        Option::SecurePath(s) => ("secure_path", Err(s.clone())),
+        Option::LogInput(b) => ("log_input", Ok(*b)),
+        Option::LogOutput(b) => ("log_output", Ok(*b)),
+        Option::UsePty(b) => ("use_pty", Ok(*b)),
+        Option::Noexec(b) => ("noexec", Ok(*b)),
+        Option::Intercept(b) => ("intercept", Ok(*b)),
+        Option::LogSubcmds(b) => ("log_subcmds", Ok(*b)),
+        Option::Closefrom(n) => ("closefrom", Err(n.to_string())),
+        Option::ClosefromOverride(b) => ("closefrom_override", Ok(*b)),
+        Option::Umask(m) => ("umask", Err(format!("{:04o}", m))),
+        Option::CommandTimeout(t) => ("command_timeout", Err(t.to_string())),
+        Option::UserCommandTimeouts(b) => ("user_command_timeouts", Ok(*b)),
+        // sudo counts this in minutes
+        Option::PasswdTimeout(t) => ("passwd_timeout", Err((*t as f64 / 60.0).to_string())),
+        Option::Pwfeedback(b) => ("pwfeedback", Ok(*b)),
+        Option::Runcwd(d) => ("runcwd", Err(d.clone())),
+        Option::Runchroot(d) => ("runchroot", Err(d.clone())),
+        Option::Rlimit(r) => {
+            let limit = |l: std::option::Option<u64>| match l {
+                Some(n) => n.to_string(),
+                None => "infinity".to_string(),
+            };
+            let value = if r.soft == r.hard {
+                limit(r.soft)
+            } else {
+                format!("{},{}", limit(r.soft), limit(r.hard))
+            };
+            return Some(format!("rlimit_{}={}", r.resource.name(), quote(&value)));
+        }
+        Option::LoginRlimits(_) | Option::Nice(_) | Option::Ionice(_) => return None,
+        Option::Cgroup(_)
+        | Option::CgroupMemoryMax(_)
+        | Option::CgroupCpuMax(_)
+        | Option::CgroupPidsMax(_) => return None,
+        Option::Landlock(_) | Option::LandlockRequired(_) => return None,
+        Option::Capabilities(_) | Option::Namespaces(_) => return None,
+        Option::Runner(_) | Option::Background(_) => return None,
+    };
+    Some(match value {
+        Ok(true) => name.to_string(),
+        Ok(false) => format!("!{}", name),
+        Err(s) => format!("{}={}", name, quote(&s)),
+    })
+}
+
+/// Function to get the tag for an [Option] in a command specification
+///
+/// It returns [None] for options that can only be `Defaults`.
+fn tag(o: &Option) -> std::option::Option<&'static str> {
+    match o {
+        Option::Setenv(true) => Some("SETENV"),
+        Option::Setenv(false) => Some("NOSETENV"),
+        Option::Authenticate(true) => Some("PASSWD"),
+        Option::Authenticate(false) => Some("NOPASSWD"),
+        Option::LogInput(true) => Some("LOG_INPUT"),
+        Option::LogInput(false) => Some("NOLOG_INPUT"),
+        Option::LogOutput(true) => Some("LOG_OUTPUT"),
+        Option::LogOutput(false) => Some("NOLOG_OUTPUT"),
+        Option::Noexec(true) => Some("NOEXEC"),
+        Option::Noexec(false) => Some("EXEC"),
+        Option::Intercept(true) => Some("INTERCEPT"),
+        Option::Intercept(false) => Some("NOINTERCEPT"),
+        Option::LogSubcmds(true) => Some("LOG_SUBCMDS"),
+        Option::LogSubcmds(false) => Some("NOLOG_SUBCMDS"),
+        _ => None,
+    }
+}
+
+/// Function to write a list of [User]s
+fn users_str(users: &[User]) -> String {
+    users
+        .iter()
+        .map(|u| match u {
+            User::Username(n) => escape(n),
+            User::Usergroup(g) => format!("%{}", escape(g)),
+            User::Useralias(a) => escape(a),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Function to write a [CmdSpec], with its Runas list and tags
+fn cmd_spec_str(cmd_spec: &CmdSpec, warnings: &mut Vec<Warning>) -> String {
+    let mut ret = String::new();
+
+    // Runas list
+    // Group names are given without the `%`
+    let groups: Vec<String> = cmd_spec
+        .run_as_groups
+        .iter()
+        .map(|g| match g {
+            User::Username(n) | User::Usergroup(n) | User::Useralias(n) => escape(n),
+        })
+        .collect();
+    ret.push('(');
+    ret.push_str(&users_str(&cmd_spec.run_as_users));
+    if !groups.is_empty() {
+        ret.push_str(if cmd_spec.run_as_users.is_empty() {
+            ": "
+        } else {
+            " : "
+        });
+        ret.push_str(&groups.join(", "));
+    }
+    ret.push_str(") ");
+
+    // Options
+    // These have to come before the tags
+    for o in &cmd_spec.options {
+        match o {
+            Option::CommandTimeout(t) => ret.push_str(&format!("TIMEOUT={} ", t)),
+            Option::Runcwd(d) => ret.push_str(&format!("CWD={} ", escape(d))),
+            Option::Runchroot(d) => ret.push_str(&format!("CHROOT={} ", escape(d))),
+            _ => (),
+        }
+    }
+
+    // Tags
+    for o in &cmd_spec.options {
+        if matches!(
+            o,
+            Option::CommandTimeout(_) | Option::Runcwd(_) | Option::Runchroot(_)
+        ) {
+            continue;
+        }
+        match tag(o) {
+            Some(t) => {
+                ret.push_str(t);
+                ret.push_str(": ");
+            }
+            None => warnings.push(Warning {
+                line: None,
+                message: format!("{:?} has no tag and was dropped", o),
+            }),
+        }
+    }
+
+    // Commands
+    let cmds: Vec<String> = cmd_spec
+        .commands
+        .iter()
+        .map(|c| match c {
+            Command::CmdPath(p) => escape(&p.to_string_lossy()),
+            Command::Sudoedit(files) => std::iter::once("sudoedit".to_string())
+                .chain(files.iter().map(|f| escape(f)))
+                .collect::<Vec<_>>()
+                .join(" "),
+        })
+        .collect();
+    ret.push_str(&cmds.join(", "));
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse;
+    use super::*;
+    use serde_json::{json, Value};
+
+    const RULE: &str = r#"{
+        "User_List": [{"username": "alice"}],
+        "Host_List": [{"hostname": "ALL"}],
+        "Cmnd_Specs": [{
+            "runasusers": [{"username": "root"}],
+            "Options": [],
+            "Commands": [{"command": "/usr/bin/id"}]
+        }]
+    }"#;
+
+    fn from_json(s: &str) -> Sudoers {
+        serde_json::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn no_password_unless_told_to() {
+        let (text, warnings) = write(&from_json(&format!(r#"{{"User_Specs": [{}]}}"#, RULE)));
+        assert!(warnings.is_empty());
+        assert_eq!(
+            text,
+            "Defaults !authenticate\n\nalice ALL = (root) /usr/bin/id\n"
+        );
+
+        // Reading it back keeps it that way
+        let (back, _) = parse(&text).unwrap();
+        assert_eq!(
+            serde_json::to_value(&back).unwrap()["Defaults"],
+            json!([{"Options": [{"authenticate": false}]}])
+        );
+
+        // Saying so is written as it is
+        let (text, _) = write(&from_json(&format!(
+            r#"{{"Defaults": [{{"Options": [{{"authenticate": true}}]}}], "User_Specs": [{}]}}"#,
+            RULE
+        )));
+        assert_eq!(
+            text,
+            "Defaults authenticate\n\nalice ALL = (root) /usr/bin/id\n"
+        );
+
+        // Without rules, there's nothing to say
+        let (text, _) = write(&from_json("{}"));
+        assert_eq!(text, "");
+    }
+
+    #[test]
+    fn text_round_trips() {
+        let input = "@include /etc/sus/extra\n\
+                     @includedir /etc/sus/d\n\
+                     \n\
+                     Defaults env_reset, secure_path=\"/usr/bin:/bin\", umask=0022\n\
+                     Defaults rlimit_nofile=\"1024,4096\", !pwfeedback\n\
+                     \n\
+                     User_Alias ADMINS = alice, %wheel\n\
+                     \n\
+                     ADMINS ALL = (root : wheel) NOPASSWD: /bin/ls, /bin/cat\n\
+                     bob web, db = (operator) TIMEOUT=60 LOG_OUTPUT: sudoedit /etc/hosts\n";
+        let (first, warnings) = parse(input).unwrap();
+        assert!(warnings.is_empty());
+        let (text, warnings) = write(&first);
+        assert!(warnings.is_empty());
+        let (second, _) = parse(&text).unwrap();
+        assert_eq!(
+            serde_json::to_value(&first).unwrap(),
+            serde_json::to_value(&second).unwrap()
+        );
+        assert_eq!(write(&second).0, text);
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let input: Value = json!({
+            "Defaults": [{"Options": [
+                {"authenticate": true},
+                {"command_timeout": 90},
+                {"passwd_timeout": 150},
+                {"runcwd": "*"}
+            ]}],
+            "User_Specs": [{
+                "User_List": [{"usergroup": "wheel"}],
+                "Host_List": [{"hostname": "ALL"}],
+                "Cmnd_Specs": [{
+                    "runasusers": [{"username": "root"}],
+                    "runasgroups": [{"usergroup": "adm"}],
+                    "Options": [{"runchroot": "/srv/jail"}, {"setenv": true}],
+                    "Commands": [{"command": "ALL"}]
+                }]
+            }]
+        });
+        let sudoers: Sudoers = serde_json::from_value(input.clone()).unwrap();
+        let (text, warnings) = write(&sudoers);
+        assert!(warnings.is_empty());
+        let (back, warnings) = parse(&text).unwrap();
+        assert!(warnings.is_empty());
+        let mut back = serde_json::to_value(&back).unwrap();
+        back.as_object_mut().unwrap().remove("User_Aliases");
+        assert_eq!(back, input);
+    }
+
+    #[test]
+    fn what_sudo_cant_say_is_dropped() {
+        let (text, warnings) = write(&from_json(&format!(
+            r#"{{"Defaults": [{{"Options": [{{"authenticate": false}}, {{"cgroup": true}}]}}],
+                "User_Specs": [{}]}}"#,
+            RULE
+        )));
+        assert_eq!(warnings.len(), 1);
+        assert!(text.starts_with("Defaults !authenticate\n"));
+    }
+}
@@ -15,6 +15,10 @@ pub use iterator::from_iterator;
 
 use super::Executable;
 
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
 /// Type for (automatic) [Executable] factories
 ///
 /// We need to be able to generate [Executable]s in different ways. We might
@@ -60,3 +64,21 @@ pub enum ExecutableFactoryError {
     /// `content` is the content of the string.
     ArgMalformed { position: usize, content: String },
 }
+
+impl Display for ExecutableFactoryError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ExecutableFactoryError::PathNotFound => write!(f, "Path not found")?,
+            ExecutableFactoryError::PathMalformed { content } => {
+                write!(f, "Path malformed - {:?}", content)?;
+            }
+            ExecutableFactoryError::ArgNotFound => write!(f, "Arguments not found")?,
+            ExecutableFactoryError::ArgMalformed { position, content } => {
+                write!(f, "Argument {} malformed - {:?}", position, content)?;
+            }
+        };
+        Ok(())
+    }
+}
+
+impl Error for ExecutableFactoryError {}
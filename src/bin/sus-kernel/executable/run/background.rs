@@ -0,0 +1,59 @@
+//! Module for running an [Executable][eb] in the background
+//!
+//! With `sus -b`, the user gets their shell back as soon as the request has
+//! been checked and logged. The kernel forks, and the parent exits right away.
+//! The child carries on to run the [Executable][eb] as usual, in a session of
+//! its own, so it has no controlling terminal and isn't sent the terminal's
+//! signals. Any of the standard streams that are a terminal are pointed at
+//! `/dev/null` instead, since nothing is reading or writing the terminal for
+//! it anymore. Streams the user redirected elsewhere are kept.
+//!
+//! How the [Executable][eb] ends can't be reported back to the user's shell.
+//! If the [Options][op] ask for it, it's still logged.
+//!
+//! [eb]: crate::executable::Executable
+//! [op]: crate::permission::verify::Options
+
+use nix::errno::Errno;
+use nix::fcntl;
+use nix::fcntl::OFlag;
+use nix::sys::stat::Mode;
+use nix::unistd;
+use nix::unistd::ForkResult;
+
+/// Function to move this process into the background
+///
+/// Only the child returns, as the leader of a new session. The parent exits
+/// successfully, since the request was allowed, but only once the child has
+/// left the terminal's session. Otherwise, if the user's terminal closed right
+/// after, the child could still be sent `SIGHUP`. If the fork fails, nothing
+/// has changed.
+pub fn detach() -> Result<(), Errno> {
+    let (read, write) = unistd::pipe2(OFlag::O_CLOEXEC)?;
+    if let ForkResult::Parent { .. } = unsafe { unistd::fork() }? {
+        // Wait for the child to close its end
+        let _ = unistd::close(write);
+        let mut buf = [0u8; 1];
+        while let Err(Errno::EINTR) = unistd::read(read, &mut buf) {}
+        std::process::exit(0);
+    }
+    let _ = unistd::close(read);
+    // The parent is going away, so the only way to report failure is to exit
+    // Exit the same way the kernel does on failure
+    if unistd::setsid().is_err() || detach_streams().is_err() {
+        std::process::exit(1);
+    }
+    unistd::close(write)
+}
+
+/// Function to point any of the standard streams that are a terminal at
+/// `/dev/null`
+fn detach_streams() -> Result<(), Errno> {
+    let null = fcntl::open("/dev/null", OFlag::O_RDWR | OFlag::O_CLOEXEC, Mode::empty())?;
+    for fd in 0..3 {
+        if unistd::isatty(fd).unwrap_or(false) {
+            unistd::dup2(null, fd)?;
+        }
+    }
+    unistd::close(null)
+}
@@ -0,0 +1,331 @@
+//! Module for running an [Executable][eb] in its own cgroup
+//!
+//! The policy can ask for each [Executable][eb] to run in a cgroup of its own,
+//! under [config::CGROUP_PARENT]. That keeps everything it starts together, so
+//! it can be limited with `cgroup_memory_max`, `cgroup_cpu_max`, and
+//! `cgroup_pids_max`, and so what it used can be logged once it's done. Only
+//! cgroup v2 is supported.
+//!
+//! The runner creates the [Cgroup] before it forks, and the child moves itself
+//! into it before it becomes the [Executable][eb]. Once the child has exited,
+//! the runner gets the [Usage]. The [Cgroup] is removed when it's dropped,
+//! killing anything that was left behind. If a runner died before it could do
+//! that, the next one to create a [Cgroup] removes it instead.
+//!
+//! [eb]: crate::executable::Executable
+
+use super::RunError;
+use crate::config;
+use crate::permission::verify::Options;
+
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// What an [Executable][eb] used while it ran
+///
+/// Either might be missing, if the [Executable][eb] didn't run in a [Cgroup],
+/// or if the kernel doesn't keep track of it.
+///
+/// [eb]: crate::executable::Executable
+#[derive(Debug, Clone, Default)]
+pub struct Usage {
+    /// The most memory it used at once, in bytes
+    pub peak_memory: Option<u64>,
+    /// How much CPU time it used
+    pub cpu: Option<Duration>,
+}
+
+impl Display for Usage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(m) = self.peak_memory {
+            write!(f, "; Peak memory {} bytes", m)?;
+        }
+        if let Some(c) = self.cpu {
+            write!(f, "; CPU time {}.{:0>6}s", c.as_secs(), c.subsec_micros())?;
+        }
+        Ok(())
+    }
+}
+
+/// A cgroup created for a single [Executable][eb]
+///
+/// [eb]: crate::executable::Executable
+#[derive(Debug)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Function to create the [Cgroup] the [Options] ask for, if any
+    ///
+    /// It's named after this process, and has the limits from the [Options]
+    /// set. Controllers are turned on as needed in [config::CGROUP_PARENT] and
+    /// above it. The memory controller is always turned on if it can be, so
+    /// the peak can be logged. Any [Cgroup]s left behind by runners that have
+    /// died are removed first. See [sweep].
+    ///
+    /// This has to be done as `root`.
+    pub fn create(opts: &Options) -> io::Result<Option<Cgroup>> {
+        if !opts.cgroup() {
+            return Ok(None);
+        }
+        let limits = [
+            ("memory", "memory.max", opts.cgroup_memory_max()),
+            ("cpu", "cpu.max", opts.cgroup_cpu_max()),
+            ("pids", "pids.max", opts.cgroup_pids_max()),
+        ];
+
+        // Turn on the controllers
+        // Failing to turn on one that's only used for the log isn't an error
+        let root = PathBuf::from(config::CGROUP_ROOT);
+        let parent = root.join(config::CGROUP_PARENT);
+        fs::create_dir_all(&parent)?;
+        for dir in [&root, &parent] {
+            let control = dir.join("cgroup.subtree_control");
+            let _ = fs::write(&control, "+memory");
+            for (controller, _, value) in &limits {
+                if value.is_some() {
+                    fs::write(&control, format!("+{}", controller))?;
+                }
+            }
+        }
+
+        // Clean up after runners that didn't, then make ours
+        sweep(&parent);
+        let ret = Cgroup {
+            path: parent.join(format!("session-{}", std::process::id())),
+        };
+        fs::create_dir(&ret.path)?;
+        for (_, file, value) in &limits {
+            if let Some(v) = value {
+                fs::write(ret.path.join(file), v)?;
+            }
+        }
+        Ok(Some(ret))
+    }
+
+    /// Function to open the file processes are moved into the [Cgroup] with
+    ///
+    /// This is opened before forking, so the child only has to [join] it.
+    pub fn procs(&self) -> io::Result<File> {
+        OpenOptions::new()
+            .write(true)
+            .open(self.path.join("cgroup.procs"))
+    }
+
+    /// Function to get what was used in the [Cgroup]
+    ///
+    /// Anything that can't be read is left out.
+    pub fn usage(&self) -> Usage {
+        let read = |name: &str| fs::read_to_string(self.path.join(name)).ok();
+        Usage {
+            peak_memory: read("memory.peak").and_then(|s| s.trim().parse().ok()),
+            cpu: read("cpu.stat").and_then(|s| {
+                s.lines()
+                    .find_map(|l| l.strip_prefix("usage_usec "))
+                    .and_then(|u| u.trim().parse().ok())
+                    .map(Duration::from_micros)
+            }),
+        }
+    }
+}
+
+impl Drop for Cgroup {
+    /// Function to remove the [Cgroup]
+    ///
+    /// Anything still running in it is killed first. A [Cgroup] can only be
+    /// removed once everything in it has exited, so this tries for a while
+    /// before giving up. Failing to remove it isn't an error, since the
+    /// [Executable][eb] already ran.
+    ///
+    /// [eb]: crate::executable::Executable
+    fn drop(&mut self) {
+        // The file is always there in a real cgroup, so don't create it
+        let _ = OpenOptions::new()
+            .write(true)
+            .open(self.path.join("cgroup.kill"))
+            .and_then(|mut f| f.write_all(b"1"));
+        // Wait for up to a second
+        for _ in 0..100 {
+            if fs::remove_dir(&self.path).is_ok() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// Function to remove the [Cgroup]s in `parent` whose runner is gone
+///
+/// Each is named after the runner that made it. If that process doesn't exist
+/// anymore, nothing is going to remove its [Cgroup], so it's dropped here.
+/// Anything else in `parent` is left alone.
+fn sweep(parent: &Path) {
+    let entries = match fs::read_dir(parent) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let pid = match name
+            .to_str()
+            .and_then(|n| n.strip_prefix("session-"))
+            .and_then(|p| p.parse::<u32>().ok())
+        {
+            Some(p) => p,
+            None => continue,
+        };
+        if pid != std::process::id() && !Path::new(&format!("/proc/{}", pid)).exists() {
+            drop(Cgroup { path: entry.path() });
+        }
+    }
+}
+
+/// Function for a runner to create the [Cgroup] the [Options] ask for
+///
+/// It returns the [Cgroup], along with its [procs][Cgroup::procs] file for the
+/// child to [join].
+pub fn prepare(opts: &Options) -> Result<Option<(Cgroup, File)>, RunError> {
+    let cgroup = match Cgroup::create(opts) {
+        Ok(Some(c)) => c,
+        Ok(None) => return Ok(None),
+        Err(e) => return Err(RunError::Cgroup { cause: e }),
+    };
+    let procs = cgroup.procs().map_err(|e| RunError::Cgroup { cause: e })?;
+    Ok(Some((cgroup, procs)))
+}
+
+/// Function to move this process into a [Cgroup]
+///
+/// It's given the file from [Cgroup::procs]. This is called by the child after
+/// it forks.
+pub fn join(procs: &mut File) -> io::Result<()> {
+    procs.write_all(std::process::id().to_string().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permission::verify::sudoers_type;
+
+    use nix::libc;
+    use nix::sys::signal::Signal;
+    use nix::sys::wait;
+    use nix::sys::wait::WaitStatus;
+    use nix::unistd;
+    use nix::unistd::ForkResult;
+
+    #[test]
+    fn usage_is_parsed() {
+        let dir = std::env::temp_dir().join(format!("sus-cgroup-{}", std::process::id()));
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("memory.peak"), "4096\n").unwrap();
+        fs::write(
+            dir.join("cpu.stat"),
+            "usage_usec 1500000\nuser_usec 1000000\n",
+        )
+        .unwrap();
+
+        // It isn't a real cgroup, so don't try to remove it like one
+        let cgroup = Cgroup { path: dir.clone() };
+        let usage = cgroup.usage();
+        std::mem::forget(cgroup);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(usage.peak_memory, Some(4096));
+        assert_eq!(usage.cpu, Some(Duration::from_micros(1_500_000)));
+        assert_eq!(
+            usage.to_string(),
+            "; Peak memory 4096 bytes; CPU time 1.500000s"
+        );
+    }
+
+    #[test]
+    fn stale_ones_are_swept() {
+        let dir = std::env::temp_dir().join(format!("sus-sweep-{}", std::process::id()));
+        fs::create_dir(&dir).unwrap();
+        // No process can have a PID above the kernel's limit of 2^22
+        let dead = dir.join("session-4194305");
+        let ours = dir.join(format!("session-{}", std::process::id()));
+        let other = dir.join("other-1");
+        for d in [&dead, &ours, &other] {
+            fs::create_dir(d).unwrap();
+        }
+
+        sweep(&dir);
+        let left = (dead.exists(), ours.exists(), other.exists());
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(left, (false, true, true));
+    }
+
+    #[test]
+    fn created_limited_and_removed() {
+        // This needs root, and cgroup v2 where the kernel expects it
+        let root = PathBuf::from(config::CGROUP_ROOT);
+        let controllers = match fs::read_to_string(root.join("cgroup.controllers")) {
+            Ok(c) if unistd::geteuid().is_root() => c,
+            _ => return,
+        };
+        let has = |name: &str| controllers.split_whitespace().any(|c| c == name);
+
+        // Only ask for the limits that can be set here
+        let mut opts = vec![sudoers_type::Option::Cgroup(true)];
+        let mut limits = Vec::new();
+        if has("pids") {
+            opts.push(sudoers_type::Option::CgroupPidsMax("16".to_string()));
+            limits.push(("pids.max", "16"));
+        }
+        if has("memory") {
+            opts.push(sudoers_type::Option::CgroupMemoryMax(
+                "67108864".to_string(),
+            ));
+            limits.push(("memory.max", "67108864"));
+        }
+        let (cgroup, mut procs) = prepare(&Options(opts)).unwrap().unwrap();
+        let path = cgroup.path.clone();
+        assert!(path.is_dir());
+        for (file, value) in limits {
+            assert_eq!(fs::read_to_string(path.join(file)).unwrap().trim(), value);
+        }
+
+        // Put something in it that would run forever
+        let child = match unsafe { unistd::fork() }.unwrap() {
+            ForkResult::Parent { child } => child,
+            ForkResult::Child => {
+                if join(&mut procs).is_ok() {
+                    loop {
+                        unistd::pause();
+                    }
+                }
+                unsafe { libc::_exit(1) }
+            }
+        };
+        let joined = (0..100).any(|_| {
+            let procs = fs::read_to_string(path.join("cgroup.procs")).unwrap_or_default();
+            if procs.lines().any(|p| p == child.to_string()) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+            false
+        });
+        assert!(joined);
+        if has("memory") {
+            assert!(cgroup.usage().peak_memory.is_some());
+        }
+
+        // Removing it kills what's left
+        drop(cgroup);
+        assert!(!path.exists());
+        assert_eq!(
+            wait::waitpid(child, None).unwrap(),
+            WaitStatus::Signaled(child, Signal::SIGKILL, false)
+        );
+    }
+}
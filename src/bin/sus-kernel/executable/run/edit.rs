@@ -0,0 +1,580 @@
+//! Module for editing files as another user, without running an editor as them
+//!
+//! Editing a configuration file by running an editor as `root` means running a
+//! large program, along with any plugins or shell escapes it has, with full
+//! privileges. Instead, `sus -e` asks for the [config::SUDOEDIT_PATH]
+//! pseudo-command, with the files to edit as its arguments. The policy allows
+//! that with a `sudoedit` command listing the files.
+//!
+//! [edit] opens each file as the target user, and copies it to a temporary file
+//! in [config::SUDOEDIT_TMPDIR] that belongs to the user. The user's editor is
+//! then run on the copies as the user, with nothing more than they already
+//! have. Once it exits, every copy that changed is written back into its file,
+//! which keeps its owner and mode. Files that didn't exist are created as the
+//! target user.
+//!
+//! No symbolic links are followed on the way to a file, and the file itself
+//! can't be one. Neither can it be in a directory the user can write to, since
+//! they could then swap what's in it. Both are checked for every directory on
+//! the way, unless the user is `root`.
+
+use super::sanitize;
+use super::sanitize::sanitize;
+use super::supervise::set_handlers;
+use super::Executable;
+use super::RunError;
+use super::RunResult;
+use crate::config;
+use crate::permission::privilege;
+use crate::permission::verify::Options;
+use crate::permission::Permission;
+
+use nix::errno::Errno;
+use nix::fcntl;
+use nix::fcntl::OFlag;
+use nix::libc;
+use nix::sys::signal;
+use nix::sys::signal::{SigHandler, Signal};
+use nix::sys::stat;
+use nix::sys::stat::{FileStat, Mode, SFlag};
+use nix::sys::wait;
+use nix::sys::wait::{WaitPidFlag, WaitStatus};
+use nix::unistd;
+use nix::unistd::{ForkResult, Gid, Uid};
+use std::error::Error;
+use std::ffi::{CString, OsStr};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+/// The mode files that didn't exist are created with
+const NEW_FILE_MODE: u32 = 0o644;
+
+/// The IDs to act as when touching files
+struct Ids {
+    uid: Uid,
+    gid: Gid,
+    groups: Vec<Gid>,
+}
+
+impl Ids {
+    /// Function to get the IDs of the user who ran us
+    fn user() -> Result<Ids, Errno> {
+        Ok(Ids {
+            uid: unistd::getuid(),
+            gid: unistd::getgid(),
+            groups: unistd::getgroups()?,
+        })
+    }
+
+    /// Function to get the IDs of the target, from the [Permission]s asked for
+    fn target(perm: &Permission) -> Ids {
+        let mut groups: Vec<Gid> = perm.secondary_gids.iter().cloned().collect();
+        groups.push(perm.primary_gid);
+        groups.sort_by_key(|g| g.as_raw());
+        groups.dedup();
+        Ids {
+            uid: perm.uid,
+            gid: perm.primary_gid,
+            groups,
+        }
+    }
+
+    /// Function to run `f` with the Effective IDs set to these
+    ///
+    /// This has to be called as `root`, and goes back to `root` afterwards. If
+    /// that fails, we'd be left as someone else, so this panics instead.
+    fn act<T, F: FnOnce() -> T>(&self, f: F) -> Result<T, Errno> {
+        unistd::setgroups(&self.groups)?;
+        unistd::setegid(self.gid)?;
+        unistd::seteuid(self.uid)?;
+        privilege::undumpable()?;
+        let ret = f();
+        privilege::regain_root().expect("Failed to regain root");
+        Ok(ret)
+    }
+
+    /// Function to see if these IDs could change what's in a directory
+    ///
+    /// Owning it counts, since the owner can always change its mode.
+    fn can_write(&self, st: &FileStat) -> bool {
+        if st.st_uid == self.uid.as_raw() {
+            return true;
+        }
+        let in_group =
+            st.st_gid == self.gid.as_raw() || self.groups.iter().any(|g| g.as_raw() == st.st_gid);
+        if in_group {
+            st.st_mode & 0o020 != 0
+        } else {
+            st.st_mode & 0o002 != 0
+        }
+    }
+}
+
+/// A file being edited
+struct Target {
+    /// The path the user gave
+    path: String,
+    /// The directory it's in
+    dir: File,
+    /// Its name in that directory
+    name: CString,
+    /// The file itself, open for reading and writing, unless it doesn't exist
+    file: Option<File>,
+    /// What it had in it to start with
+    contents: Vec<u8>,
+    /// The copy the user edits
+    temp: PathBuf,
+}
+
+/// Function to wrap a file descriptor in a [File], so it's closed when dropped
+fn owned(fd: RawFd) -> File {
+    unsafe { File::from_raw_fd(fd) }
+}
+
+/// Function to get the [Errno] out of an [io::Error]
+fn errno(e: io::Error) -> Errno {
+    Errno::from_i32(e.raw_os_error().unwrap_or(libc::EIO))
+}
+
+/// Function to see what type of file something is
+fn file_type(st: &FileStat) -> SFlag {
+    SFlag::from_bits_truncate(st.st_mode) & SFlag::S_IFMT
+}
+
+/// Function to open a file to edit, as whoever is acting
+///
+/// Every directory on the way is opened in turn, without following symbolic
+/// links, and checked against the `user`. The file itself is opened for
+/// reading and writing. If it doesn't exist, only its directory is returned.
+fn open(path: &str, user: &Ids) -> Result<(File, CString, Option<File>), EditError> {
+    let syscall = |en| EditError::Syscall {
+        path: path.to_string(),
+        errno: en,
+    };
+    let check = |dir: &File| -> Result<(), EditError> {
+        let st = stat::fstat(dir.as_raw_fd()).map_err(syscall)?;
+        if !user.uid.is_root() && user.can_write(&st) {
+            return Err(EditError::WritableDir {
+                path: path.to_string(),
+            });
+        }
+        Ok(())
+    };
+
+    // Split it up
+    // Relative paths would be relative to wherever the user ran us from
+    let mut parts: Vec<&str> = match path.strip_prefix('/') {
+        Some(p) => p.split('/').filter(|p| !p.is_empty()).collect(),
+        None => return Err(syscall(Errno::EINVAL)),
+    };
+    let name = parts.pop().ok_or_else(|| syscall(Errno::EINVAL))?;
+    if parts.iter().chain([&name]).any(|p| *p == "." || *p == "..") {
+        return Err(syscall(Errno::EINVAL));
+    }
+
+    // Walk down to the directory it's in
+    let flags = OFlag::O_PATH | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC;
+    let mut dir = owned(fcntl::open("/", flags, Mode::empty()).map_err(syscall)?);
+    for part in parts {
+        check(&dir)?;
+        let next =
+            owned(fcntl::openat(dir.as_raw_fd(), part, flags, Mode::empty()).map_err(syscall)?);
+        let st = stat::fstat(next.as_raw_fd()).map_err(syscall)?;
+        match file_type(&st) {
+            SFlag::S_IFDIR => dir = next,
+            SFlag::S_IFLNK => {
+                return Err(EditError::Symlink {
+                    path: path.to_string(),
+                })
+            }
+            _ => return Err(syscall(Errno::ENOTDIR)),
+        }
+    }
+    check(&dir)?;
+
+    // Open the file itself
+    // Don't block if it's something like a FIFO, since it'll be refused anyway
+    let name = CString::new(name).map_err(|_| syscall(Errno::EINVAL))?;
+    let res = fcntl::openat(
+        dir.as_raw_fd(),
+        name.as_c_str(),
+        OFlag::O_RDWR | OFlag::O_NOFOLLOW | OFlag::O_NONBLOCK | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    );
+    let file = match res {
+        Ok(fd) => owned(fd),
+        Err(Errno::ENOENT) => return Ok((dir, name, None)),
+        Err(Errno::ELOOP) => {
+            return Err(EditError::Symlink {
+                path: path.to_string(),
+            })
+        }
+        Err(en) => return Err(syscall(en)),
+    };
+    let st = stat::fstat(file.as_raw_fd()).map_err(syscall)?;
+    if file_type(&st) != SFlag::S_IFREG {
+        return Err(EditError::NotRegular {
+            path: path.to_string(),
+        });
+    }
+    Ok((dir, name, Some(file)))
+}
+
+/// Function to create the copy of a file for the user to edit
+///
+/// This should be done as the user, so the copy is theirs. It's named at
+/// random, but ends with the name of the file, so editors can tell what kind
+/// of file it is.
+fn make_temp(path: &str, contents: &[u8]) -> Result<PathBuf, Errno> {
+    let mut random = [0u8; 8];
+    File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(&mut random))
+        .map_err(errno)?;
+    let random: String = random.iter().map(|b| format!("{:02x}", b)).collect();
+    let name = Path::new(path)
+        .file_name()
+        .unwrap_or_else(|| OsStr::new("file"));
+    let temp = Path::new(config::SUDOEDIT_TMPDIR).join(format!(
+        "sus-{}-{}",
+        random,
+        name.to_string_lossy()
+    ));
+
+    let fd = fcntl::open(
+        &temp,
+        OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_EXCL | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC,
+        Mode::from_bits_truncate(0o600),
+    )?;
+    if let Err(e) = owned(fd).write_all(contents) {
+        let _ = fs::remove_file(&temp);
+        return Err(errno(e));
+    }
+    Ok(temp)
+}
+
+/// Function to read what the user left in a copy
+///
+/// This should be done as the user. The copy might have been replaced, so
+/// symbolic links aren't followed.
+fn read_temp(temp: &Path) -> Result<Vec<u8>, Errno> {
+    let fd = fcntl::open(
+        temp,
+        OFlag::O_RDONLY | OFlag::O_NOFOLLOW | OFlag::O_NONBLOCK | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    )?;
+    let mut file = owned(fd);
+    let st = stat::fstat(file.as_raw_fd())?;
+    if file_type(&st) != SFlag::S_IFREG {
+        return Err(Errno::EINVAL);
+    }
+    let mut ret = Vec::new();
+    file.read_to_end(&mut ret).map_err(errno)?;
+    Ok(ret)
+}
+
+/// Function to write new contents into a file being edited
+///
+/// This should be done as the target. An existing file is overwritten in
+/// place, so it keeps its owner and mode. A new one is created with
+/// [NEW_FILE_MODE], as long as nothing took its place in the meantime.
+fn write_back(target: &Target, contents: &[u8]) -> Result<(), Errno> {
+    match &target.file {
+        Some(file) => {
+            file.set_len(0).map_err(errno)?;
+            file.write_all_at(contents, 0).map_err(errno)?;
+            file.sync_all().map_err(errno)
+        }
+        None => {
+            let fd = fcntl::openat(
+                target.dir.as_raw_fd(),
+                target.name.as_c_str(),
+                OFlag::O_WRONLY
+                    | OFlag::O_CREAT
+                    | OFlag::O_EXCL
+                    | OFlag::O_NOFOLLOW
+                    | OFlag::O_CLOEXEC,
+                Mode::from_bits_truncate(NEW_FILE_MODE),
+            )?;
+            let mut file = owned(fd);
+            // Don't let the kernel's umask decide the mode
+            stat::fchmod(fd, Mode::from_bits_truncate(NEW_FILE_MODE))?;
+            file.write_all(contents).map_err(errno)?;
+            file.sync_all().map_err(errno)
+        }
+    }
+}
+
+/// Function to open a file to edit, and make the user's copy of it
+///
+/// The file is opened as the `target`, and the copy is made as the `user`.
+fn prepare(path: &str, user: &Ids, target: &Ids) -> Result<Target, EditError> {
+    let syscall = |path: &str, en| EditError::Syscall {
+        path: path.to_string(),
+        errno: en,
+    };
+    let (dir, name, mut file) = target
+        .act(|| open(path, user))
+        .map_err(|en| syscall(path, en))??;
+    let mut contents = Vec::new();
+    if let Some(f) = &mut file {
+        f.read_to_end(&mut contents)
+            .map_err(|e| syscall(path, errno(e)))?;
+    }
+    let temp = user
+        .act(|| make_temp(path, &contents))
+        .and_then(|r| r)
+        .map_err(|en| syscall(config::SUDOEDIT_TMPDIR, en))?;
+    Ok(Target {
+        path: path.to_string(),
+        dir,
+        name,
+        file,
+        contents,
+        temp,
+    })
+}
+
+/// Function to find the user's editor
+///
+/// Like `sudo`, it's taken from `SUDO_EDITOR`, `VISUAL`, or `EDITOR` in the
+/// user's environment, in that order. It's split on whitespace so that it can
+/// have arguments. If none of them are set, [config::DEFAULT_EDITOR] is used.
+fn editor(env: &[CString]) -> Vec<CString> {
+    let var = |name: &str| {
+        env.iter().find_map(|e| {
+            let e = e.to_str().ok()?;
+            e.strip_prefix(name)?.strip_prefix('=')
+        })
+    };
+    let editor = ["SUDO_EDITOR", "VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|v| var(v))
+        .find(|e| !e.trim().is_empty())
+        .unwrap_or(config::DEFAULT_EDITOR);
+    editor
+        .split_whitespace()
+        .filter_map(|w| CString::new(w).ok())
+        .collect()
+}
+
+/// Function to run the user's editor on the copies, and wait for it
+///
+/// The editor runs as the user, with their environment, and looked up in
+/// their `PATH`. Its state is cleaned up first with [sanitize], like for any
+/// [Executable], but the rest of what the [Options] ask for isn't done, since
+/// nothing here runs with privileges.
+///
+/// While it runs, this process ignores `SIGINT` and `SIGQUIT` from the
+/// terminal, so the changes aren't lost. It returns how the editor ended.
+fn run_editor(user: &Ids, opts: &Options, temps: &[&Path]) -> Result<WaitStatus, RunError> {
+    let env = sanitize::saved_env();
+    let mut args = editor(&env);
+    args.extend(
+        temps
+            .iter()
+            .filter_map(|t| CString::new(t.as_os_str().as_bytes()).ok()),
+    );
+    let path = env
+        .iter()
+        .find_map(|e| e.as_bytes().strip_prefix(b"PATH="))
+        .map(|p| OsStr::from_bytes(p).to_os_string());
+
+    set_handlers(&[Signal::SIGINT, Signal::SIGQUIT], SigHandler::SigIgn)
+        .map_err(|en| RunError::Signal { errno: en })?;
+    let child = match unsafe { unistd::fork() }.map_err(|en| RunError::Fork { errno: en })? {
+        ForkResult::Parent { child } => child,
+        ForkResult::Child => {
+            let become_user = || -> Result<(), Errno> {
+                sanitize(opts)?;
+                unistd::setgroups(&user.groups)?;
+                unistd::setgid(user.gid)?;
+                unistd::setuid(user.uid)
+            };
+            if become_user().is_ok() {
+                // Only this process looks at its own PATH
+                if let Some(p) = path {
+                    std::env::set_var("PATH", p);
+                }
+                let _ = unistd::execvpe(&args[0], &args, &env);
+            }
+            // Exit the way shells do when a command can't be run
+            unsafe { libc::_exit(127) }
+        }
+    };
+
+    // Wait for it to finish
+    loop {
+        match wait::waitpid(child, Some(WaitPidFlag::WUNTRACED)) {
+            Ok(ws @ WaitStatus::Exited(_, _)) | Ok(ws @ WaitStatus::Signaled(_, _, _)) => {
+                return Ok(ws)
+            }
+            // Stop with the editor, and continue it when we're continued
+            Ok(WaitStatus::Stopped(_, _)) => {
+                let _ = signal::raise(Signal::SIGSTOP);
+                let _ = signal::kill(child, Signal::SIGCONT);
+            }
+            Ok(_) | Err(Errno::EINTR) => (),
+            Err(en) => return Err(RunError::Wait { errno: en }),
+        }
+    }
+}
+
+/// Function that edits the files given as the arguments of the [Executable]
+///
+/// Each of them is opened as the target in the [Permission]s given, copied
+/// for the user, and edited with the user's editor. What changed is written
+/// back once the editor exits. See the [module documentation][self].
+///
+/// If any file can't be opened, or the user's copies can't be made, nothing is
+/// edited. If a file can't be written back, its copy is left behind so the
+/// changes aren't lost. Problems with the files are printed, since they're
+/// what the user needs to fix. This exits with the editor's status, or with
+/// `1` if anything couldn't be written back. It only returns if the editor
+/// couldn't be run at all.
+pub fn edit(perm: &Permission, execable: &Executable, opts: &Options) -> RunResult {
+    if execable.args.len() < 2 {
+        return Err(RunError::Edit {
+            errno: Errno::EINVAL,
+        });
+    }
+    privilege::regain_root().map_err(|en| RunError::Privilege { errno: en })?;
+    let user = Ids::user().map_err(|en| RunError::Edit { errno: en })?;
+    let target = Ids::target(perm);
+
+    let remove_temps = |targets: &[Target]| {
+        let _ = user.act(|| {
+            for t in targets {
+                let _ = fs::remove_file(&t.temp);
+            }
+        });
+    };
+
+    // Open everything, and copy it for the user
+    // Don't leave copies behind if anything can't be
+    let mut targets = Vec::new();
+    for path in execable.args.iter().skip(1) {
+        match prepare(&path.to_string_lossy(), &user, &target) {
+            Ok(t) => targets.push(t),
+            Err(e) => {
+                remove_temps(&targets);
+                eprintln!("sus-kernel: {}", e);
+                return Err(RunError::Edit { errno: e.errno() });
+            }
+        }
+    }
+
+    // Edit
+    let temps: Vec<&Path> = targets.iter().map(|t| t.temp.as_path()).collect();
+    let status = match run_editor(&user, opts, &temps) {
+        Ok(s) => s,
+        Err(e) => {
+            remove_temps(&targets);
+            return Err(e);
+        }
+    };
+    let mut code = match status {
+        WaitStatus::Exited(_, c) => c,
+        _ => {
+            eprintln!("sus-kernel: the editor was killed, so nothing was changed");
+            remove_temps(&targets);
+            std::process::exit(1);
+        }
+    };
+
+    // Write back whatever changed
+    for t in &targets {
+        let contents = match user.act(|| read_temp(&t.temp)).and_then(|r| r) {
+            Ok(c) => c,
+            Err(en) => {
+                eprintln!(
+                    "sus-kernel: {}: {}, so {} wasn't changed",
+                    t.temp.display(),
+                    en.desc(),
+                    t.path
+                );
+                code = 1;
+                continue;
+            }
+        };
+        if contents == t.contents {
+            eprintln!("sus-kernel: {} unchanged", t.path);
+        } else if let Err(en) = target.act(|| write_back(t, &contents)).and_then(|r| r) {
+            eprintln!(
+                "sus-kernel: {}: {}, so the changes were left in {}",
+                t.path,
+                en.desc(),
+                t.temp.display()
+            );
+            code = 1;
+            continue;
+        }
+        let _ = user.act(|| fs::remove_file(&t.temp));
+    }
+    std::process::exit(code)
+}
+
+/// Error for opening a file to edit
+///
+/// These are printed for the user, since they're usually something about the
+/// file that they can fix.
+#[derive(Debug)]
+pub enum EditError {
+    /// A directory on the way to the file can be changed by the user
+    WritableDir { path: String },
+    /// The file, or a directory on the way to it, is a symbolic link
+    Symlink { path: String },
+    /// The file isn't a regular file
+    NotRegular { path: String },
+    /// A system call failed
+    Syscall { path: String, errno: Errno },
+}
+
+impl EditError {
+    /// Function to get the [Errno] closest to the error
+    fn errno(&self) -> Errno {
+        match self {
+            EditError::WritableDir { .. } => Errno::EPERM,
+            EditError::Symlink { .. } => Errno::ELOOP,
+            EditError::NotRegular { .. } => Errno::EINVAL,
+            EditError::Syscall { errno, .. } => *errno,
+        }
+    }
+}
+
+impl Display for EditError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            EditError::WritableDir { path } => {
+                write!(
+                    f,
+                    "{}: editing files in a writable directory isn't allowed",
+                    path
+                )?;
+            }
+            EditError::Symlink { path } => {
+                write!(f, "{}: editing symbolic links isn't allowed", path)?;
+            }
+            EditError::NotRegular { path } => {
+                write!(f, "{}: not a regular file", path)?;
+            }
+            EditError::Syscall { path, errno } => {
+                write!(f, "{}: {}", path, errno.desc())?;
+            }
+        };
+        Ok(())
+    }
+}
+
+impl Error for EditError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
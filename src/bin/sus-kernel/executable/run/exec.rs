@@ -4,19 +4,124 @@
 //! way to do that. It will either change to the given [Executable], or it will
 //! fail to do so and return to this application.
 
+use super::landlock;
+use super::landlock::Ruleset;
+use super::limits;
+use super::namespace;
+use super::noexec;
+use super::sanitize::sanitize;
 use super::Executable;
 use super::RunError;
 use super::RunResult;
+#[cfg(feature = "log")]
+use crate::config;
+use crate::permission::capability;
+use crate::permission::privilege;
+use crate::permission::verify::Options;
 use crate::permission::Permission;
 
+use nix::errno::Errno;
 use nix::unistd;
 use std::ffi::CString;
 
 /// Function that calls `execve` to run the [Executable] given
 ///
-/// It will set the permissions to those given in the first parameter, then
-/// execute the new binary. It only returns if any of those steps failed.
-pub fn exec(perm: &Permission, execable: &Executable) -> RunResult {
+/// It will switch back to `root`, clean up the state inherited from the user
+/// with [sanitize], set the permissions to those given in the first parameter,
+/// then execute the new binary. It only returns if any of those steps failed.
+///
+/// The resource limits and priority the [Options] ask for are set next, with
+/// [limits::apply]. If the [Options] set `runchroot`, the root directory is
+/// changed before the permissions are, since only `root` can do that. The
+/// working directory is changed to `runcwd` after, so that the new user has to
+/// be able to get to it. If `runcwd` is `*` or unset, the working directory is
+/// kept, unless the root was changed, in which case it's `/`.
+///
+/// If the [Options] set `namespaces`, they're entered once the root directory
+/// is changed, so the mounts are made inside it. See [namespace::enter].
+///
+/// If the [Permission]s limit the [capabilities][cp], the securebits are locked
+/// before the switch, and everything but those capabilities is dropped after.
+/// See [capability::restrict].
+///
+/// If the [Options] set `landlock`, the [Ruleset] is built as `root` once the
+/// root directory is changed, and enforced once the permissions are. If the
+/// kernel doesn't have Landlock, the [Executable] is only run if
+/// `landlock_required` is turned off. Either way, what was applied is logged.
+///
+/// If the [Options] set `noexec`, the [Executable] isn't allowed to run
+/// anything else once it starts. See [noexec].
+///
+/// [cp]: Permission::capabilities
+/// [noexec]: super::noexec
+pub fn exec(perm: &Permission, execable: &Executable, opts: &Options) -> RunResult {
+    // Get root back to make the switch
+    // Fail out on error
+    privilege::regain_root().map_err(|en| RunError::Privilege { errno: en })?;
+
+    // Clean up what the user gave us
+    // Fail out on error
+    sanitize(opts).map_err(|en| RunError::Sanitize { errno: en })?;
+
+    // Set the limits and priority
+    // This has to be done before changing the root, so the configuration can
+    //  be read
+    limits::apply(perm, opts).map_err(|en| RunError::Limits { errno: en })?;
+
+    // Start what stops it from running anything else, if asked
+    // This has to be done as root, before anything else changes
+    let gate = noexec::prepare(opts).map_err(|en| RunError::Noexec { errno: en })?;
+
+    // Change the root
+    // A relative path would be relative to wherever the user ran us from
+    if let Some(root) = opts.runchroot() {
+        if !root.starts_with('/') {
+            return Err(RunError::Chroot {
+                errno: Errno::EINVAL,
+            });
+        }
+        unistd::chroot(root).map_err(|en| RunError::Chroot { errno: en })?;
+        unistd::chdir("/").map_err(|en| RunError::Chdir { errno: en })?;
+    }
+
+    // Move into new namespaces
+    // This might fork, leaving the parent behind as init
+    namespace::enter(opts).map_err(|en| RunError::Namespace { errno: en })?;
+
+    // Build the Landlock ruleset
+    // This has to be done as root, so every path can be opened
+    let ruleset = match opts.landlock().map(Ruleset::create) {
+        Some(Ok(r)) => Some(r),
+        Some(Err(en)) if landlock::unsupported(en) && !opts.landlock_required() => None,
+        Some(Err(en)) => return Err(RunError::Landlock { errno: en }),
+        None => None,
+    };
+
+    // Log what confines it, if anything
+    #[cfg(feature = "log")]
+    {
+        let mut sandbox: Vec<String> = Vec::new();
+        if let Some(ns) = opts.namespaces() {
+            sandbox.push(ns.to_string());
+        }
+        if opts.landlock().is_some() {
+            sandbox.push(match &ruleset {
+                Some(r) => r.to_string(),
+                None => "no Landlock, since the kernel doesn't support it".to_string(),
+            });
+        }
+        if !sandbox.is_empty() {
+            (config::SANDBOX_LOGGER)(execable, perm, &sandbox.join("; "))
+                .map_err(|e| RunError::Sandbox { cause: e })?;
+        }
+    }
+
+    // Keep the capabilities through the switch, if they're limited
+    // This has to be done as root
+    if perm.capabilities.is_some() {
+        capability::lock().map_err(|en| RunError::Capabilities { errno: en })?;
+    }
+
     // Set the secondary groups
     // First, ensure that the primary group is part of the list of secondary
     //  groups. It is not guaranteed to be.
@@ -28,7 +133,7 @@ pub fn exec(perm: &Permission, execable: &Executable) -> RunResult {
         new_sgid_set.insert(perm.primary_gid);
 
         // Convert to a vector and sort it
-        let mut new_sgid_vec = Vec::from_iter(new_sgid_set.into_iter());
+        let mut new_sgid_vec = Vec::from_iter(new_sgid_set);
         new_sgid_vec.sort_by_key(|g| g.as_raw());
 
         // Do the call
@@ -44,6 +149,29 @@ pub fn exec(perm: &Permission, execable: &Executable) -> RunResult {
     // Fail out on error
     unistd::setuid(perm.uid).map_err(|en| RunError::SetUID { errno: en })?;
 
+    // Drop all but the capabilities asked for
+    // Fail out on error
+    if let Some(caps) = &perm.capabilities {
+        capability::restrict(caps).map_err(|en| RunError::Capabilities { errno: en })?;
+    }
+
+    // Change directory
+    // Fail out on error
+    if let Some(dir) = opts.runcwd().filter(|d| *d != "*") {
+        unistd::chdir(dir).map_err(|en| RunError::Chdir { errno: en })?;
+    }
+
+    // Limit what files it can get to, if asked
+    if let Some(r) = ruleset {
+        r.enforce().map_err(|en| RunError::Landlock { errno: en })?;
+    }
+
+    // Don't let it run anything else, if asked
+    // This has to be the last thing before executing
+    if let Some(g) = gate {
+        g.install().map_err(|en| RunError::Noexec { errno: en })?;
+    }
+
     // Execute
     unistd::execve::<CString, CString>(&execable.path, &execable.args, &[])
         .map_err(|en| RunError::Execute { errno: en })
@@ -0,0 +1,680 @@
+//! Module for checking everything an [Executable] runs
+//!
+//! Allowing a script is the same as allowing everything the script runs. With
+//! `intercept`, the policy can ask for each program the [Executable] runs to be
+//! checked against the policy as well, with the same [Verifier]s the request
+//! was. Anything not allowed fails to run with `EACCES`. With `log_subcmds`,
+//! each of them is logged. Anything denied is always logged.
+//!
+//! This is done by a tracer, which is a process of its own that follows the
+//! [Executable] and everything it starts with `ptrace`. The runner starts it
+//! with [prepare] as `root`, before it forks. The tracer creates the
+//! [Verifier]s once, then waits to be told who to trace with
+//! [Channel::serve]. The child waits until it's traced, then
+//! [installs][Channel::install] a `seccomp` filter that stops it at every
+//! `execve` and `execveat`, and becomes the [Executable].
+//!
+//! Each call is checked twice. When it's made, what it's trying to run is read
+//! out of the caller's memory and checked against the policy. Anything denied
+//! fails right there. Another thread could change that memory before the call
+//! reads it, though. So, once the call has replaced the caller, and before the
+//! new program has run anything, the tracer makes sure the file that's running
+//! is the one that was checked, with the arguments that were checked. If it
+//! isn't, the process is killed.
+//!
+//! Scripts are run by the interpreter on their `#!` line. For those, the
+//! interpreter has to be the one the script named, and it has to have been
+//! given the script and its arguments. Anything that runs through some other
+//! kind of interpreter, like `binfmt_misc`, is killed.
+
+use super::sanitize::detach;
+use super::Executable;
+use super::RunError;
+use crate::config;
+use crate::permission::privilege;
+use crate::permission::seccomp;
+use crate::permission::seccomp::Action;
+use crate::permission::verify::helper::message::{recv, send};
+use crate::permission::verify::{Options, Verifier, VerifyError, VerifyResult};
+use crate::permission::Permission;
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::ptrace;
+use nix::sys::socket;
+use nix::sys::socket::{AddressFamily, SockFlag, SockType};
+use nix::unistd;
+use nix::unistd::{ForkResult, Pid};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CString, OsStr};
+use std::fs;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+
+/// The longest path that's read out of the [Executable]'s memory
+const MAX_PATH: usize = libc::PATH_MAX as usize;
+/// The longest argument that's read out of the [Executable]'s memory
+const MAX_ARG: usize = 128 * 1024;
+/// The most arguments that are read out of the [Executable]'s memory
+const MAX_ARGS: usize = 4096;
+/// How much of a file the kernel reads to find a `#!` line
+const MAX_SHEBANG: usize = 256;
+
+// What libc doesn't have from `linux/ptrace.h`
+const PTRACE_GET_SYSCALL_INFO: libc::c_uint = 0x420e;
+const PTRACE_SYSCALL_INFO_SECCOMP: u8 = 3;
+
+/// A call a traced process is stopped at, from `struct ptrace_syscall_info`
+#[repr(C)]
+#[derive(Debug, Default)]
+struct SyscallInfo {
+    op: u8,
+    pad: [u8; 3],
+    arch: u32,
+    instruction_pointer: u64,
+    stack_pointer: u64,
+    nr: u64,
+    args: [u64; 6],
+    ret_data: u32,
+}
+
+/// The socket between the runner, its child, and the tracer
+#[derive(Debug)]
+pub struct Channel {
+    sock: RawFd,
+}
+
+/// Function for a runner to start the tracer, if the [Options] ask for
+/// `intercept` or `log_subcmds`
+///
+/// This has to be called as `root`, before the runner forks. It has to be
+/// called before the runner makes a `pid` namespace too, so the tracer isn't
+/// in it.
+pub fn prepare(
+    perm: &Permission,
+    execable: &Executable,
+    opts: &Options,
+) -> Result<Option<Channel>, RunError> {
+    if !opts.intercept() && !opts.log_subcmds() {
+        return Ok(None);
+    }
+    let (ours, theirs) = socket::socketpair(
+        AddressFamily::Unix,
+        SockType::SeqPacket,
+        None,
+        SockFlag::SOCK_CLOEXEC,
+    )
+    .map_err(|en| RunError::Intercept { errno: en })?;
+    match unsafe { unistd::fork() } {
+        Ok(ForkResult::Parent { .. }) => {
+            let _ = unistd::close(theirs);
+            Ok(Some(Channel { sock: ours }))
+        }
+        Ok(ForkResult::Child) => trace(theirs, perm, execable, opts),
+        Err(en) => {
+            let _ = unistd::close(ours);
+            let _ = unistd::close(theirs);
+            Err(RunError::Intercept { errno: en })
+        }
+    }
+}
+
+impl Channel {
+    /// Function for the child to wait until it's traced, then install the
+    /// filter
+    ///
+    /// This has to be done as `root`, since the filter doesn't set
+    /// `no_new_privs`. See [seccomp::install_as_root]. If the tracer couldn't
+    /// trace the child, this fails with [Errno::EPERM].
+    pub fn install(&self) -> Result<(), Errno> {
+        let traced = recv::<bool>(self.sock).map(|(t, _)| t).unwrap_or(false);
+        let _ = unistd::close(self.sock);
+        if !traced {
+            return Err(Errno::EPERM);
+        }
+        seccomp::install_as_root(
+            &[],
+            &[
+                (libc::SYS_execve, Action::Trace),
+                (libc::SYS_execveat, Action::Trace),
+            ],
+            Action::Allow,
+        )
+    }
+
+    /// Function for the runner to tell the tracer who the child is
+    ///
+    /// The tracer keeps going until the child and everything it started have
+    /// exited. If it never hears from the runner, it just exits.
+    pub fn serve(self, child: Pid) {
+        let _ = send(self.sock, &child.as_raw(), None);
+        let _ = unistd::close(self.sock);
+    }
+}
+
+/// Function to run the tracer itself
+///
+/// The user's current [Permission]s and the [Verifier]s are made once, up
+/// front. Then, it becomes `root` in every ID, so the user can't kill it, and
+/// gives up everything but `sock`, with [detach]. It then waits to hear who to
+/// trace. This never returns.
+///
+/// Everything it traces is killed if it exits, so nothing can get past a check
+/// that was cut short.
+fn trace(sock: RawFd, perm: &Permission, execable: &Executable, opts: &Options) -> ! {
+    // This needs the user's Real UID, so it has to come before the switch
+    let cur = (config::CURRENT_PERMISSION_FACTORY)().ok();
+    if privilege::become_root().is_err() {
+        unsafe { libc::_exit(1) }
+    }
+    detach(sock);
+    let mut tracer = Tracer {
+        perm,
+        execable,
+        opts,
+        cur,
+        verifiers: (config::VERIFIERS_FACTORY)(),
+        pending: HashMap::new(),
+        first: true,
+    };
+
+    let child = match recv::<libc::pid_t>(sock) {
+        Ok((pid, _)) => Pid::from_raw(pid),
+        Err(_) => unsafe { libc::_exit(1) },
+    };
+    let options = ptrace::Options::PTRACE_O_TRACESECCOMP
+        | ptrace::Options::PTRACE_O_TRACEEXEC
+        | ptrace::Options::PTRACE_O_TRACEFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORK
+        | ptrace::Options::PTRACE_O_TRACECLONE
+        | ptrace::Options::PTRACE_O_EXITKILL;
+    let traced = ptrace::seize(child, options).is_ok();
+    let _ = send(sock, &traced, None);
+    let _ = unistd::close(sock);
+    if traced {
+        tracer.follow(child);
+    }
+    drop(tracer);
+    unsafe { libc::_exit(0) }
+}
+
+/// What the tracer keeps track of
+struct Tracer<'a> {
+    perm: &'a Permission,
+    execable: &'a Executable,
+    opts: &'a Options,
+    /// The user's current [Permission]s, if they could be found
+    cur: Option<Permission>,
+    verifiers: Vec<Box<Verifier>>,
+    /// What each thread that's making a call was allowed to run
+    ///
+    /// [None] means it wasn't checked, so it isn't verified either.
+    pending: HashMap<Pid, Option<Expected>>,
+    /// Whether the child has yet to become the [Executable]
+    first: bool,
+}
+
+/// What should be running once an allowed call is done
+#[derive(Debug)]
+struct Expected {
+    /// What was checked, for the log
+    sub: Executable,
+    /// The device and inode of the file that should be running
+    file: (u64, u64),
+    /// The arguments it should have
+    args: Vec<Vec<u8>>,
+    /// Whether it's an interpreter running a script, in which case `args` only
+    /// has to be at the end of what it has
+    script: bool,
+}
+
+impl Tracer<'_> {
+    /// Function to follow `child` and everything it starts, until they've all
+    /// exited
+    ///
+    /// Processes the child starts are traced as soon as they're created. They
+    /// stop once before they run anything, so each is known by then.
+    fn follow(&mut self, child: Pid) {
+        let mut traced = HashSet::new();
+        traced.insert(child);
+        while !traced.is_empty() {
+            // nix can't describe stops for real-time signals, so this waits
+            //  itself
+            let mut status = 0;
+            let pid = unsafe { libc::waitpid(-1, &mut status, libc::__WALL) };
+            if pid < 0 {
+                match Errno::last() {
+                    Errno::EINTR => continue,
+                    _ => break,
+                }
+            }
+            let pid = Pid::from_raw(pid);
+            if libc::WIFEXITED(status) || libc::WIFSIGNALED(status) {
+                traced.remove(&pid);
+                self.pending.remove(&pid);
+                continue;
+            }
+            if !libc::WIFSTOPPED(status) {
+                continue;
+            }
+            traced.insert(pid);
+
+            let sig = libc::WSTOPSIG(status);
+            let deliver = match status >> 16 {
+                // A signal is about to be delivered, so let it be
+                0 => sig,
+                libc::PTRACE_EVENT_SECCOMP => {
+                    self.called(pid);
+                    0
+                }
+                libc::PTRACE_EVENT_EXEC => {
+                    if let Ok(former) = ptrace::getevent(pid) {
+                        let former = Pid::from_raw(former as libc::pid_t);
+                        if former != pid {
+                            traced.remove(&former);
+                        }
+                        self.executed(pid, former);
+                    } else {
+                        self.executed(pid, pid);
+                    }
+                    0
+                }
+                // The process was suspended, so keep it that way until it's
+                //  continued
+                libc::PTRACE_EVENT_STOP
+                    if matches!(
+                        sig,
+                        libc::SIGSTOP | libc::SIGTSTP | libc::SIGTTIN | libc::SIGTTOU
+                    ) =>
+                {
+                    unsafe {
+                        libc::ptrace(libc::PTRACE_LISTEN as _, pid.as_raw(), 0, 0);
+                    }
+                    continue;
+                }
+                _ => 0,
+            };
+            unsafe {
+                libc::ptrace(libc::PTRACE_CONT as _, pid.as_raw(), 0, deliver);
+            }
+        }
+    }
+
+    /// Function to decide on a call to `execve` or `execveat`
+    ///
+    /// The first call is the child becoming the [Executable], which was
+    /// already allowed, so it's let through without being checked. Anything
+    /// denied fails without being made. If that can't be done, the caller is
+    /// killed instead.
+    fn called(&mut self, pid: Pid) {
+        if self.first {
+            self.first = false;
+            self.pending.insert(pid, None);
+            return;
+        }
+        match self.decide(pid) {
+            Ok(expected) => {
+                self.pending.insert(pid, expected);
+            }
+            Err(en) => {
+                self.pending.remove(&pid);
+                if fail(pid, en).is_err() {
+                    unsafe {
+                        libc::kill(pid.as_raw(), libc::SIGKILL);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Function to decide on a call, and log it
+    ///
+    /// With `intercept`, the call is only allowed if the [Verifier]s allow
+    /// what it runs. If that can't be read, it's denied. Otherwise, everything
+    /// is allowed. It's logged if the [Options] set `log_subcmds`, or if it was
+    /// denied. Denied calls fail with [Errno::EACCES].
+    ///
+    /// Shells try every directory in `PATH` until one works. Calls for paths
+    /// that don't exist fail with [Errno::ENOENT] here, without being checked
+    /// or logged.
+    fn decide(&mut self, pid: Pid) -> Result<Option<Expected>, Errno> {
+        let expected = match called_with(pid) {
+            Some(Ok(e)) => e,
+            Some(Err(en)) => return Err(en),
+            None if self.opts.intercept() => return Err(Errno::EACCES),
+            None => return Ok(None),
+        };
+        let allowed = !self.opts.intercept() || self.check(&expected.sub);
+        self.log(&expected.sub, allowed);
+        if allowed {
+            Ok(Some(expected))
+        } else {
+            Err(Errno::EACCES)
+        }
+    }
+
+    /// Function to verify what a call ran, before it runs anything
+    ///
+    /// `former` is the thread that made the call. If it was let through
+    /// without being checked, so is this. Otherwise, if what's running isn't
+    /// what was checked, it's killed and logged as denied.
+    fn executed(&mut self, pid: Pid, former: Pid) {
+        let expected = match self.pending.remove(&former) {
+            Some(Some(e)) => e,
+            Some(None) => return,
+            // The call didn't stop here first, like the child's own when
+            //  `noexec` decided on it
+            None if self.first => {
+                self.first = false;
+                return;
+            }
+            None => {
+                unsafe {
+                    libc::kill(pid.as_raw(), libc::SIGKILL);
+                }
+                return;
+            }
+        };
+        if expected.matches(pid) {
+            return;
+        }
+        unsafe {
+            libc::kill(pid.as_raw(), libc::SIGKILL);
+        }
+        let sub = running(pid).unwrap_or(expected.sub);
+        self.log(&sub, false);
+    }
+
+    /// Function to check something the [Executable] runs against the policy
+    ///
+    /// This is done just like for the request, with the user's current
+    /// [Permission]s, and the same target.
+    fn check(&mut self, sub: &Executable) -> bool {
+        let cur = match &self.cur {
+            Some(c) => c,
+            None => return false,
+        };
+        let mut res: VerifyResult = Err(VerifyError::NotAllowed { err: None });
+        for v in self.verifiers.iter_mut() {
+            res = res.or_else(|_| v(cur, self.perm, sub));
+        }
+        res.is_ok()
+    }
+
+    /// Function to log something the [Executable] runs, if it should be
+    fn log(&self, sub: &Executable, allowed: bool) {
+        #[cfg(feature = "log")]
+        if self.opts.log_subcmds() || !allowed {
+            let _ = (config::SUBCOMMAND_LOGGER)(self.execable, self.perm, sub, allowed);
+        }
+        #[cfg(not(feature = "log"))]
+        let _ = (self.execable, sub, allowed);
+    }
+}
+
+impl Expected {
+    /// Function to see if what `pid` is running is what was expected
+    fn matches(&self, pid: Pid) -> bool {
+        let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+        let file = match fs::metadata(proc_dir.join("exe")) {
+            Ok(m) => (m.dev(), m.ino()),
+            Err(_) => return false,
+        };
+        let args = match fs::read(proc_dir.join("cmdline")) {
+            Ok(c) => split_args(&c),
+            Err(_) => return false,
+        };
+        file == self.file
+            && if self.script {
+                args.ends_with(&self.args)
+            } else {
+                args == self.args
+            }
+    }
+}
+
+/// Function to read what a call to `execve` or `execveat` is trying to run
+///
+/// Relative paths are resolved against the caller's working directory, or the
+/// directory `execveat` was given. This gives [None] if any of that can't be
+/// read, and [Errno::ENOENT] if there's nothing there to run.
+fn called_with(pid: Pid) -> Option<Result<Expected, Errno>> {
+    let mut info = SyscallInfo::default();
+    let res = unsafe {
+        libc::ptrace(
+            PTRACE_GET_SYSCALL_INFO as _,
+            pid.as_raw(),
+            std::mem::size_of::<SyscallInfo>(),
+            &mut info as *mut SyscallInfo,
+        )
+    };
+    if res <= 0 || info.op != PTRACE_SYSCALL_INFO_SECCOMP {
+        return None;
+    }
+    let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+    let mem = File::open(proc_dir.join("mem")).ok()?;
+    let args = &info.args;
+    let (dirfd, path, argv) = if info.nr as libc::c_long == libc::SYS_execveat {
+        (args[0] as libc::c_int, args[1], args[2])
+    } else {
+        (libc::AT_FDCWD, args[0], args[1])
+    };
+
+    // Get the path, and the file it names, from where the caller is
+    let raw = read_string(&mem, path, MAX_PATH)?;
+    let dir = if dirfd == libc::AT_FDCWD {
+        proc_dir.join("cwd")
+    } else {
+        proc_dir.join("fd").join(dirfd.to_string())
+    };
+    let (path, file) = if raw.starts_with(b"/") {
+        let path = PathBuf::from(OsStr::from_bytes(&raw));
+        let file = proc_dir.join("root").join(path.strip_prefix("/").ok()?);
+        (path, file)
+    } else if raw.is_empty() {
+        (fs::read_link(&dir).ok()?, dir.clone())
+    } else {
+        let path = fs::read_link(&dir).ok()?.join(OsStr::from_bytes(&raw));
+        (path, dir.join(OsStr::from_bytes(&raw)))
+    };
+    let meta = match fs::metadata(&file) {
+        Ok(m) => m,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Some(Err(Errno::ENOENT)),
+        Err(_) => return None,
+    };
+
+    // Get the arguments
+    let mut args = Vec::new();
+    let width = std::mem::size_of::<usize>() as u64;
+    for i in 0..MAX_ARGS as u64 {
+        let mut ptr = [0u8; std::mem::size_of::<usize>()];
+        mem.read_exact_at(&mut ptr, argv.checked_add(i * width)?)
+            .ok()?;
+        let ptr = usize::from_ne_bytes(ptr) as u64;
+        if ptr == 0 {
+            break;
+        }
+        args.push(read_string(&mem, ptr, MAX_ARG)?);
+    }
+
+    let sub = Executable {
+        path: CString::new(path.as_os_str().as_bytes()).ok()?,
+        args: args
+            .iter()
+            .map(|a| CString::new(a.clone()).ok())
+            .collect::<Option<_>>()?,
+    };
+    let expected = match interpreter(&file) {
+        // The kernel gives the interpreter the script by the name it was
+        //  called with, then the rest of the arguments
+        Some(interp) => {
+            let interp = proc_dir
+                .join("root")
+                .join(interp.strip_prefix("/").unwrap_or(&interp));
+            let interp = fs::metadata(interp).ok()?;
+            let name = if raw.starts_with(b"/") || dirfd == libc::AT_FDCWD {
+                raw
+            } else if raw.is_empty() {
+                format!("/dev/fd/{}", dirfd).into_bytes()
+            } else {
+                let mut name = format!("/dev/fd/{}/", dirfd).into_bytes();
+                name.extend_from_slice(&raw);
+                name
+            };
+            let mut expected = vec![name];
+            expected.extend(args.into_iter().skip(1));
+            Expected {
+                sub,
+                file: (interp.dev(), interp.ino()),
+                args: expected,
+                script: true,
+            }
+        }
+        None => Expected {
+            sub,
+            file: (meta.dev(), meta.ino()),
+            args,
+            script: false,
+        },
+    };
+    Some(Ok(expected))
+}
+
+/// Function to find the interpreter on a script's `#!` line, if it is one
+fn interpreter(file: &Path) -> Option<PathBuf> {
+    let mut buf = [0u8; MAX_SHEBANG];
+    let mut f = File::open(file).ok()?;
+    let mut len = 0;
+    while len < buf.len() {
+        match f.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => return None,
+        }
+    }
+    let line = buf[..len].strip_prefix(b"#!")?;
+    let line = line.split(|b| *b == b'\n').next()?;
+    let interp = line
+        .split(|b| *b == b' ' || *b == b'\t')
+        .find(|w| !w.is_empty())?;
+    Some(PathBuf::from(OsStr::from_bytes(interp)))
+}
+
+/// Function to get what `pid` is running, for the log
+fn running(pid: Pid) -> Option<Executable> {
+    let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+    let path = fs::read_link(proc_dir.join("exe")).ok()?;
+    let args = split_args(&fs::read(proc_dir.join("cmdline")).ok()?);
+    Some(Executable {
+        path: CString::new(path.as_os_str().as_bytes()).ok()?,
+        args: args
+            .into_iter()
+            .map(|a| CString::new(a).ok())
+            .collect::<Option<_>>()?,
+    })
+}
+
+/// Function to split the contents of `/proc/<pid>/cmdline` into arguments
+fn split_args(cmdline: &[u8]) -> Vec<Vec<u8>> {
+    let cmdline = cmdline.strip_suffix(b"\0").unwrap_or(cmdline);
+    if cmdline.is_empty() {
+        return Vec::new();
+    }
+    cmdline.split(|b| *b == 0).map(|a| a.to_vec()).collect()
+}
+
+/// Function to make the call a traced process is stopped at fail with `en`,
+/// instead of being made
+///
+/// The call's number is set to `-1`, which the kernel skips, and its return
+/// value to the error.
+#[cfg(target_arch = "x86_64")]
+fn fail(pid: Pid, en: Errno) -> Result<(), Errno> {
+    let mut regs = ptrace::getregs(pid)?;
+    regs.orig_rax = u64::MAX;
+    regs.rax = -(en as i64) as u64;
+    ptrace::setregs(pid, regs)
+}
+
+/// Function to make the call a traced process is stopped at fail with `en`,
+/// instead of being made
+///
+/// The call's number is set to `-1`, which the kernel skips, and its return
+/// value to the error. Here, the number is in a register set of its own.
+#[cfg(target_arch = "aarch64")]
+fn fail(pid: Pid, en: Errno) -> Result<(), Errno> {
+    // From `linux/elf.h`, and the kernel's `struct user_pt_regs`
+    const NT_PRSTATUS: libc::c_int = 1;
+    const NT_ARM_SYSTEM_CALL: libc::c_int = 0x404;
+    #[repr(C)]
+    struct Regs {
+        regs: [u64; 31],
+        sp: u64,
+        pc: u64,
+        pstate: u64,
+    }
+    fn regset<T>(req: libc::c_uint, pid: Pid, set: libc::c_int, val: &mut T) -> Result<(), Errno> {
+        let mut iov = libc::iovec {
+            iov_base: val as *mut T as *mut libc::c_void,
+            iov_len: std::mem::size_of::<T>(),
+        };
+        Errno::result(unsafe {
+            libc::ptrace(req as _, pid.as_raw(), set, &mut iov as *mut libc::iovec)
+        })?;
+        Ok(())
+    }
+
+    regset(
+        libc::PTRACE_SETREGSET as _,
+        pid,
+        NT_ARM_SYSTEM_CALL,
+        &mut -1i32,
+    )?;
+    let mut regs = Regs {
+        regs: [0; 31],
+        sp: 0,
+        pc: 0,
+        pstate: 0,
+    };
+    regset(libc::PTRACE_GETREGSET as _, pid, NT_PRSTATUS, &mut regs)?;
+    regs.regs[0] = -(en as i64) as u64;
+    regset(libc::PTRACE_SETREGSET as _, pid, NT_PRSTATUS, &mut regs)
+}
+
+/// Function to make the call a traced process is stopped at fail
+///
+/// This doesn't know where the call's number is kept here, so the caller is
+/// killed instead.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn fail(_: Pid, _: Errno) -> Result<(), Errno> {
+    Err(Errno::ENOSYS)
+}
+
+/// Function to read a string out of another process's memory
+///
+/// It's read in pieces until a `NUL`, since it might end right before memory
+/// that can't be read. Strings longer than `max` give [None].
+fn read_string(mem: &File, addr: u64, max: usize) -> Option<Vec<u8>> {
+    let mut ret = Vec::new();
+    let mut buf = [0u8; 256];
+    while ret.len() < max {
+        let at = addr.checked_add(ret.len() as u64)?;
+        // Don't read past the end of the page
+        let len = buf.len().min(4096 - (at % 4096) as usize);
+        let n = mem.read_at(&mut buf[..len], at).ok()?;
+        if n == 0 {
+            return None;
+        }
+        if let Some(end) = buf[..n].iter().position(|b| *b == 0) {
+            ret.extend_from_slice(&buf[..end]);
+            return Some(ret);
+        }
+        ret.extend_from_slice(&buf[..n]);
+    }
+    None
+}
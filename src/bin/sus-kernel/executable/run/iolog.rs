@@ -0,0 +1,234 @@
+//! Module for recording the input and output of a session
+//!
+//! For auditing, it can be useful to record exactly what happened while a
+//! command ran. This module writes those recordings. It follows the layout
+//! `sudo` uses for its I/O logs, so that existing tools and habits carry over.
+//!
+//! Every session gets its own directory under [config::IOLOG_DIR], named after
+//! its ID. The ID is six base-36 digits taken from a counter in the `seq` file,
+//! and the directory splits it into three levels. For instance, session
+//! `00002A` is stored in `00/00/2A`. Each directory contains
+//!   * `log.json`: Information about the session, like who ran what
+//!   * `timing`: One line per event, giving the [Stream], the seconds since the
+//!     last event, and the number of bytes. Window size changes are recorded
+//!     with the event number [WINDOW_EVENT] and the new rows and columns
+//!     instead of the number of bytes.
+//!   * `stdin`, `stdout`, `stderr`, `ttyin`, `ttyout`: The data for each
+//!     [Stream], one after another
+//!
+//! [config::IOLOG_DIR]: crate::config::IOLOG_DIR
+
+use crate::config;
+
+use nix::fcntl::{flock, FlockArg};
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::Instant;
+
+/// The streams of data that can be recorded
+///
+/// When the command is on a terminal, what the user types and what they see
+/// go through the terminal, and are recorded as [TtyIn][ti] and [TtyOut][to].
+/// Otherwise, the standard streams are recorded separately.
+///
+/// [ti]: Stream::TtyIn
+/// [to]: Stream::TtyOut
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    Stdin = 0,
+    Stdout = 1,
+    Stderr = 2,
+    TtyIn = 3,
+    TtyOut = 4,
+}
+
+/// The event number for a window size change in the `timing` file
+pub const WINDOW_EVENT: u32 = 5;
+
+/// Every [Stream], in the order of their event numbers
+pub const STREAMS: [Stream; 5] = [
+    Stream::Stdin,
+    Stream::Stdout,
+    Stream::Stderr,
+    Stream::TtyIn,
+    Stream::TtyOut,
+];
+
+impl Stream {
+    /// The name of the file the [Stream] is recorded to
+    pub fn name(self) -> &'static str {
+        match self {
+            Stream::Stdin => "stdin",
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+            Stream::TtyIn => "ttyin",
+            Stream::TtyOut => "ttyout",
+        }
+    }
+
+    /// Whether the [Stream] is input from the user, as opposed to output from
+    /// the command
+    pub fn is_input(self) -> bool {
+        matches!(self, Stream::Stdin | Stream::TtyIn)
+    }
+}
+
+/// Structure representing a session being recorded
+pub struct Session {
+    /// The ID of the session
+    pub id: String,
+    /// The `timing` file
+    timing: File,
+    /// The file for each [Stream], indexed by its event number
+    ///
+    /// A [Stream] that isn't being recorded has [None].
+    files: Vec<Option<File>>,
+    /// When the last event happened
+    last: Instant,
+}
+
+impl Session {
+    /// Function to start recording a new session
+    ///
+    /// It allocates an ID, creates the directory for the session, and writes
+    /// `info` to `log.json`. Input is only recorded if `input` is set, and
+    /// output only if `output` is set.
+    pub fn create(input: bool, output: bool, info: &serde_json::Value) -> io::Result<Session> {
+        let base = Path::new(config::IOLOG_DIR);
+        make_dir(base)?;
+
+        // Create the directory
+        // The last level must be new. If the counter wrapped around, don't
+        //  overwrite an old session.
+        let id = next_id(base)?;
+        let first = base.join(&id[0..2]);
+        let second = first.join(&id[2..4]);
+        make_dir(&first)?;
+        make_dir(&second)?;
+        let dir = second.join(&id[4..6]);
+        DirBuilder::new()
+            .mode(config::IOLOG_DIR_MODE)
+            .create(&dir)?;
+
+        // Write the information
+        let mut log = create_file(&dir.join("log.json"))?;
+        serde_json::to_writer_pretty(&mut log, info)?;
+        log.write_all(b"\n")?;
+
+        // Create the files
+        let timing = create_file(&dir.join("timing"))?;
+        let mut files = Vec::new();
+        for s in STREAMS {
+            let file = create_file(&dir.join(s.name()))?;
+            let recorded = if s.is_input() { input } else { output };
+            files.push(if recorded { Some(file) } else { None });
+        }
+
+        Ok(Session {
+            id,
+            timing,
+            files,
+            last: Instant::now(),
+        })
+    }
+
+    /// Function to record `data` on the given [Stream]
+    ///
+    /// Nothing is written if the [Stream] isn't being recorded.
+    pub fn record(&mut self, stream: Stream, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        if let Some(f) = &mut self.files[stream as usize] {
+            f.write_all(data)?;
+            let delay = self.delay();
+            writeln!(self.timing, "{} {} {}", stream as u32, delay, data.len())?;
+        }
+        Ok(())
+    }
+
+    /// Function to record that the window changed size
+    pub fn window(&mut self, rows: u16, cols: u16) -> io::Result<()> {
+        let delay = self.delay();
+        writeln!(self.timing, "{} {} {} {}", WINDOW_EVENT, delay, rows, cols)
+    }
+
+    /// Function to get the time since the last event, formatted for the
+    /// `timing` file
+    fn delay(&mut self) -> String {
+        let now = Instant::now();
+        let d = now - self.last;
+        self.last = now;
+        format!("{}.{:0>9}", d.as_secs(), d.subsec_nanos())
+    }
+}
+
+/// Function to create a directory for recordings if it doesn't exist
+///
+/// Recordings can contain passwords, so new directories are given the mode
+/// [config::IOLOG_DIR_MODE].
+fn make_dir(dir: &Path) -> io::Result<()> {
+    match DirBuilder::new().mode(config::IOLOG_DIR_MODE).create(dir) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Function to create a new file in a recording
+fn create_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(config::IOLOG_FILE_MODE)
+        .open(path)
+}
+
+/// Function to allocate the next session ID
+///
+/// The counter is stored in base 36 in the `seq` file in `base`. It's locked
+/// while it's being updated, so concurrent sessions get different IDs.
+fn next_id(base: &Path) -> io::Result<String> {
+    let mut seq = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .mode(config::IOLOG_FILE_MODE)
+        .open(base.join("seq"))?;
+    flock(seq.as_raw_fd(), FlockArg::LockExclusive).map_err(io::Error::from)?;
+
+    // Read the last ID
+    // An empty file means no sessions yet
+    let mut contents = String::new();
+    seq.read_to_string(&mut contents)?;
+    let last = match contents.trim() {
+        "" => 0,
+        s => {
+            u64::from_str_radix(s, 36).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        }
+    };
+
+    // Write the next one
+    // Wrap around once we run out of digits, like sudo does
+    let next = (last + 1) % 36u64.pow(6);
+    let id = to_base36(next);
+    seq.seek(SeekFrom::Start(0))?;
+    seq.set_len(0)?;
+    writeln!(seq, "{}", id)?;
+    seq.sync_all()?;
+    Ok(id)
+}
+
+/// Function to format a number as six base-36 digits
+fn to_base36(mut n: u64) -> String {
+    const DIGITS: &[u8; 36] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    let mut ret = [b'0'; 6];
+    for c in ret.iter_mut().rev() {
+        *c = DIGITS[(n % 36) as usize];
+        n /= 36;
+    }
+    String::from_utf8_lossy(&ret).to_string()
+}
@@ -0,0 +1,218 @@
+//! Module for limiting what files an [Executable][eb] can get to with Landlock
+//!
+//! The policy can give a `landlock` option with paths the [Executable][eb] may
+//! `read` and paths it may `write`. Everything under a `read` path may be read
+//! and executed. Everything under a `write` path may also be written to,
+//! created, and removed. Nothing else on the filesystem may be touched, though
+//! files the [Executable][eb] already has open still work.
+//!
+//! The [Ruleset] is built as `root` before the switch to the new user, so the
+//! paths can be opened no matter who the [Executable][eb] runs as. It's then
+//! [enforced][Ruleset::enforce] after the switch, just before `execve`, and is
+//! kept by all of the [Executable][eb]'s children.
+//!
+//! Older kernels don't have Landlock. If `landlock_required` is set, which is
+//! the default, the [Executable][eb] isn't run on them.
+//!
+//! [eb]: crate::executable::Executable
+
+use crate::permission::verify::sudoers_type::Landlock;
+
+use nix::errno::Errno;
+use nix::fcntl;
+use nix::fcntl::OFlag;
+use nix::libc;
+use nix::sys::stat;
+use nix::sys::stat::{Mode, SFlag};
+use nix::unistd;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::os::unix::io::RawFd;
+
+// libc doesn't have these, so they're from `linux/landlock.h`
+const SYS_LANDLOCK_CREATE_RULESET: libc::c_long = 444;
+const SYS_LANDLOCK_ADD_RULE: libc::c_long = 445;
+const SYS_LANDLOCK_RESTRICT_SELF: libc::c_long = 446;
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+const ACCESS_FS_REFER: u64 = 1 << 13;
+const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+/// What a `read` path allows
+const ACCESS_READ: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+/// What can be allowed on something that isn't a directory
+const ACCESS_FILE: u64 =
+    ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE | ACCESS_FS_TRUNCATE;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// Function to get what the kernel can restrict for a version of Landlock
+///
+/// Version 1 has everything up to making symbolic links. Version 2 adds moving
+/// files between directories, and version 3 adds truncating them. Rights added
+/// later aren't restricted.
+fn handled(abi: i32) -> u64 {
+    let mut ret = ACCESS_READ
+        | ACCESS_FS_WRITE_FILE
+        | ACCESS_FS_REMOVE_DIR
+        | ACCESS_FS_REMOVE_FILE
+        | ACCESS_FS_MAKE_CHAR
+        | ACCESS_FS_MAKE_DIR
+        | ACCESS_FS_MAKE_REG
+        | ACCESS_FS_MAKE_SOCK
+        | ACCESS_FS_MAKE_FIFO
+        | ACCESS_FS_MAKE_BLOCK
+        | ACCESS_FS_MAKE_SYM;
+    if abi >= 2 {
+        ret |= ACCESS_FS_REFER;
+    }
+    if abi >= 3 {
+        ret |= ACCESS_FS_TRUNCATE;
+    }
+    ret
+}
+
+/// A Landlock ruleset, built but not yet enforced
+#[derive(Debug)]
+pub struct Ruleset {
+    fd: RawFd,
+    abi: i32,
+    landlock: Landlock,
+}
+
+impl Ruleset {
+    /// Function to build the [Ruleset] for a `landlock` option
+    ///
+    /// Every path in it has to exist. It fails with [Errno::ENOSYS] or
+    /// [Errno::EOPNOTSUPP] if the kernel doesn't have Landlock, or has it
+    /// turned off.
+    pub fn create(landlock: &Landlock) -> Result<Ruleset, Errno> {
+        // See what the kernel can do
+        let abi = Errno::result(unsafe {
+            libc::syscall(
+                SYS_LANDLOCK_CREATE_RULESET,
+                std::ptr::null::<RulesetAttr>(),
+                0,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        })? as i32;
+        let handled = handled(abi);
+
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        let fd = Errno::result(unsafe {
+            libc::syscall(
+                SYS_LANDLOCK_CREATE_RULESET,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0,
+            )
+        })? as RawFd;
+        // Close it if anything goes wrong from here
+        let ret = Ruleset {
+            fd,
+            abi,
+            landlock: landlock.clone(),
+        };
+
+        let rules = landlock
+            .read
+            .iter()
+            .map(|p| (p, ACCESS_READ))
+            .chain(landlock.write.iter().map(|p| (p, handled)));
+        for (path, access) in rules {
+            ret.allow(path, access & handled)?;
+        }
+        Ok(ret)
+    }
+
+    /// Function to allow `access` to everything under `path`
+    ///
+    /// Only some rights make sense for something that isn't a directory, so
+    /// the rest are dropped for those.
+    fn allow(&self, path: &str, access: u64) -> Result<(), Errno> {
+        let parent = fcntl::open(path, OFlag::O_PATH | OFlag::O_CLOEXEC, Mode::empty())?;
+        let res = stat::fstat(parent).and_then(|st| {
+            let is_dir = SFlag::from_bits_truncate(st.st_mode) & SFlag::S_IFMT == SFlag::S_IFDIR;
+            let attr = PathBeneathAttr {
+                allowed_access: if is_dir { access } else { access & ACCESS_FILE },
+                parent_fd: parent,
+            };
+            Errno::result(unsafe {
+                libc::syscall(
+                    SYS_LANDLOCK_ADD_RULE,
+                    self.fd,
+                    LANDLOCK_RULE_PATH_BENEATH,
+                    &attr as *const PathBeneathAttr,
+                    0,
+                )
+            })
+        });
+        let _ = unistd::close(parent);
+        res.map(drop)
+    }
+
+    /// Function to start enforcing the [Ruleset] on this process
+    ///
+    /// This sets `no_new_privs` first, since Landlock needs that of anyone
+    /// who isn't `root`. That also means the [Executable][eb] can't gain
+    /// privileges by running a Set-UID binary.
+    ///
+    /// [eb]: crate::executable::Executable
+    pub fn enforce(self) -> Result<(), Errno> {
+        Errno::result(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+        Errno::result(unsafe { libc::syscall(SYS_LANDLOCK_RESTRICT_SELF, self.fd, 0) })?;
+        Ok(())
+    }
+}
+
+impl Drop for Ruleset {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.fd);
+    }
+}
+
+impl Display for Ruleset {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let list = |paths: &[String]| {
+            paths
+                .iter()
+                .map(|p| format!("{:?}", p))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        write!(f, "Landlock ABI {}", self.abi)?;
+        write!(f, " reading [{}]", list(&self.landlock.read))?;
+        write!(f, " writing [{}]", list(&self.landlock.write))
+    }
+}
+
+/// Function to see if an error from [Ruleset::create] means the kernel doesn't
+/// have Landlock
+pub fn unsupported(en: Errno) -> bool {
+    en == Errno::ENOSYS || en == Errno::EOPNOTSUPP
+}
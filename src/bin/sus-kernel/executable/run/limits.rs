@@ -0,0 +1,287 @@
+//! Module for the resource limits and priority an [Executable] runs with
+//!
+//! By default, the [Executable] runs with the resource limits `init` has and
+//! the default priority, not those of whoever ran the kernel. See
+//! [sanitize][sn]. The policy can change that. It can set `rlimit`s, a `nice`
+//! value, and an `ionice` class. With `login_rlimits`, it can also start from
+//! the limits the target user would get by logging in.
+//!
+//! The login limits are the ones `init` runs with, changed by any entries for
+//! the target user in [config::LOGIN_LIMITS_PATHS]. Those are read the same way
+//! `pam_limits` would.
+//!
+//! [Executable]: crate::executable::Executable
+//! [sn]: super::sanitize::sanitize
+
+use crate::config;
+use crate::permission::verify::sudoers_type::{IoniceClass, RlimitResource};
+use crate::permission::verify::Options;
+use crate::permission::Permission;
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::resource;
+use nix::sys::resource::Resource;
+use nix::unistd::Gid;
+use std::fs;
+use std::path::Path;
+
+/// Function to get the [Resource] for an [RlimitResource]
+fn resource(r: RlimitResource) -> Resource {
+    match r {
+        RlimitResource::As => Resource::RLIMIT_AS,
+        RlimitResource::Core => Resource::RLIMIT_CORE,
+        RlimitResource::Cpu => Resource::RLIMIT_CPU,
+        RlimitResource::Data => Resource::RLIMIT_DATA,
+        RlimitResource::Fsize => Resource::RLIMIT_FSIZE,
+        RlimitResource::Locks => Resource::RLIMIT_LOCKS,
+        RlimitResource::Memlock => Resource::RLIMIT_MEMLOCK,
+        RlimitResource::Nofile => Resource::RLIMIT_NOFILE,
+        RlimitResource::Nproc => Resource::RLIMIT_NPROC,
+        RlimitResource::Rss => Resource::RLIMIT_RSS,
+        RlimitResource::Stack => Resource::RLIMIT_STACK,
+    }
+}
+
+/// Function to get what a value in `limits.conf` is measured in
+///
+/// Sizes are in kilobytes, and CPU time is in minutes. Everything else is a
+/// count.
+fn unit(r: RlimitResource) -> u64 {
+    match r {
+        RlimitResource::As
+        | RlimitResource::Core
+        | RlimitResource::Data
+        | RlimitResource::Fsize
+        | RlimitResource::Memlock
+        | RlimitResource::Rss
+        | RlimitResource::Stack => 1024,
+        RlimitResource::Cpu => 60,
+        _ => 1,
+    }
+}
+
+/// Function to set the limits and priority for the [Executable][eb]
+///
+/// This has to be done as `root`, since raising a hard limit or the priority
+/// needs it. It's done by the process that becomes the [Executable][eb], just
+/// before the switch to the new user.
+///
+/// [eb]: crate::executable::Executable
+pub fn apply(perm: &Permission, opts: &Options) -> Result<(), Errno> {
+    // Start from the login limits, if asked
+    if opts.login_rlimits() {
+        for (r, soft, hard) in login_limits(perm) {
+            resource::setrlimit(resource(r), soft, hard)?;
+        }
+    }
+    for l in opts.rlimits() {
+        resource::setrlimit(resource(l.resource), l.soft, l.hard)?;
+    }
+
+    // Priority
+    if let Some(n) = opts.nice() {
+        Errno::result(unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, n) })?;
+    }
+    if let Some(io) = opts.ionice() {
+        // libc doesn't have these, so they're from `linux/ioprio.h`
+        let class = match io.class {
+            IoniceClass::Realtime => 1,
+            IoniceClass::BestEffort => 2,
+            IoniceClass::Idle => 3,
+        };
+        let prio = (class << 13) | libc::c_int::from(io.level);
+        Errno::result(unsafe { libc::syscall(libc::SYS_ioprio_set, 1, 0, prio) })?;
+    }
+    Ok(())
+}
+
+/// Function to put the limits and priority back to what they start as
+///
+/// Every resource limit is set to what `init` has, and the nice value is set to
+/// `0`. This has to be done as `root`. If a hard limit can't be raised, say
+/// because `root` doesn't have `CAP_SYS_RESOURCE` in a container, it's kept
+/// where it is, and the soft limit is kept under it.
+pub fn reset() -> Result<(), Errno> {
+    for r in RlimitResource::ALL.iter() {
+        let (soft, hard) = init_limit(*r)?;
+        match resource::setrlimit(resource(*r), soft, hard) {
+            Err(Errno::EPERM) => {
+                let (_, max) = resource::getrlimit(resource(*r))?;
+                let under = |l: Limit| match (l, max) {
+                    (Some(l), Some(m)) => Some(l.min(m)),
+                    (l, None) => l,
+                    (None, m) => m,
+                };
+                resource::setrlimit(resource(*r), under(soft), under(hard))?;
+            }
+            res => res?,
+        }
+    }
+    Errno::result(unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 0) })?;
+    Ok(())
+}
+
+/// Limits, with `None` for no limit
+type Limit = Option<libc::rlim_t>;
+/// A [Limit], along with how specific the entry that set it was
+type Ranked = (Limit, u8);
+
+/// Function to get the limits the target user would get by logging in
+///
+/// Limits `init` has are used unless [config::LOGIN_LIMITS_PATHS] says
+/// otherwise. If those can't be read, the current ones are used instead. Like
+/// `pam_limits`, entries for the user win over entries for their groups, which
+/// win over `*`. Otherwise, later entries win.
+fn login_limits(perm: &Permission) -> Vec<(RlimitResource, Limit, Limit)> {
+    // What init has
+    // Keep how specific each entry is, with zero for what came from init
+    // Keep what we have if we can't see init's, which is what sanitize left
+    let mut ret: Vec<(RlimitResource, Ranked, Ranked)> = RlimitResource::ALL
+        .iter()
+        .map(|r| {
+            let (soft, hard) = init_limit(*r)
+                .or_else(|_| resource::getrlimit(resource(*r)))
+                .unwrap_or((None, None));
+            (*r, (soft, 0), (hard, 0))
+        })
+        .collect();
+
+    // Change it with the configuration
+    let user = users::get_user_by_uid(perm.uid.as_raw());
+    let name = user.as_ref().and_then(|u| u.name().to_str());
+    for file in config::LOGIN_LIMITS_PATHS
+        .iter()
+        .flat_map(|p| config_files(Path::new(p)))
+    {
+        for line in fs::read_to_string(file).unwrap_or_default().lines() {
+            let (rank, kind, r, limit) = match parse_line(line, perm, name) {
+                Some(e) => e,
+                None => continue,
+            };
+            let entry = ret.iter_mut().find(|e| e.0 == r).unwrap();
+            for (k, slot) in [("soft", &mut entry.1), ("hard", &mut entry.2)] {
+                if (kind == k || kind == "-") && rank >= slot.1 {
+                    *slot = (limit, rank);
+                }
+            }
+        }
+    }
+
+    ret.into_iter().map(|(r, s, h)| (r, s.0, h.0)).collect()
+}
+
+/// Function to get the soft and hard limits `init` has for a resource
+///
+/// These are what every process starts from, so they're what the
+/// [Executable][eb] gets unless the policy says otherwise. See
+/// [sanitize][sn]. They're asked for with `prlimit`, which needs
+/// `CAP_SYS_RESOURCE`. If that's not allowed, they're read from
+/// `/proc/1/limits` instead.
+///
+/// [eb]: crate::executable::Executable
+/// [sn]: super::sanitize::sanitize
+fn init_limit(r: RlimitResource) -> Result<(Limit, Limit), Errno> {
+    let mut l = libc::rlimit {
+        rlim_cur: libc::RLIM_INFINITY,
+        rlim_max: libc::RLIM_INFINITY,
+    };
+    if unsafe { libc::prlimit(1, resource(r) as _, std::ptr::null(), &mut l) } != 0 {
+        let en = Errno::last();
+        return proc_limit(r).ok_or(en);
+    }
+    let limit = |v| Some(v).filter(|v| *v != libc::RLIM_INFINITY);
+    Ok((limit(l.rlim_cur), limit(l.rlim_max)))
+}
+
+/// Function to read the limits `init` has for a resource from `/proc/1/limits`
+fn proc_limit(r: RlimitResource) -> Option<(Limit, Limit)> {
+    let label = match r {
+        RlimitResource::As => "Max address space",
+        RlimitResource::Core => "Max core file size",
+        RlimitResource::Cpu => "Max cpu time",
+        RlimitResource::Data => "Max data size",
+        RlimitResource::Fsize => "Max file size",
+        RlimitResource::Locks => "Max file locks",
+        RlimitResource::Memlock => "Max locked memory",
+        RlimitResource::Nofile => "Max open files",
+        RlimitResource::Nproc => "Max processes",
+        RlimitResource::Rss => "Max resident set",
+        RlimitResource::Stack => "Max stack size",
+    };
+    let text = fs::read_to_string("/proc/1/limits").ok()?;
+    let rest = text
+        .lines()
+        .filter_map(|l| l.strip_prefix(label))
+        .find(|rest| rest.starts_with(' '))?;
+    // The soft limit comes first, then the hard limit, then the units
+    let mut fields = rest.split_whitespace();
+    let mut next = || -> Option<Limit> {
+        match fields.next()? {
+            "unlimited" => Some(None),
+            v => v.parse().ok().map(Some),
+        }
+    };
+    Some((next()?, next()?))
+}
+
+/// Function to parse a line of `limits.conf`
+///
+/// It returns how specific the line is, whether it's for the `soft` or `hard`
+/// limit or `-` for both, and the limit it sets. Lines that aren't for the
+/// target user, or that don't set a limit this module knows, give [None].
+fn parse_line<'a>(
+    line: &'a str,
+    perm: &Permission,
+    user: Option<&str>,
+) -> Option<(u8, &'a str, RlimitResource, Limit)> {
+    let fields: Vec<&str> = line.split('#').next()?.split_whitespace().collect();
+    let (domain, kind, item, value) = match fields[..] {
+        [d, k, i, v] => (d, k, i, v),
+        _ => return None,
+    };
+
+    // See who it's for
+    let rank = if domain == "*" {
+        1
+    } else if let Some(g) = domain.strip_prefix('@') {
+        let gid = Gid::from_raw(users::get_group_by_name(g)?.gid());
+        if gid != perm.primary_gid && !perm.secondary_gids.contains(&gid) {
+            return None;
+        }
+        2
+    } else if Some(domain) == user {
+        3
+    } else {
+        return None;
+    };
+
+    // See what it sets
+    let r = RlimitResource::from_name(item)?;
+    let limit = match value {
+        "unlimited" | "infinity" | "-1" => None,
+        v => Some(v.parse::<u64>().ok()?.saturating_mul(unit(r))),
+    };
+    Some((rank, kind, r, limit))
+}
+
+/// Function to list the files to read for a path in
+/// [config::LOGIN_LIMITS_PATHS]
+///
+/// A file is read as-is. For a directory, every file in it ending in `.conf` is
+/// read in order.
+fn config_files(path: &Path) -> Vec<std::path::PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    let mut ret: Vec<_> = fs::read_dir(path)
+        .map(|d| {
+            d.filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().map(|e| e == "conf") == Some(true))
+                .collect()
+        })
+        .unwrap_or_default();
+    ret.sort();
+    ret
+}
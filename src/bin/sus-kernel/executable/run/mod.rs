@@ -2,29 +2,59 @@
 //!
 //! [Executable]s have to be run somehow. This module defines methods for
 //! invoking them, as well as auxilary types related to those functions.
+//!
+//! There are a few ways to run an [Executable]. The simplest is [exec], which
+//! replaces this process. The [supervise] runner instead stays around to log
+//! how the [Executable] ended, and [pty] goes further to record everything the
+//! [Executable] reads and writes. Which one is used can be chosen in the policy,
+//! and [select] is the [Runner] that makes that choice. It also hands the
+//! `sudoedit` pseudo-command to [edit], which edits files instead of running
+//! anything.
 
+pub mod background;
+pub mod cgroup;
+pub mod edit;
 pub mod exec;
+pub mod intercept;
+pub mod iolog;
+pub mod landlock;
+pub mod limits;
+pub mod namespace;
+pub mod noexec;
+pub mod pty;
+pub mod sanitize;
+pub mod select;
+pub mod supervise;
+pub use edit::edit;
 pub use exec::exec;
+pub use pty::pty;
+pub use select::select;
+pub use supervise::supervise;
 
 use super::Executable;
+use crate::permission::verify::Options;
 use crate::permission::Permission;
 
 use nix::errno::Errno;
 use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
 
 /// Type for functions that run [Executable]s
 ///
 /// These functions take in the [Permission]s the user wishes to execute as, and
-/// runs the [Executable] with those permissions. Ideally, this function never
+/// runs the [Executable] with those permissions. They're also given the
+/// [Options] from the policy that allowed the run. Ideally, this function never
 /// returns. If it returns, it always returns a [Result::Err].
-pub type Runner = fn(&Permission, &Executable) -> RunResult;
+pub type Runner = fn(&Permission, &Executable, &Options) -> RunResult;
 /// Abstract supertype of [Runner]
 ///
 /// For testing purposes, we might want to have [Runner]s signal other parts of
 /// the code. This trait allows for that. Since it's a `dyn` type, we can't
 /// create variables with it. However, it will work for automatically generated
 /// closures.
-pub type AbstractRunner = dyn FnMut(&Permission, &Executable) -> RunResult;
+pub type AbstractRunner = dyn FnMut(&Permission, &Executable, &Options) -> RunResult;
 
 /// Convinience type for the result of a [Runner]
 ///
@@ -52,7 +82,100 @@ pub enum RunError {
     SetPrimaryGID { errno: Errno },
     /// An error occurred when seting the Secondary GIDs of the process
     SetSecondaryGID { errno: Errno },
+    /// An error occurred when limiting the capabilities of the process
+    Capabilities { errno: Errno },
+    /// An error occurred when switching back to `root` to run the [Executable]
+    Privilege { errno: Errno },
 
     /// An error occured when attempting to change to the target binary
     Execute { errno: Errno },
+    /// An error occurred when cleaning up the state inherited from the user
+    Sanitize { errno: Errno },
+    /// An error occurred when setting the resource limits or priority
+    Limits { errno: Errno },
+    /// An error occurred when changing the root directory
+    Chroot { errno: Errno },
+    /// An error occurred when changing to the working directory
+    Chdir { errno: Errno },
+    /// An error occurred when stopping the [Executable] from running anything
+    /// else
+    Noexec { errno: Errno },
+    /// An error occurred when limiting what files the [Executable] can get to
+    Landlock { errno: Errno },
+    /// An error occurred when moving into new namespaces
+    Namespace { errno: Errno },
+    /// An error occurred when logging how the [Executable] is confined
+    #[cfg(feature = "log")]
+    Sandbox { cause: Box<dyn Error> },
+
+    /// An error occurred when installing a signal handler
+    Signal { errno: Errno },
+    /// An error occurred when creating the child process
+    Fork { errno: Errno },
+    /// An error occurred when waiting for the child process
+    Wait { errno: Errno },
+
+    /// An error occurred when creating the terminal for the child process
+    Pty { errno: Errno },
+    /// An error occurred when starting to record the session
+    Record { cause: Box<dyn Error> },
+    /// An error occurred when creating or joining the cgroup
+    Cgroup { cause: std::io::Error },
+    /// An error occurred when setting up to check what the [Executable] runs
+    Intercept { errno: Errno },
+    /// An error occurred when editing files with `sudoedit`
+    Edit { errno: Errno },
+    /// An error occurred when moving into the background
+    Background { errno: Errno },
+}
+
+impl Display for RunError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // Most of these are a failed system call, so share the wording
+        let (what, errno) = match self {
+            RunError::SetUID { errno } => ("set the UID", errno),
+            RunError::SetPrimaryGID { errno } => ("set the Primary GID", errno),
+            RunError::SetSecondaryGID { errno } => ("set the Secondary GIDs", errno),
+            RunError::Capabilities { errno } => ("limit the capabilities", errno),
+            RunError::Privilege { errno } => ("switch back to root", errno),
+            RunError::Execute { errno } => ("execute the command", errno),
+            RunError::Sanitize { errno } => ("clean up the inherited state", errno),
+            RunError::Limits { errno } => ("set the resource limits", errno),
+            RunError::Chroot { errno } => ("change the root directory", errno),
+            RunError::Chdir { errno } => ("change the working directory", errno),
+            RunError::Noexec { errno } => ("install the noexec filter", errno),
+            RunError::Landlock { errno } => ("set up Landlock", errno),
+            RunError::Namespace { errno } => ("enter the namespaces", errno),
+            RunError::Signal { errno } => ("install a signal handler", errno),
+            RunError::Fork { errno } => ("fork", errno),
+            RunError::Wait { errno } => ("wait for the command", errno),
+            RunError::Pty { errno } => ("create the terminal", errno),
+            RunError::Intercept { errno } => ("set up intercept", errno),
+            RunError::Edit { errno } => ("edit the files", errno),
+            RunError::Background { errno } => ("move into the background", errno),
+            #[cfg(feature = "log")]
+            RunError::Sandbox { cause } => {
+                return write!(f, "Could not log the sandbox: {}", cause);
+            }
+            RunError::Record { cause } => {
+                return write!(f, "Could not record the session: {}", cause);
+            }
+            RunError::Cgroup { cause } => {
+                return write!(f, "Could not set up the cgroup: {}", cause);
+            }
+        };
+        write!(f, "Could not {}: {}", what, errno)
+    }
+}
+
+impl Error for RunError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(feature = "log")]
+            RunError::Sandbox { cause } => Some(cause.as_ref()),
+            RunError::Record { cause } => Some(cause.as_ref()),
+            RunError::Cgroup { cause } => Some(cause),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,300 @@
+//! Module for running an [Executable][eb] in new namespaces
+//!
+//! The policy can give a `namespaces` option to keep a command away from the
+//! rest of the system. A new `mount` namespace lets it have a private `/tmp`,
+//! and a read-only `/home`, without anyone else seeing either. A new `ipc`
+//! namespace keeps it from System V IPC objects and POSIX message queues. A new
+//! `pid` namespace keeps it from seeing or signalling other processes.
+//!
+//! Only the children of a process end up in the `pid` namespace it creates, so
+//! that has to be done by the runner, with [unshare_pid], before it forks. The
+//! child is then the namespace's PID 1. It sets up the rest with [enter], then
+//! forks again to stay behind as [init]. That way, the [Executable][eb] isn't
+//! PID 1 itself, which would have it ignore signals it doesn't handle.
+//!
+//! [eb]: crate::executable::Executable
+
+use super::supervise::{relay, set_handlers, CHILD_PID, RELAYED_SIGNALS};
+use crate::permission::verify::sudoers_type::Namespaces;
+use crate::permission::verify::Options;
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::mount;
+use nix::mount::MsFlags;
+use nix::sched;
+use nix::sched::CloneFlags;
+use nix::sys::signal::SigHandler;
+use nix::sys::wait;
+use nix::sys::wait::WaitStatus;
+use nix::unistd;
+use nix::unistd::ForkResult;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+/// Function to see if a new `mount` namespace is needed
+fn needs_mount(ns: &Namespaces) -> bool {
+    ns.mount || ns.pid || ns.private_tmp || ns.read_only_home
+}
+
+impl Display for Namespaces {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let names: Vec<_> = [
+            ("mount", needs_mount(self)),
+            ("pid", self.pid),
+            ("ipc", self.ipc),
+        ]
+        .iter()
+        .filter(|(_, b)| *b)
+        .map(|(n, _)| *n)
+        .collect();
+        let mounts: Vec<_> = [
+            ("a private /tmp", self.private_tmp),
+            ("a read-only /home", self.read_only_home),
+        ]
+        .iter()
+        .filter(|(_, b)| *b)
+        .map(|(m, _)| *m)
+        .collect();
+        write!(f, "namespaces [{}]", names.join(", "))?;
+        if !mounts.is_empty() {
+            write!(f, " with {}", mounts.join(" and "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Function for a runner to create the `pid` namespace, if the [Options] ask
+/// for one
+///
+/// This has to be done as `root`, just before forking. The child is PID 1 in
+/// the new namespace. This process stays where it is.
+pub fn unshare_pid(opts: &Options) -> Result<(), Errno> {
+    match opts.namespaces() {
+        Some(ns) if ns.pid => sched::unshare(CloneFlags::CLONE_NEWPID),
+        _ => Ok(()),
+    }
+}
+
+/// Function to move into the namespaces the [Options] ask for
+///
+/// This has to be done as `root`. The mounts are set up here too, so they're
+/// relative to the root directory at the time. For a `pid` namespace, this
+/// process has to already be PID 1 in it, from [unshare_pid]. It then becomes
+/// [init] in there, and only the child it forks returns.
+pub fn enter(opts: &Options) -> Result<(), Errno> {
+    let ns = match opts.namespaces() {
+        Some(ns) => ns,
+        None => return Ok(()),
+    };
+    if ns.pid && unistd::getpid().as_raw() != 1 {
+        return Err(Errno::EINVAL);
+    }
+
+    let mut flags = CloneFlags::empty();
+    if needs_mount(&ns) {
+        flags |= CloneFlags::CLONE_NEWNS;
+    }
+    if ns.ipc {
+        flags |= CloneFlags::CLONE_NEWIPC;
+    }
+    sched::unshare(flags)?;
+
+    if needs_mount(&ns) {
+        // Don't let anything we mount leak out
+        mount::mount::<str, str, str, str>(
+            None,
+            "/",
+            None,
+            MsFlags::MS_REC | MsFlags::MS_PRIVATE,
+            None,
+        )?;
+        if ns.private_tmp {
+            mount::mount(
+                Some("tmpfs"),
+                "/tmp",
+                Some("tmpfs"),
+                MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+                Some("mode=1777"),
+            )?;
+        }
+        if ns.read_only_home {
+            // Bind mounts can only be made read-only once they exist
+            mount::mount::<str, str, str, str>(
+                Some("/home"),
+                "/home",
+                None,
+                MsFlags::MS_BIND | MsFlags::MS_REC,
+                None,
+            )?;
+            mount::mount::<str, str, str, str>(
+                None,
+                "/home",
+                None,
+                MsFlags::MS_REMOUNT | MsFlags::MS_BIND | MsFlags::MS_RDONLY,
+                None,
+            )?;
+        }
+        // Show only our processes
+        // There might not be a /proc after a chroot
+        if ns.pid && Path::new("/proc").is_dir() {
+            mount::mount(
+                Some("proc"),
+                "/proc",
+                Some("proc"),
+                MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC,
+                None::<&str>,
+            )?;
+        }
+    }
+
+    if ns.pid {
+        init()?;
+    }
+    Ok(())
+}
+
+/// Function to fork, then stay behind as PID 1 of the `pid` namespace
+///
+/// Only the child returns. This process relays signals to the child and reaps
+/// anything left behind in the namespace. Once the child exits, this process
+/// exits the same way, which kills everything else in the namespace. Since
+/// PID 1 can't be killed by its own signals, a child killed by a signal is
+/// reported as exiting with 128 plus the signal's number, like shells do.
+fn init() -> Result<(), Errno> {
+    let child = match unsafe { unistd::fork() }? {
+        ForkResult::Child => return Ok(()),
+        ForkResult::Parent { child } => child,
+    };
+    CHILD_PID.store(child.as_raw(), Ordering::SeqCst);
+    let _ = set_handlers(&RELAYED_SIGNALS, SigHandler::SigAction(relay));
+
+    loop {
+        let code = match wait::waitpid(None, None) {
+            Ok(WaitStatus::Exited(pid, code)) if pid == child => code,
+            Ok(WaitStatus::Signaled(pid, sig, _)) if pid == child => 128 + sig as i32,
+            Ok(_) | Err(Errno::EINTR) => continue,
+            Err(_) => 1,
+        };
+        unsafe { libc::_exit(code) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permission::verify::sudoers_type;
+
+    use std::fs;
+    use std::io::ErrorKind;
+
+    /// A file made outside the namespaces, which shouldn't be seen inside
+    const OUTSIDE: &str = "/tmp/sus-namespace-outside";
+
+    /// Function to run `check` in new namespaces, the way a runner would, and
+    /// get how it exited
+    ///
+    /// This forks, since only a process with one thread can move into a new
+    /// `mount` namespace. For a `pid` namespace, it forks again, so the
+    /// namespace's PID 1 can become [init].
+    fn in_namespaces(ns: Namespaces, check: fn() -> i32) -> WaitStatus {
+        let opts = Options(vec![sudoers_type::Option::Namespaces(ns)]);
+        let runner = match unsafe { unistd::fork() }.unwrap() {
+            ForkResult::Parent { child } => child,
+            ForkResult::Child => {
+                if unshare_pid(&opts).is_err() {
+                    unsafe { libc::_exit(100) }
+                }
+                let child = match unsafe { unistd::fork() } {
+                    Ok(ForkResult::Parent { child }) => child,
+                    Ok(ForkResult::Child) => {
+                        if enter(&opts).is_err() {
+                            unsafe { libc::_exit(101) }
+                        }
+                        unsafe { libc::_exit(check()) }
+                    }
+                    Err(_) => unsafe { libc::_exit(102) },
+                };
+                let code = match wait::waitpid(child, None) {
+                    Ok(WaitStatus::Exited(_, code)) => code,
+                    _ => 103,
+                };
+                unsafe { libc::_exit(code) }
+            }
+        };
+        wait::waitpid(runner, None).unwrap()
+    }
+
+    #[test]
+    fn private_tmp_and_read_only_home() {
+        if !unistd::geteuid().is_root() || !Path::new("/home").is_dir() {
+            return;
+        }
+        fs::write(OUTSIDE, "").unwrap();
+
+        let res = in_namespaces(
+            Namespaces {
+                private_tmp: true,
+                read_only_home: true,
+                ..Namespaces::default()
+            },
+            || {
+                if Path::new(OUTSIDE).exists() {
+                    return 1;
+                }
+                if fs::write("/tmp/sus-namespace-inside", "").is_err() {
+                    return 2;
+                }
+                match fs::write("/home/sus-namespace-inside", "") {
+                    Err(e) if e.raw_os_error() == Some(libc::EROFS) => 0,
+                    _ => 3,
+                }
+            },
+        );
+        let left = Path::new("/tmp/sus-namespace-inside").exists();
+        fs::remove_file(OUTSIDE).unwrap();
+
+        assert!(matches!(res, WaitStatus::Exited(_, 0)), "{:?}", res);
+        assert!(!left);
+        assert_eq!(
+            fs::metadata("/home/sus-namespace-inside")
+                .unwrap_err()
+                .kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn pid_namespace_runs_under_init() {
+        if !unistd::geteuid().is_root() {
+            return;
+        }
+        let ns = Namespaces {
+            pid: true,
+            ..Namespaces::default()
+        };
+
+        // The command is PID 1's child, and /proc shows it that way
+        let res = in_namespaces(ns, || {
+            let proc_self = fs::read_link("/proc/self").ok();
+            if unistd::getppid().as_raw() != 1 || proc_self != Some("2".into()) {
+                return 1;
+            }
+            7
+        });
+        assert!(matches!(res, WaitStatus::Exited(_, 7)), "{:?}", res);
+
+        // A signal comes out like a shell would report it
+        let res = in_namespaces(ns, || {
+            let _ = nix::sys::signal::raise(nix::sys::signal::Signal::SIGTERM);
+            1
+        });
+        assert!(
+            matches!(res, WaitStatus::Exited(_, c) if c == 128 + libc::SIGTERM),
+            "{:?}",
+            res
+        );
+    }
+}
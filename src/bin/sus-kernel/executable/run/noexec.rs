@@ -0,0 +1,119 @@
+//! Module for stopping an [Executable] from running anything else
+//!
+//! With `noexec`, the [Executable] itself is run, but it can't start a shell or
+//! any other program. A `seccomp` filter passes every `execve` and `execveat`
+//! to a [Gate], which is a process of its own. The first call is the one the
+//! runner makes to become the [Executable], so it's let through. Every call
+//! after that fails with [Errno::EACCES], whatever it was made with. The filter
+//! is kept by all of the [Executable]'s children too.
+//!
+//! The [Gate] is started as `root` with [prepare], before anything the user
+//! controls has run. It leaves its parent behind, so the [Executable] never
+//! sees it as a child. The filter is then installed with [Gate::install] right
+//! before the runner becomes the [Executable], and the listener handed to the
+//! [Gate]. The [Gate] exits once nothing using the filter is left. If it goes
+//! away before then, every call fails anyway.
+
+use super::sanitize::detach;
+use crate::permission::seccomp;
+use crate::permission::seccomp::Action;
+use crate::permission::verify::helper::message::{recv, send};
+use crate::permission::verify::Options;
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::socket;
+use nix::sys::socket::{AddressFamily, SockFlag, SockType};
+use nix::sys::wait;
+use nix::unistd;
+use nix::unistd::ForkResult;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+/// The socket the runner sends the listener to the gate over
+#[derive(Debug)]
+pub struct Gate {
+    sock: RawFd,
+}
+
+/// Function to start the [Gate], if the [Options] ask for `noexec`
+///
+/// It forks twice, so that the [Gate] is left without a parent, then waits for
+/// the first fork to exit.
+pub fn prepare(opts: &Options) -> Result<Option<Gate>, Errno> {
+    if !opts.noexec() {
+        return Ok(None);
+    }
+    let (ours, theirs) = socket::socketpair(
+        AddressFamily::Unix,
+        SockType::SeqPacket,
+        None,
+        SockFlag::SOCK_CLOEXEC,
+    )?;
+    let middle = match unsafe { unistd::fork() } {
+        Ok(ForkResult::Parent { child }) => child,
+        Ok(ForkResult::Child) => {
+            if let Ok(ForkResult::Child) = unsafe { unistd::fork() } {
+                keep(theirs)
+            }
+            unsafe { libc::_exit(0) }
+        }
+        Err(en) => {
+            let _ = unistd::close(ours);
+            let _ = unistd::close(theirs);
+            return Err(en);
+        }
+    };
+    let _ = unistd::close(theirs);
+    loop {
+        match wait::waitpid(middle, None) {
+            Err(Errno::EINTR) => continue,
+            Err(en) => return Err(en),
+            Ok(_) => break,
+        }
+    }
+    Ok(Some(Gate { sock: ours }))
+}
+
+impl Gate {
+    /// Function to install the filter, and hand the listener to the [Gate]
+    ///
+    /// This has to be the last thing before executing. It sets `no_new_privs`,
+    /// so the [Executable] can't gain privileges by running a Set-UID binary
+    /// either, even if the [Gate] were to let it.
+    pub fn install(self) -> Result<(), Errno> {
+        seccomp::no_new_privs()?;
+        let listener = seccomp::install_listener(
+            &[],
+            &[
+                (libc::SYS_execve, Action::Notify),
+                (libc::SYS_execveat, Action::Notify),
+            ],
+            Action::Allow,
+        )?;
+        let res = send(self.sock, &(), Some(listener)).map_err(|_| Errno::EPIPE);
+        let _ = unistd::close(listener);
+        let _ = unistd::close(self.sock);
+        res
+    }
+}
+
+/// Function to run the [Gate] itself
+///
+/// It gives up everything but `sock`, with [detach]. Then, it gets the
+/// listener, and answers it until nothing is left to make calls. This never
+/// returns.
+fn keep(sock: RawFd) -> ! {
+    detach(sock);
+
+    let listener = recv::<()>(sock).ok().and_then(|(_, f)| f);
+    let _ = unistd::close(sock);
+    if let Some(listener) = listener {
+        let mut first = true;
+        while let Some(notif) = seccomp::receive(listener.as_raw_fd()) {
+            let res = if first { Ok(()) } else { Err(Errno::EACCES) };
+            first = false;
+            seccomp::respond(listener.as_raw_fd(), notif.id, res);
+        }
+    }
+    unsafe { libc::_exit(0) }
+}
@@ -0,0 +1,492 @@
+//! Module containing a method that runs an [Executable] on a new terminal
+//!
+//! To record a session, everything the user types and sees has to pass through
+//! this process. This module does that by creating a new pseudo-terminal for
+//! the [Executable] to run on. This process holds the other end of it, and
+//! relays data between it and the user's terminal, recording it as it goes.
+//!
+//! Any of the standard streams that aren't a terminal, like when output is
+//! redirected to a file, are given to the [Executable] as pipes instead. That
+//! way, they're relayed and recorded separately, just like `sudo` does.
+//!
+//! The new terminal is also useful without recording. A command run on the
+//! user's terminal can push input into it with `TIOCSTI`, which the user's
+//! shell reads once the command exits. With `use_pty`, the [Executable] only
+//! ever sees the new terminal. Any other descriptors for terminals are closed
+//! in the child, and the new terminal is torn down once it exits. Streams that
+//! aren't terminals are passed through as-is, since there's nothing to record.
+//!
+//! Since the [Executable] runs in its own session, suspending it from the
+//! user's terminal with `^Z` only affects jobs inside it.
+
+use super::cgroup;
+use super::exec;
+use super::intercept;
+use super::iolog::{Session, Stream};
+use super::namespace;
+use super::sanitize::open_fds;
+use super::supervise::{exit_like, relay, set_handlers, Exit, CHILD_PID, RELAYED_SIGNALS};
+use super::supervise::{start_timeout, stop_timeout};
+use super::Executable;
+use super::RunError;
+use super::RunResult;
+use crate::permission::privilege;
+use crate::permission::verify::Options;
+use crate::permission::Permission;
+
+#[cfg(feature = "log")]
+use crate::config;
+
+use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use nix::libc;
+use nix::poll::{PollFd, PollFlags};
+use nix::pty::Winsize;
+use nix::sys::signal;
+use nix::sys::signal::{SigHandler, SigSet, SigmaskHow, Signal};
+use nix::sys::termios;
+use nix::sys::termios::SetArg;
+use nix::sys::wait;
+use nix::sys::wait::{WaitPidFlag, WaitStatus};
+use nix::unistd;
+use nix::unistd::{ForkResult, Pid};
+use serde_json::json;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// The write end of the pipe the signal handler uses to wake up the main loop
+///
+/// It's negative until the pipe has been created.
+static WAKE_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// The signals that wake up the main loop instead of being relayed
+///
+/// When the child exits, we need to stop. When the user's terminal changes
+/// size, we need to change the size of the child's terminal to match.
+const WAKE_SIGNALS: [Signal; 2] = [Signal::SIGCHLD, Signal::SIGWINCH];
+
+/// Signal handler that writes the signal number to [WAKE_FD]
+extern "C" fn wake(sig: libc::c_int) {
+    let fd = WAKE_FD.load(Ordering::SeqCst);
+    if fd >= 0 {
+        let b = sig as u8;
+        unsafe {
+            libc::write(fd, &b as *const u8 as *const libc::c_void, 1);
+        }
+    }
+}
+
+/// Function to get the size of the terminal `fd`
+fn get_winsize(fd: RawFd) -> Option<Winsize> {
+    let mut ws: Winsize = unsafe { std::mem::zeroed() };
+    match unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut ws) } {
+        0 => Some(ws),
+        _ => None,
+    }
+}
+
+/// Function to close every descriptor for a terminal, other than the standard
+/// streams
+///
+/// This is run in the child, after the standard streams have been set up. A
+/// descriptor left open for the user's terminal would let the [Executable]
+/// reach it, even though it runs on a new one.
+fn close_other_ttys() {
+    for fd in open_fds() {
+        if fd > 2 && unistd::isatty(fd).unwrap_or(false) {
+            let _ = unistd::close(fd);
+        }
+    }
+}
+
+/// Function to write all of `data` to `fd`
+fn write_all(fd: RawFd, mut data: &[u8]) -> Result<(), Errno> {
+    while !data.is_empty() {
+        match unistd::write(fd, data) {
+            Ok(n) => data = &data[n..],
+            Err(Errno::EINTR) => (),
+            Err(en) => return Err(en),
+        }
+    }
+    Ok(())
+}
+
+/// A stream of data to copy from one file descriptor to another
+struct Relay {
+    /// Where to read from
+    from: RawFd,
+    /// Where to write to
+    to: RawFd,
+    /// What to record the data as
+    stream: Stream,
+    /// Whether to close `to` once `from` runs out
+    ///
+    /// This is set for pipes, so the child sees the end of its input.
+    close_to: bool,
+    /// Whether `from` is still open
+    open: bool,
+}
+
+/// Function that runs the [Executable] given on a new terminal and records the
+/// session
+///
+/// Input is recorded if the [Options] have `log_input` set, and output if they
+/// have `log_output` set. The session's ID is logged along with the
+/// [Executable] before it starts. If neither is set, nothing is recorded, but
+/// the [Executable] still runs on the new terminal.
+///
+/// Like [supervise][sv], this process waits for the [Executable], relaying
+/// signals to it, then logs how it ended and exits the same way. The user's
+/// terminal is put in raw mode while the [Executable] runs, and set back
+/// afterwards.
+///
+/// This function only returns if the terminal, the recording, or the child
+/// couldn't be set up.
+///
+/// [sv]: super::supervise
+pub fn pty(perm: &Permission, execable: &Executable, opts: &Options) -> RunResult {
+    // Get root back to create the terminal and the recording
+    privilege::regain_root().map_err(|en| RunError::Privilege { errno: en })?;
+
+    let pty_err = |en| RunError::Pty { errno: en };
+    let recording = opts.log_input() || opts.log_output();
+
+    // Find which of our standard streams are terminals
+    // Copy the settings and size of the first one to the new terminal
+    let is_tty = [0, 1, 2].map(|fd| unistd::isatty(fd).unwrap_or(false));
+    let user_tty = (0..3).find(|fd| is_tty[*fd as usize]);
+    let orig_termios = user_tty.and_then(|fd| termios::tcgetattr(fd).ok());
+    let winsize = user_tty.and_then(get_winsize);
+
+    // Create the terminal
+    // Give it to the target user, like a login would
+    let pty = nix::pty::openpty(winsize.as_ref(), orig_termios.as_ref()).map_err(pty_err)?;
+    for fd in [pty.master, pty.slave] {
+        nix::fcntl::fcntl(fd, nix::fcntl::F_SETFD(nix::fcntl::FdFlag::FD_CLOEXEC))
+            .map_err(pty_err)?;
+    }
+    unistd::fchown(pty.slave, Some(perm.uid), None).map_err(pty_err)?;
+
+    // Create pipes for the streams that aren't terminals
+    // For each stream, find the end the child uses and the end we use
+    // If we aren't recording, the child can just have the stream
+    let mut child_fds = [pty.slave; 3];
+    let mut pipe_fds = Vec::new();
+    let mut relays = Vec::new();
+    for (fd, tty) in is_tty.iter().enumerate() {
+        if *tty {
+            continue;
+        }
+        let fd = fd as RawFd;
+        if !recording {
+            child_fds[fd as usize] = fd;
+            continue;
+        }
+        let (r, w) = unistd::pipe2(OFlag::O_CLOEXEC).map_err(pty_err)?;
+        relays.push(match fd {
+            0 => {
+                child_fds[0] = r;
+                pipe_fds.push(r);
+                Relay {
+                    from: 0,
+                    to: w,
+                    stream: Stream::Stdin,
+                    close_to: true,
+                    open: true,
+                }
+            }
+            _ => {
+                child_fds[fd as usize] = w;
+                pipe_fds.push(w);
+                Relay {
+                    from: r,
+                    to: fd,
+                    stream: if fd == 1 {
+                        Stream::Stdout
+                    } else {
+                        Stream::Stderr
+                    },
+                    close_to: false,
+                    open: true,
+                }
+            }
+        });
+    }
+    // The terminal itself
+    if is_tty[0] {
+        relays.push(Relay {
+            from: 0,
+            to: pty.master,
+            stream: Stream::TtyIn,
+            close_to: false,
+            open: true,
+        });
+    }
+    relays.push(Relay {
+        from: pty.master,
+        to: user_tty.unwrap_or(1),
+        stream: Stream::TtyOut,
+        close_to: false,
+        open: true,
+    });
+
+    // Start recording, if we're asked to
+    let mut session = if recording {
+        let (rows, cols) = winsize.map_or((24, 80), |ws| (ws.ws_row, ws.ws_col));
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let info = json!({
+            "timestamp": { "seconds": now.as_secs(), "nanoseconds": now.subsec_nanos() },
+            "submituid": unistd::getuid().as_raw(),
+            "runuid": perm.uid.as_raw(),
+            "rungid": perm.primary_gid.as_raw(),
+            "command": execable.path.to_string_lossy(),
+            "runargv": execable.args.iter().map(|a| a.to_string_lossy()).collect::<Vec<_>>(),
+            "lines": rows,
+            "columns": cols,
+        });
+        let session = Session::create(opts.log_input(), opts.log_output(), &info)
+            .map_err(|e| RunError::Record { cause: e.into() })?;
+        #[cfg(feature = "log")]
+        {
+            (config::SESSION_LOGGER)(execable, perm, &session.id)
+                .map_err(|e| RunError::Record { cause: e })?;
+        }
+        Some(session)
+    } else {
+        None
+    };
+
+    // Set up signals
+    // Block them until the handlers know who the child is
+    let relayed: Vec<Signal> = RELAYED_SIGNALS
+        .iter()
+        .copied()
+        .filter(|s| !WAKE_SIGNALS.contains(s))
+        .collect();
+    let mut blocked = SigSet::empty();
+    for sig in RELAYED_SIGNALS.iter().chain(WAKE_SIGNALS.iter()) {
+        blocked.add(*sig);
+    }
+    let mut old_mask = SigSet::empty();
+    signal::sigprocmask(SigmaskHow::SIG_BLOCK, Some(&blocked), Some(&mut old_mask))
+        .map_err(|en| RunError::Signal { errno: en })?;
+    let (wake_r, wake_w) = unistd::pipe2(OFlag::O_CLOEXEC | OFlag::O_NONBLOCK).map_err(pty_err)?;
+    WAKE_FD.store(wake_w, Ordering::SeqCst);
+    set_handlers(&relayed, SigHandler::SigAction(relay))
+        .and_then(|_| set_handlers(&WAKE_SIGNALS, SigHandler::Handler(wake)))
+        .map_err(|en| RunError::Signal { errno: en })?;
+
+    // Make the cgroup, if asked
+    let mut cgroup = cgroup::prepare(opts)?;
+
+    // Start checking what it runs, if asked
+    // This has to be done before the pid namespace, so the tracer isn't in it
+    let intercept = intercept::prepare(perm, execable, opts)?;
+
+    // Make the pid namespace, if asked
+    // The child is the first thing in it
+    namespace::unshare_pid(opts).map_err(|en| RunError::Namespace { errno: en })?;
+
+    // Don't let the user signal us once the child is running
+    // This has to be done after the tracer is started, since it needs the user
+    privilege::become_root().map_err(|en| RunError::Privilege { errno: en })?;
+
+    // Time from just before the fork
+    let start = Instant::now();
+    let child = match unsafe { unistd::fork() }.map_err(|en| RunError::Fork { errno: en })? {
+        ForkResult::Parent { child } => child,
+        ForkResult::Child => {
+            // Put the signals back the way they were
+            let _ = set_handlers(&RELAYED_SIGNALS, SigHandler::SigDfl);
+            let _ = set_handlers(&WAKE_SIGNALS, SigHandler::SigDfl);
+            let _ = signal::sigprocmask(SigmaskHow::SIG_SETMASK, Some(&old_mask), None);
+            // Start a new session on the terminal, then become the executable
+            let _ = unistd::setsid();
+            unsafe {
+                libc::ioctl(pty.slave, libc::TIOCSCTTY, 0);
+            }
+            for (fd, child_fd) in child_fds.iter().enumerate() {
+                let _ = unistd::dup2(*child_fd, fd as RawFd);
+            }
+            close_other_ttys();
+            // Don't run outside the cgroup if we were asked to be in one
+            if let Some((_, procs)) = &mut cgroup {
+                if cgroup::join(procs).is_err() {
+                    unsafe { libc::_exit(1) }
+                }
+            }
+            // Don't run unchecked if we were asked to check
+            if let Some(c) = &intercept {
+                if c.install().is_err() {
+                    unsafe { libc::_exit(1) }
+                }
+            }
+            let _ = exec(perm, execable, opts);
+            // We only get here if that failed
+            // Exit the same way the kernel does on failure
+            unsafe { libc::_exit(1) }
+        }
+    };
+    CHILD_PID.store(child.as_raw(), Ordering::SeqCst);
+    if let Some(c) = intercept {
+        c.serve(child);
+    }
+    start_timeout(opts).map_err(|en| RunError::Signal { errno: en })?;
+
+    // Close the child's ends
+    let _ = unistd::close(pty.slave);
+    for fd in pipe_fds {
+        let _ = unistd::close(fd);
+    }
+
+    // Put the user's terminal in raw mode, so every key goes to the child's
+    //  terminal as-is
+    let raw_termios = if is_tty[0] {
+        orig_termios.clone().map(|mut t| {
+            termios::cfmakeraw(&mut t);
+            t
+        })
+    } else {
+        None
+    };
+    if let Some(t) = &raw_termios {
+        let _ = termios::tcsetattr(0, SetArg::TCSADRAIN, t);
+    }
+    signal::sigprocmask(SigmaskHow::SIG_SETMASK, Some(&old_mask), None)
+        .map_err(|en| RunError::Signal { errno: en })?;
+
+    // Relay until the child is done
+    let exit = relay_loop(
+        child,
+        wake_r,
+        pty.master,
+        user_tty,
+        &mut relays,
+        &mut session,
+    );
+
+    // Put the user's terminal back
+    // Tear down the new one, which hangs up anything still running on it
+    if let (Some(_), Some(t)) = (&raw_termios, &orig_termios) {
+        let _ = termios::tcsetattr(0, SetArg::TCSADRAIN, t);
+    }
+    let _ = unistd::close(pty.master);
+    let exit = stop_timeout(exit?);
+
+    // See what it used, then clean up
+    let usage = cgroup.as_ref().map(|(c, _)| c.usage()).unwrap_or_default();
+    drop(cgroup);
+
+    // Log how it ended
+    // The executable already ran, so failing to log shouldn't change how we exit
+    #[cfg(feature = "log")]
+    {
+        let _ = (config::EXIT_LOGGER)(execable, perm, child, &exit, start.elapsed(), &usage);
+    }
+    #[cfg(not(feature = "log"))]
+    {
+        let _ = (start, session.map(|s| s.id), usage);
+    }
+
+    exit_like(exit)
+}
+
+/// Function to relay data between the user and the child until the child exits
+///
+/// Once the child has exited, whatever output is left is read, then this
+/// function returns. The data is recorded to the [Session], if there is one.
+/// Failing to record data doesn't stop the relaying. The child is already
+/// running, and it's better to lose part of the recording than to leave the
+/// user's terminal in raw mode.
+fn relay_loop(
+    child: Pid,
+    wake_r: RawFd,
+    master: RawFd,
+    user_tty: Option<RawFd>,
+    relays: &mut [Relay],
+    session: &mut Option<Session>,
+) -> Result<Exit, RunError> {
+    let mut exit: Option<Exit> = None;
+    let mut buf = [0u8; 4096];
+    loop {
+        // Wait for something to happen
+        // Once the child has exited, don't wait. Just read what's left.
+        let open: Vec<usize> = (0..relays.len())
+            .filter(|i| relays[*i].open)
+            .filter(|i| exit.is_none() || !relays[*i].stream.is_input())
+            .collect();
+        let mut fds = vec![PollFd::new(wake_r, PollFlags::POLLIN)];
+        fds.extend(
+            open.iter()
+                .map(|i| PollFd::new(relays[*i].from, PollFlags::POLLIN)),
+        );
+        let timeout = if exit.is_some() { 0 } else { -1 };
+        match (nix::poll::poll(&mut fds, timeout), &exit) {
+            (Ok(0), Some(e)) => return Ok(e.clone()),
+            (Ok(_), _) | (Err(Errno::EINTR), _) => (),
+            (Err(en), _) => return Err(RunError::Wait { errno: en }),
+        }
+        let ready = |fd: &PollFd| matches!(fd.revents(), Some(r) if !r.is_empty());
+
+        // Handle signals
+        if ready(&fds[0]) {
+            while let Ok(n) = unistd::read(wake_r, &mut buf) {
+                if n == 0 {
+                    break;
+                }
+                for sig in &buf[..n] {
+                    if *sig as libc::c_int == libc::SIGWINCH {
+                        // Pass on the new size
+                        if let Some(ws) = user_tty.and_then(get_winsize) {
+                            unsafe {
+                                libc::ioctl(master, libc::TIOCSWINSZ, &ws);
+                            }
+                            if let Some(s) = session {
+                                let _ = s.window(ws.ws_row, ws.ws_col);
+                            }
+                        }
+                    }
+                }
+            }
+            // Whatever the signal, check on the child
+            loop {
+                match wait::waitpid(child, Some(WaitPidFlag::WNOHANG)) {
+                    Ok(WaitStatus::Exited(_, code)) => exit = Some(Exit::Code(code)),
+                    Ok(WaitStatus::Signaled(_, sig, _)) => exit = Some(Exit::Signal(sig)),
+                    Err(Errno::EINTR) => continue,
+                    Ok(_) | Err(_) => (),
+                }
+                break;
+            }
+        }
+
+        // Copy data
+        for (fd, i) in fds[1..].iter().zip(open) {
+            if !ready(fd) {
+                continue;
+            }
+            let r = &mut relays[i];
+            match unistd::read(r.from, &mut buf) {
+                Ok(n) if n > 0 => {
+                    if let Some(s) = session {
+                        let _ = s.record(r.stream, &buf[..n]);
+                    }
+                    if write_all(r.to, &buf[..n]).is_err() && r.stream.is_input() {
+                        r.open = false;
+                    }
+                }
+                Err(Errno::EINTR) | Err(Errno::EAGAIN) => (),
+                // The end of the stream, or the terminal was closed
+                _ => {
+                    r.open = false;
+                    if r.close_to {
+                        let _ = unistd::close(r.to);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! Module for cleaning up the state a process inherits
+//!
+//! The kernel is started by the user, so it begins with whatever state they
+//! chose to give it. Without cleaning up, an [Executable][eb] would inherit all
+//! of it: open file descriptors, ignored or blocked signals, the umask, resource
+//! limits, and priority. Any of these can change how a privileged program
+//! behaves, so this module resets them.
+//!
+//! The standard streams are handled separately by [std_fds], since they have
+//! to be fixed before anything else opens a file.
+//!
+//! The kernel changes some of this state for itself when it starts. So the
+//! [Executable][eb] still gets what the user gave, that state is kept aside by
+//! [save] first.
+//!
+//! [eb]: crate::executable::Executable
+
+use super::limits;
+use crate::config;
+use crate::permission::verify::Options;
+
+use nix::errno::Errno;
+use nix::fcntl;
+use nix::fcntl::{FcntlArg, OFlag};
+use nix::sys::signal;
+use nix::sys::signal::{SaFlags, SigAction, SigHandler, SigSet, SigmaskHow, Signal};
+use nix::sys::stat;
+use nix::sys::stat::Mode;
+use nix::unistd;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::io::RawFd;
+
+/// The state the user gave, as kept by [save]
+struct Saved {
+    umask: u32,
+    env: Vec<CString>,
+}
+thread_local! {
+    // The kernel only saves this, reads it, and forks from its main thread
+    static SAVED: RefCell<Option<Saved>> = RefCell::new(None);
+}
+
+/// Function to make sure the standard streams are open
+///
+/// If the user closed any of them, the next file we open would take its place.
+/// We might then write the log to what the [Executable][eb] thinks is its
+/// output, or the [Executable][eb] might write over one of our files. So, any
+/// that are closed are opened to `/dev/null`.
+///
+/// This should be called before anything else runs.
+///
+/// [eb]: crate::executable::Executable
+pub fn std_fds() -> Result<(), Errno> {
+    for fd in 0..3 {
+        if fcntl::fcntl(fd, FcntlArg::F_GETFD) != Err(Errno::EBADF) {
+            continue;
+        }
+        // The lower ones are open, so this should get `fd`
+        let null = fcntl::open("/dev/null", OFlag::O_RDWR, Mode::empty())?;
+        if null != fd {
+            unistd::dup2(null, fd)?;
+            unistd::close(null)?;
+        }
+    }
+    Ok(())
+}
+
+/// Function to keep the state the user gave before the kernel changes it
+///
+/// This keeps the umask. [sanitize] gives it back to the [Executable][eb]. If
+/// this is never called, [sanitize] uses whatever it is when it runs.
+///
+/// The environment is kept too, but never given to the [Executable][eb]. It's
+/// only for what runs as the user, like the editor for `sudoedit`. See
+/// [saved_env].
+///
+/// [eb]: crate::executable::Executable
+pub fn save() -> Result<(), Errno> {
+    // Reading the umask means setting it, so put it back after
+    let umask = stat::umask(Mode::from_bits_truncate(0o777));
+    stat::umask(umask);
+    let env = std::env::vars_os()
+        .filter_map(|(k, v)| {
+            let mut var = k.into_vec();
+            var.push(b'=');
+            var.extend(v.into_vec());
+            CString::new(var).ok()
+        })
+        .collect();
+    SAVED.with(|s| {
+        *s.borrow_mut() = Some(Saved {
+            umask: umask.bits() as u32,
+            env,
+        })
+    });
+    Ok(())
+}
+
+/// Function to get the environment the user gave, as kept by [save]
+///
+/// Each entry is `NAME=value`. It's empty if [save] was never called, or if
+/// [sanitize] already ran.
+pub fn saved_env() -> Vec<CString> {
+    SAVED.with(|s| match &*s.borrow() {
+        Some(s) => s.env.clone(),
+        None => Vec::new(),
+    })
+}
+
+/// Function to list the file descriptors this process has open
+///
+/// If `/proc` isn't available, every descriptor up to the limit is listed
+/// instead, whether it's open or not.
+pub fn open_fds() -> Vec<RawFd> {
+    match std::fs::read_dir("/proc/self/fd") {
+        Ok(dir) => dir
+            .filter_map(|e| e.ok()?.file_name().to_str()?.parse().ok())
+            .collect(),
+        Err(_) => {
+            let max = unistd::sysconf(unistd::SysconfVar::OPEN_MAX)
+                .ok()
+                .flatten()
+                .unwrap_or(1024);
+            (0..max as RawFd).collect()
+        }
+    }
+}
+
+/// Function to cut a process off from everything but `keep`
+///
+/// It leaves the user's session, so signals from the terminal don't reach it,
+/// closes every other file descriptor, and puts `/dev/null` on the standard
+/// streams. This is for processes that stay behind to watch the
+/// [Executable][eb], so they don't hold on to its terminal or pipes.
+///
+/// [eb]: crate::executable::Executable
+pub fn detach(keep: RawFd) {
+    let _ = unistd::setsid();
+    for fd in open_fds() {
+        if fd != keep {
+            let _ = unistd::close(fd);
+        }
+    }
+    if let Ok(null) = fcntl::open("/dev/null", OFlag::O_RDWR, Mode::empty()) {
+        for fd in 0..3 {
+            let _ = unistd::dup2(null, fd);
+        }
+        if null > 2 {
+            let _ = unistd::close(null);
+        }
+    }
+}
+
+/// Function to clean up the state of this process before running an
+/// [Executable][eb]
+///
+/// In particular, it
+///   * resets every signal to its default action, and unblocks them all
+///   * sets the umask to the user's combined with the policy's, which is
+///     `umask` in the [Options], or [config::DEFAULT_UMASK] if that isn't set.
+///     As with `sudo`, a umask of `0777` means to use the user's as-is.
+///   * sets every resource limit to what `init` has, and the nice value to
+///     `0`, with [limits::reset]. The [Options] can change them after, with
+///     [limits::apply].
+///   * closes every file descriptor from `closefrom` in the [Options] up, or
+///     from [config::DEFAULT_CLOSEFROM] if that isn't set
+///
+/// The standard streams are never closed.
+///
+/// [eb]: crate::executable::Executable
+pub fn sanitize(opts: &Options) -> Result<(), Errno> {
+    // Reset signals
+    // The user might have ignored some, which the executable would inherit
+    let default = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+    for sig in Signal::iterator() {
+        if sig == Signal::SIGKILL || sig == Signal::SIGSTOP {
+            continue;
+        }
+        unsafe { signal::sigaction(sig, &default) }?;
+    }
+    signal::sigprocmask(SigmaskHow::SIG_SETMASK, Some(&SigSet::empty()), None)?;
+
+    // Set the umask
+    // Use the one the user gave if it was saved
+    let saved = SAVED.with(|s| s.borrow_mut().take());
+    let current = stat::umask(Mode::from_bits_truncate(0o777)).bits() as u32;
+    let user = saved.as_ref().map(|s| s.umask).unwrap_or(current);
+    let policy = opts.umask().unwrap_or(config::DEFAULT_UMASK);
+    let umask = if policy == 0o777 { user } else { user | policy };
+    stat::umask(Mode::from_bits_truncate(umask as _));
+
+    // Reset the limits and priority
+    // The user could have lowered them to make the executable fail partway
+    //  through, or raised its nice value to slow it down
+    limits::reset()?;
+
+    // Close descriptors
+    let lowest = opts.closefrom().unwrap_or(config::DEFAULT_CLOSEFROM).max(3);
+    for fd in open_fds() {
+        if fd >= lowest {
+            let _ = unistd::close(fd);
+        }
+    }
+
+    Ok(())
+}
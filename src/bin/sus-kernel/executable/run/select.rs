@@ -0,0 +1,60 @@
+//! Module containing a [Runner][rn] that picks another [Runner][rn]
+//!
+//! The policy can say how an [Executable] should be run, either for a single
+//! rule or for everything with `Defaults`. This module looks at the [Options]
+//! and hands off to the right [Runner][rn].
+//!
+//! [rn]: super::Runner
+
+use super::background;
+use super::Executable;
+use super::RunError;
+use super::RunResult;
+use super::{edit, exec, pty, supervise};
+use crate::config;
+use crate::permission::verify::sudoers_type::RunnerKind;
+use crate::permission::verify::Options;
+use crate::permission::Permission;
+
+/// Function to run the [Executable] with the [Runner][rn] the [Options] ask for
+///
+/// Recording the session needs [pty], so it's always used if the [Options] ask
+/// for input or output to be logged. It's also used if they set `use_pty`, to
+/// keep the [Executable] away from the user's terminal. Otherwise, the `runner`
+/// option is used. If the [Options] don't say, [config::DEFAULT_RUNNER] is
+/// used.
+///
+/// Enforcing a `command_timeout`, cleaning up a `cgroup`, starting a `pid`
+/// namespace, or checking what the [Executable] runs with `intercept` or
+/// `log_subcmds` needs something to stay around until the [Executable] exits,
+/// so [supervise] is always used if the [Options] ask for any of those.
+///
+/// The [config::SUDOEDIT_PATH] pseudo-command isn't run at all. It's always
+/// handed to [edit], whatever the [Options] say.
+///
+/// If the user asked for the [Executable] to run in the background, this moves
+/// there first with [background::detach], then carries on as usual.
+///
+/// [rn]: super::Runner
+pub fn select(perm: &Permission, execable: &Executable, opts: &Options) -> RunResult {
+    if execable.path.as_bytes() == config::SUDOEDIT_PATH.as_bytes() {
+        return edit(perm, execable, opts);
+    }
+    if opts.background() {
+        background::detach().map_err(|en| RunError::Background { errno: en })?;
+    }
+    if opts.log_input() || opts.log_output() || opts.use_pty() {
+        return pty(perm, execable, opts);
+    }
+    let pid_namespace = opts.namespaces().map(|n| n.pid) == Some(true);
+    let intercept = opts.intercept() || opts.log_subcmds();
+    if opts.command_timeout().is_some() || opts.cgroup() || pid_namespace || intercept {
+        return supervise(perm, execable, opts);
+    }
+    let runner = match opts.runner() {
+        Some(RunnerKind::Exec) => exec,
+        Some(RunnerKind::Supervise) => supervise,
+        None => config::DEFAULT_RUNNER,
+    };
+    runner(perm, execable, opts)
+}
@@ -0,0 +1,316 @@
+//! Module containing a method that runs an [Executable] in a child process
+//!
+//! When [exec] is used, this process is replaced, so nothing is left behind to
+//! record how the [Executable] ended. This module forks instead. The child
+//! changes to the [Executable] exactly like [exec] would, while the parent
+//! stays behind. The parent relays signals to the child, waits for it, logs how
+//! it ended, and then exits the same way.
+//!
+//! If the policy sets a `command_timeout`, the parent also kills the child once
+//! it runs for too long. It's sent `SIGTERM` first, then `SIGKILL` if it's
+//! still around after [config::COMMAND_TIMEOUT_GRACE] seconds.
+//!
+//! If the policy asks for a [cgroup], the child runs in it, and what it used is
+//! logged along with how it ended.
+
+use super::cgroup;
+use super::exec;
+use super::intercept;
+use super::namespace;
+use super::Executable;
+use super::RunError;
+use super::RunResult;
+use crate::permission::privilege;
+use crate::permission::verify::Options;
+use crate::permission::Permission;
+
+use crate::config;
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::signal;
+use nix::sys::signal::{SaFlags, SigAction, SigHandler, SigSet, SigmaskHow, Signal};
+use nix::sys::wait;
+use nix::sys::wait::{WaitPidFlag, WaitStatus};
+use nix::unistd;
+use nix::unistd::ForkResult;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::time::Instant;
+
+/// How the child process ended
+#[derive(Debug, Clone)]
+pub enum Exit {
+    /// It exited on its own with this status
+    Code(i32),
+    /// It was killed by this signal
+    Signal(Signal),
+    /// It ended this way after being told to stop for running past its
+    /// timeout
+    Timeout(Box<Exit>),
+}
+
+impl Display for Exit {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Exit::Code(c) => write!(f, "exit status {}", c)?,
+            Exit::Signal(s) => write!(f, "signal {}", s)?,
+            Exit::Timeout(e) => write!(f, "{} after exceeding its timeout", e)?,
+        };
+        Ok(())
+    }
+}
+
+/// The signals that are relayed to the child
+///
+/// These are the ones a user would send to stop, suspend, or resize what they
+/// ran. Since they'd be sending them to this process, they need to be passed
+/// along.
+pub(super) const RELAYED_SIGNALS: [Signal; 7] = [
+    Signal::SIGHUP,
+    Signal::SIGINT,
+    Signal::SIGQUIT,
+    Signal::SIGTERM,
+    Signal::SIGTSTP,
+    Signal::SIGCONT,
+    Signal::SIGWINCH,
+];
+
+/// The PID of the child, for the signal handler to relay to
+///
+/// It's zero until the child has been created. The signal handler can't be
+/// given any state other than through a global.
+pub(super) static CHILD_PID: AtomicI32 = AtomicI32::new(0);
+
+/// Signal handler that relays signals to the child
+///
+/// Signals from the terminal, like `^C`, are sent to the whole foreground
+/// process group. The child already got those, so only signals sent by some
+/// other process are relayed. The child's own signals to us aren't relayed back
+/// either.
+pub(super) extern "C" fn relay(sig: libc::c_int, info: *mut libc::siginfo_t, _: *mut libc::c_void) {
+    let child = CHILD_PID.load(Ordering::SeqCst);
+    if child <= 0 || info.is_null() {
+        return;
+    }
+    // Only codes of zero or less are sent by processes
+    let info = unsafe { &*info };
+    if info.si_code > 0 || unsafe { info.si_pid() } == child {
+        return;
+    }
+    unsafe {
+        libc::kill(child, sig);
+    }
+}
+
+/// Whether the child has run past its timeout
+///
+/// This is set by [expire] the first time it goes off, so it knows to use
+/// `SIGKILL` the next time.
+static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+/// Signal handler for `SIGALRM` that kills the child once it's run too long
+///
+/// The first time, the child is sent `SIGTERM` so it can clean up, and the
+/// alarm is set again for [config::COMMAND_TIMEOUT_GRACE] seconds. If it goes
+/// off again, the child is sent `SIGKILL`.
+extern "C" fn expire(_: libc::c_int) {
+    let child = CHILD_PID.load(Ordering::SeqCst);
+    if child <= 0 {
+        return;
+    }
+    unsafe {
+        if !TIMED_OUT.swap(true, Ordering::SeqCst) {
+            libc::kill(child, libc::SIGTERM);
+            libc::alarm(config::COMMAND_TIMEOUT_GRACE);
+        } else {
+            libc::kill(child, libc::SIGKILL);
+        }
+    }
+}
+
+/// Function to start counting down the `command_timeout`, if there is one
+///
+/// This has to be called after [CHILD_PID] is set.
+pub(super) fn start_timeout(opts: &Options) -> Result<(), Errno> {
+    let timeout = match opts.command_timeout() {
+        Some(t) => t,
+        None => return Ok(()),
+    };
+    set_handlers(&[Signal::SIGALRM], SigHandler::Handler(expire))?;
+    unsafe {
+        libc::alarm(timeout.min(libc::c_uint::MAX as u64) as libc::c_uint);
+    }
+    Ok(())
+}
+
+/// Function to stop counting down once the child has exited
+///
+/// If the child was told to stop for running too long, the [Exit] is wrapped
+/// in [Exit::Timeout] to say so.
+pub(super) fn stop_timeout(exit: Exit) -> Exit {
+    unsafe {
+        libc::alarm(0);
+    }
+    if TIMED_OUT.load(Ordering::SeqCst) {
+        Exit::Timeout(Box::new(exit))
+    } else {
+        exit
+    }
+}
+
+/// Function to set the handler for every signal in `sigs`
+pub(super) fn set_handlers(sigs: &[Signal], handler: SigHandler) -> Result<(), Errno> {
+    let action = SigAction::new(handler, SaFlags::SA_RESTART, SigSet::empty());
+    for sig in sigs {
+        unsafe { signal::sigaction(*sig, &action) }?;
+    }
+    Ok(())
+}
+
+/// Function that runs the [Executable] given in a child process and waits for
+/// it
+///
+/// The child sets the permissions given in the first parameter, then executes
+/// the new binary, just like [exec]. This process keeps its permissions so it
+/// can log how the child ended, along with how long it ran. It then exits with
+/// the same status as the child, or dies of the same signal.
+///
+/// If the child is suspended, this process suspends itself too, so that the
+/// shell sees the job as stopped. When this process is continued, so is the
+/// child.
+///
+/// This process switches back to `root` first, so that it can signal the
+/// child and write the log. Just before it forks, it becomes `root` in every
+/// ID, so the user can't kill it to get out of the timeout. See
+/// [privilege::become_root].
+///
+/// If the [Options] set a `command_timeout`, the child is killed once it's
+/// been running that long. See [start_timeout]. If they ask for a [cgroup],
+/// the child is put in it, and it's removed once the child is done. If they
+/// ask for a `pid` namespace, the child is the first process in it. If they
+/// set `intercept` or `log_subcmds`, everything the child runs is checked or
+/// logged. See [intercept].
+///
+/// This function only returns if the child couldn't be created or waited for.
+pub fn supervise(perm: &Permission, execable: &Executable, opts: &Options) -> RunResult {
+    privilege::regain_root().map_err(|en| RunError::Privilege { errno: en })?;
+
+    // Block the relayed signals until the handlers know who the child is
+    // Otherwise, a signal that came in right after the fork would be lost
+    let mut relayed = SigSet::empty();
+    for sig in RELAYED_SIGNALS {
+        relayed.add(sig);
+    }
+    let mut old_mask = SigSet::empty();
+    signal::sigprocmask(SigmaskHow::SIG_BLOCK, Some(&relayed), Some(&mut old_mask))
+        .map_err(|en| RunError::Signal { errno: en })?;
+    set_handlers(&RELAYED_SIGNALS, SigHandler::SigAction(relay))
+        .map_err(|en| RunError::Signal { errno: en })?;
+
+    // Make the cgroup, if asked
+    let mut cgroup = cgroup::prepare(opts)?;
+
+    // Start checking what it runs, if asked
+    // This has to be done before the pid namespace, so the tracer isn't in it
+    let intercept = intercept::prepare(perm, execable, opts)?;
+
+    // Make the pid namespace, if asked
+    // The child is the first thing in it
+    namespace::unshare_pid(opts).map_err(|en| RunError::Namespace { errno: en })?;
+
+    // Don't let the user signal us once the child is running
+    // This has to be done after the tracer is started, since it needs the user
+    privilege::become_root().map_err(|en| RunError::Privilege { errno: en })?;
+
+    // Time from just before the fork
+    let start = Instant::now();
+    let child = match unsafe { unistd::fork() }.map_err(|en| RunError::Fork { errno: en })? {
+        ForkResult::Parent { child } => child,
+        ForkResult::Child => {
+            // Put the signals back the way they were, then become the executable
+            // Any signals that came in are delivered once they're unblocked
+            let _ = set_handlers(&RELAYED_SIGNALS, SigHandler::SigDfl);
+            let _ = signal::sigprocmask(SigmaskHow::SIG_SETMASK, Some(&old_mask), None);
+            // Don't run outside the cgroup if we were asked to be in one
+            if let Some((_, procs)) = &mut cgroup {
+                if cgroup::join(procs).is_err() {
+                    unsafe { libc::_exit(1) }
+                }
+            }
+            // Don't run unchecked if we were asked to check
+            if let Some(c) = &intercept {
+                if c.install().is_err() {
+                    unsafe { libc::_exit(1) }
+                }
+            }
+            let _ = exec(perm, execable, opts);
+            // We only get here if that failed
+            // Exit the same way the kernel does on failure
+            unsafe { libc::_exit(1) }
+        }
+    };
+    CHILD_PID.store(child.as_raw(), Ordering::SeqCst);
+    if let Some(c) = intercept {
+        c.serve(child);
+    }
+    start_timeout(opts).map_err(|en| RunError::Signal { errno: en })?;
+    signal::sigprocmask(SigmaskHow::SIG_SETMASK, Some(&old_mask), None)
+        .map_err(|en| RunError::Signal { errno: en })?;
+
+    // Wait for the child to finish
+    let exit = loop {
+        match wait::waitpid(child, Some(WaitPidFlag::WUNTRACED)) {
+            Ok(WaitStatus::Exited(_, code)) => break Exit::Code(code),
+            Ok(WaitStatus::Signaled(_, sig, _)) => break Exit::Signal(sig),
+            // Stop with the child, and continue it when we're continued
+            Ok(WaitStatus::Stopped(_, _)) => {
+                let _ = signal::raise(Signal::SIGSTOP);
+                let _ = signal::kill(child, Signal::SIGCONT);
+            }
+            Ok(_) | Err(Errno::EINTR) => (),
+            Err(en) => return Err(RunError::Wait { errno: en }),
+        }
+    };
+    let exit = stop_timeout(exit);
+
+    // See what it used, then clean up
+    let usage = cgroup.as_ref().map(|(c, _)| c.usage()).unwrap_or_default();
+    drop(cgroup);
+
+    // Log how it ended
+    // The executable already ran, so failing to log shouldn't change how we exit
+    #[cfg(feature = "log")]
+    {
+        let _ = (config::EXIT_LOGGER)(execable, perm, child, &exit, start.elapsed(), &usage);
+    }
+    #[cfg(not(feature = "log"))]
+    {
+        let _ = (start, usage);
+    }
+
+    exit_like(exit)
+}
+
+/// Function to exit the same way the child did
+///
+/// If the child was killed by a signal, we kill ourselves with the same signal.
+/// Some signals don't kill by default, so if we're still alive after that, we
+/// exit with the status a shell would give.
+pub(super) fn exit_like(exit: Exit) -> ! {
+    match exit {
+        Exit::Code(c) => std::process::exit(c),
+        Exit::Timeout(e) => exit_like(*e),
+        Exit::Signal(sig) => {
+            // Stop relaying, and make sure the signal isn't caught or blocked
+            CHILD_PID.store(0, Ordering::SeqCst);
+            let _ = unsafe { signal::signal(sig, SigHandler::SigDfl) };
+            let mut mask = SigSet::empty();
+            mask.add(sig);
+            let _ = signal::sigprocmask(SigmaskHow::SIG_UNBLOCK, Some(&mask), None);
+            let _ = signal::raise(sig);
+            std::process::exit(128 + sig as i32)
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Module for protecting the kernel itself
+//!
+//! The kernel runs Set-UID `root`, but it's started by the user. Without any
+//! care, the user could attach a debugger to it, make it dump core with the
+//! policy in memory, or feed it environment variables that change how the
+//! libraries it uses behave. This module has a function that closes those off
+//! before the kernel does anything else.
+
+use crate::config;
+use crate::executable::run::sanitize;
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::resource;
+use nix::sys::resource::Resource;
+use nix::sys::stat;
+use nix::sys::stat::Mode;
+use nix::unistd;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// Function to protect this process from the user that started it
+///
+/// In particular, it
+///   * checks that the kernel is running with an Effective UID of `root`,
+///     since nothing else will work otherwise
+///   * marks the process as not dumpable, so the user can't `ptrace` it and
+///     it won't dump core
+///   * sets the soft core size limit to zero, in case anything turns dumping
+///     back on
+///   * sets the umask to [config::KERNEL_UMASK]
+///   * clears the environment
+///
+/// The user's umask and environment are saved first with [sanitize::save], so
+/// they can be given back to the [Executable][eb], or to the editor for
+/// `sudoedit`. The [Request][rq] itself is built from the
+/// command line, so nothing else is needed from the environment.
+///
+/// This should be called as early as possible, right after
+/// [sanitize::std_fds].
+///
+/// [eb]: crate::executable::Executable
+/// [rq]: crate::request::Request
+pub fn harden() -> Result<(), HardenError> {
+    // Check that we're root
+    // Nothing after this will work if we aren't, so fail out with a clear error
+    if !unistd::geteuid().is_root() {
+        return Err(HardenError::NotRoot);
+    }
+
+    // Stop the user from attaching to us
+    // This also stops core dumps
+    if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } != 0 {
+        return Err(HardenError::Dumpable {
+            errno: Errno::last(),
+        });
+    }
+
+    // Save what the user gave us before changing it
+    sanitize::save().map_err(|en| HardenError::Save { errno: en })?;
+
+    // Make sure we never dump core
+    // Only the soft limit is lowered, so it can be raised again for the
+    //  executable without needing any capabilities
+    let (_, hard) = resource::getrlimit(Resource::RLIMIT_CORE)
+        .map_err(|en| HardenError::CoreLimit { errno: en })?;
+    resource::setrlimit(Resource::RLIMIT_CORE, Some(0), hard)
+        .map_err(|en| HardenError::CoreLimit { errno: en })?;
+
+    // Use a fixed umask
+    stat::umask(Mode::from_bits_truncate(config::KERNEL_UMASK as _));
+
+    // Clear the environment
+    // Collect first since we can't remove while iterating
+    let vars: Vec<_> = std::env::vars_os().map(|(k, _)| k).collect();
+    for k in vars {
+        std::env::remove_var(k);
+    }
+
+    Ok(())
+}
+
+/// Error for [harden]
+///
+/// The kernel might not have been installed Set-UID, or one of the system calls
+/// might fail.
+#[derive(Debug)]
+pub enum HardenError {
+    /// The Effective UID isn't `root`
+    NotRoot,
+    /// An error occurred when marking the process as not dumpable
+    Dumpable { errno: Errno },
+    /// An error occurred when saving the user's umask or limits
+    Save { errno: Errno },
+    /// An error occurred when setting the core size limit
+    CoreLimit { errno: Errno },
+}
+
+impl Display for HardenError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            HardenError::NotRoot => {
+                write!(f, "Not running as root; is the kernel installed Set-UID?")?;
+            }
+            HardenError::Dumpable { errno } => {
+                write!(f, "Could not disable core dumps and tracing: {}", errno)?;
+            }
+            HardenError::Save { errno } => {
+                write!(f, "Could not save the user's process state: {}", errno)?;
+            }
+            HardenError::CoreLimit { errno } => {
+                write!(f, "Could not set the core size limit: {}", errno)?;
+            }
+        };
+        Ok(())
+    }
+}
+
+impl Error for HardenError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
@@ -5,17 +5,43 @@
 //!
 //! [rq]: crate::request::Request
 
-use super::to_write;
 use super::LogResult;
+use super::{exit_to_write, sandbox_to_write, session_to_write, subcommand_to_write, to_write};
 
 use crate::config;
+use crate::executable::run::cgroup::Usage;
+use crate::executable::run::supervise::Exit;
 use crate::executable::Executable;
+use crate::permission::privilege::with_root;
 use crate::permission::verify::VerifyResult;
 use crate::permission::Permission;
 
+use nix::unistd::Pid;
+use std::fs::File;
 use std::fs::OpenOptions;
 use std::fs::Permissions;
+use std::io;
 use std::os::unix::fs::PermissionsExt;
+use std::time::Duration;
+
+/// Function to open the log file for appending
+///
+/// It creates the file at [config::LOG_FILE_PATH] if needed, and sets its mode
+/// to [config::LOG_FILE_PERMS].
+fn open() -> io::Result<File> {
+    // Only root can write to it, so switch while opening it
+    with_root(|| {
+        // Open the file in append mode
+        let f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(config::LOG_FILE_PATH)?;
+
+        // Set the file mode
+        f.set_permissions(Permissions::from_mode(config::LOG_FILE_PERMS))?;
+        Ok(f)
+    })
+}
 
 /// Function to log a given [Request][rq] and [VerifyResult] to a file
 ///
@@ -46,17 +72,70 @@ pub fn to_file(
     req_p: &Permission,
     res: &VerifyResult,
 ) -> LogResult {
-    // Open the file in append mode
-    let mut f = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(config::LOG_FILE_PATH)?;
-
-    // Set the file mode
-    f.set_permissions(Permissions::from_mode(config::LOG_FILE_PERMS))?;
+    // Open the file
+    let mut f = open()?;
 
     // Pass it to the logger and return
     // Don't need to close the file. It will automatically be closed when the
     //  scope ends
     to_write(&mut f, ex, cur_p, req_p, res)
 }
+
+/// Function to log how an [Executable] ended to a file
+///
+/// This is the [ExitLogger][el] counterpart to [to_file]. It writes to the same
+/// file, so the whole story of a [Request][rq] is in one place.
+///
+/// [el]: super::ExitLogger
+/// [rq]: crate::request::Request
+pub fn exit_to_file(
+    ex: &Executable,
+    req_p: &Permission,
+    pid: Pid,
+    exit: &Exit,
+    duration: Duration,
+    usage: &Usage,
+) -> LogResult {
+    let mut f = open()?;
+    exit_to_write(&mut f, ex, req_p, pid, exit, duration, usage)
+}
+
+/// Function to log the start of a recorded session to a file
+///
+/// This is the [SessionLogger][sl] counterpart to [to_file]. It writes to the
+/// same file, so the session ID sits next to the [Request][rq] it's for.
+///
+/// [sl]: super::SessionLogger
+/// [rq]: crate::request::Request
+pub fn session_to_file(ex: &Executable, req_p: &Permission, session: &str) -> LogResult {
+    let mut f = open()?;
+    session_to_write(&mut f, ex, req_p, session)
+}
+
+/// Function to log the sandbox an [Executable] runs in to a file
+///
+/// This is the [SandboxLogger][sl] counterpart to [to_file]. It writes to the
+/// same file, so what confined the [Executable] sits next to its [Request][rq].
+///
+/// [sl]: super::SandboxLogger
+/// [rq]: crate::request::Request
+pub fn sandbox_to_file(ex: &Executable, req_p: &Permission, sandbox: &str) -> LogResult {
+    let mut f = open()?;
+    sandbox_to_write(&mut f, ex, req_p, sandbox)
+}
+
+/// Function to log what an [Executable] runs to a file
+///
+/// This is the [SubcommandLogger][sl] counterpart to [to_file]. It writes to
+/// the same file.
+///
+/// [sl]: super::SubcommandLogger
+pub fn subcommand_to_file(
+    ex: &Executable,
+    req_p: &Permission,
+    sub: &Executable,
+    allowed: bool,
+) -> LogResult {
+    let mut f = open()?;
+    subcommand_to_write(&mut f, ex, req_p, sub, allowed)
+}
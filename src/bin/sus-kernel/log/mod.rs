@@ -10,16 +10,20 @@
 #![cfg(feature = "log")]
 
 pub mod file;
-pub use file::to_file;
+pub use file::{exit_to_file, sandbox_to_file, session_to_file, subcommand_to_file, to_file};
 
 mod write;
-use write::to_write;
+use write::{exit_to_write, sandbox_to_write, session_to_write, subcommand_to_write, to_write};
 
+use crate::executable::run::cgroup::Usage;
+use crate::executable::run::supervise::Exit;
 use crate::executable::Executable;
 use crate::permission::verify::VerifyResult;
 use crate::permission::Permission;
 
+use nix::unistd::Pid;
 use std::error::Error;
+use std::time::Duration;
 
 /// Type for logging functions
 ///
@@ -43,6 +47,37 @@ pub type Logger = fn(&Executable, &Permission, &Permission, &VerifyResult) -> Lo
 pub type AbstractLogger =
     dyn FnMut(&Executable, &Permission, &Permission, &VerifyResult) -> LogResult;
 
+/// Type for functions logging how an [Executable] ended
+///
+/// Some [Runner][rn]s stay around while the [Executable] runs. Once it's done,
+/// they call one of these functions with the [Permission]s it ran as, its PID,
+/// how it [Exit]ed, how long it ran for, and what it used.
+///
+/// [rn]: crate::executable::run::Runner
+pub type ExitLogger = fn(&Executable, &Permission, Pid, &Exit, Duration, &Usage) -> LogResult;
+
+/// Type for functions logging the start of a recorded session
+///
+/// When a session is recorded, the recording is given an ID. These functions
+/// are called with it, as well as the [Executable] and the [Permission]s it
+/// will run as, so the [Request][rq] can be tied to its recording.
+///
+/// [rq]: crate::request::Request
+pub type SessionLogger = fn(&Executable, &Permission, &str) -> LogResult;
+
+/// Type for functions logging the sandbox an [Executable] runs in
+///
+/// When the policy asks for the [Executable] to be confined, these functions
+/// are called with a description of what was applied, just before it starts.
+pub type SandboxLogger = fn(&Executable, &Permission, &str) -> LogResult;
+
+/// Type for functions logging what an [Executable] itself runs
+///
+/// When the policy asks for `intercept` or `log_subcmds`, these functions are
+/// called for every program the [Executable] tries to run, given as the second
+/// [Executable]. The last parameter says whether it was allowed to.
+pub type SubcommandLogger = fn(&Executable, &Permission, &Executable, bool) -> LogResult;
+
 /// Result type for [Logger]s
 ///
 /// [Logger]s may return arbitrary errors in the process of writing the data
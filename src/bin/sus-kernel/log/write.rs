@@ -18,13 +18,28 @@
 use super::LogResult;
 
 use crate::config;
+use crate::executable::run::cgroup::Usage;
+use crate::executable::run::supervise::Exit;
 use crate::executable::Executable;
-use crate::permission::verify::VerifyResult;
+use crate::permission::verify::{VerifyError, VerifyResult};
 use crate::permission::Permission;
 
+use nix::unistd::Pid;
 use std::io::Write;
+use std::time::Duration;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Function to get the current time for the log
+///
+/// Don't fail if we're before the epoch. Instead, return a negation factor to
+/// print a negative number.
+fn timestamp() -> (i128, Duration) {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(res) => (1, res),
+        Err(e) => (-1, e.duration()),
+    }
+}
+
 pub fn to_write<W>(
     w: &mut W,
     ex: &Executable,
@@ -36,12 +51,7 @@ where
     W: Write,
 {
     // Get the Duration since the epoch
-    // Don't fail if we're before the epoch. Instead, just print a negative
-    //  number.
-    let (tstamp_negation, tstamp) = match SystemTime::now().duration_since(UNIX_EPOCH) {
-        Ok(res) => (1, res),
-        Err(e) => (-1, e.duration()),
-    };
+    let (tstamp_negation, tstamp) = timestamp();
 
     // Write out
     // Use different format strings for success and failure. This causes code
@@ -59,6 +69,19 @@ where
             req_perm = req_p,
         ),
 
+        // If the policy couldn't be checked, always say why
+        // The administrator has to fix it, and it says nothing about the user
+        Err(e) if !matches!(e, VerifyError::NotAllowed { .. }) => write!(
+            w,
+            config::LOG_WRITE_POLICY_FAILURE_MSG!(),
+            tstamp_secs = tstamp_negation * (tstamp.as_secs() as i128),
+            tstamp_nanos = tstamp.subsec_nanos(),
+            execable = ex,
+            cur_perm = cur_p,
+            req_perm = req_p,
+            failure = e,
+        ),
+
         // Have different handling based on features
         // If the `log_fail_msg` feature is enabled, we should provide the error
         //  to the log. Otherwise, we should not.
@@ -87,3 +110,130 @@ where
 
     Ok(())
 }
+
+/// Function to log how an [Executable] ended to an existing [Write] object
+///
+/// The format is given by [config::LOG_WRITE_EXIT_MSG].
+pub fn exit_to_write<W>(
+    w: &mut W,
+    ex: &Executable,
+    req_p: &Permission,
+    pid: Pid,
+    exit: &Exit,
+    duration: Duration,
+    usage: &Usage,
+) -> LogResult
+where
+    W: Write,
+{
+    // Get the Duration since the epoch
+    let (tstamp_negation, tstamp) = timestamp();
+
+    // Write out
+    write!(
+        w,
+        config::LOG_WRITE_EXIT_MSG!(),
+        tstamp_secs = tstamp_negation * (tstamp.as_secs() as i128),
+        tstamp_nanos = tstamp.subsec_nanos(),
+        execable = ex,
+        req_perm = req_p,
+        pid = pid,
+        status = exit,
+        duration_secs = duration.as_secs(),
+        duration_nanos = duration.subsec_nanos(),
+        usage = usage,
+    )?;
+
+    Ok(())
+}
+
+/// Function to log the start of a recorded session to an existing [Write]
+/// object
+///
+/// The format is given by [config::LOG_WRITE_SESSION_MSG].
+pub fn session_to_write<W>(
+    w: &mut W,
+    ex: &Executable,
+    req_p: &Permission,
+    session: &str,
+) -> LogResult
+where
+    W: Write,
+{
+    // Get the Duration since the epoch
+    let (tstamp_negation, tstamp) = timestamp();
+
+    // Write out
+    write!(
+        w,
+        config::LOG_WRITE_SESSION_MSG!(),
+        tstamp_secs = tstamp_negation * (tstamp.as_secs() as i128),
+        tstamp_nanos = tstamp.subsec_nanos(),
+        execable = ex,
+        req_perm = req_p,
+        session = session,
+    )?;
+
+    Ok(())
+}
+
+/// Function to log the sandbox an [Executable] runs in to an existing [Write]
+/// object
+///
+/// The format is given by [config::LOG_WRITE_SANDBOX_MSG].
+pub fn sandbox_to_write<W>(
+    w: &mut W,
+    ex: &Executable,
+    req_p: &Permission,
+    sandbox: &str,
+) -> LogResult
+where
+    W: Write,
+{
+    // Get the Duration since the epoch
+    let (tstamp_negation, tstamp) = timestamp();
+
+    // Write out
+    write!(
+        w,
+        config::LOG_WRITE_SANDBOX_MSG!(),
+        tstamp_secs = tstamp_negation * (tstamp.as_secs() as i128),
+        tstamp_nanos = tstamp.subsec_nanos(),
+        execable = ex,
+        req_perm = req_p,
+        sandbox = sandbox,
+    )?;
+
+    Ok(())
+}
+
+/// Function to log what an [Executable] runs to an existing [Write] object
+///
+/// The format is given by [config::LOG_WRITE_SUBCOMMAND_MSG].
+pub fn subcommand_to_write<W>(
+    w: &mut W,
+    ex: &Executable,
+    req_p: &Permission,
+    sub: &Executable,
+    allowed: bool,
+) -> LogResult
+where
+    W: Write,
+{
+    // Get the Duration since the epoch
+    let (tstamp_negation, tstamp) = timestamp();
+
+    // Write out
+    write!(
+        w,
+        config::LOG_WRITE_SUBCOMMAND_MSG!(),
+        tstamp_secs = tstamp_negation * (tstamp.as_secs() as i128),
+        tstamp_nanos = tstamp.subsec_nanos(),
+        status = if allowed { "SUBCMD" } else { "SUBCMD-DENIED" },
+        execable = ex,
+        req_perm = req_p,
+        subcmd = sub,
+    )?;
+
+    Ok(())
+}
@@ -7,12 +7,11 @@
 
 mod config;
 mod executable;
+mod harden;
 mod log;
 mod permission;
 mod request;
-
-use crate::permission::verify::Verifier;
-use permission::verify::from_sudoers;
+mod setting;
 
 #[cfg(feature = "log")]
 use log::AbstractLogger;
@@ -46,28 +45,31 @@ fn main() {
     std::panic::set_hook(Box::new(|_| {
         std::process::exit(1);
     }));
+    // Make sure the standard streams are open before we open anything
+    executable::run::sanitize::std_fds().unwrap();
+    // Protect ourselves from the user
+    // This is the one failure worth explaining, since it's usually a bad install
+    if let Err(e) = harden::harden() {
+        eprintln!("sus-kernel: {}", e);
+        std::process::exit(1);
+    }
+    // Run as the user until we need root
+    permission::privilege::drop_root().unwrap();
 
     // Get the executable to run
     let executable = config::EXECUTABLE_FACTORY().unwrap();
     // Get the current and requested permissions
     let current_permissions = config::CURRENT_PERMISSION_FACTORY().unwrap();
     let requested_permissions = config::REQUESTED_PERMISSION_FACTORY().unwrap();
+    // Get what else the user asked for
+    let settings = config::SETTINGS_FACTORY().unwrap();
     // Put the runner in a box
     let runner = Box::new(config::RUNNER);
 
     // Create the verifiers
-    // We need to clone them from the slice reference
-    let verifiers = {
-        // Do the clone
-        let vfers = from_sudoers();
-        // Create and return
-        // Box everything up as well
-        // See: https://newbedev.com/how-to-create-a-vector-of-boxed-closures-in-rust
-        vfers
-            .into_iter()
-            .map(|f| Box::new(f) as Box<Verifier>)
-            .collect()
-    };
+    let verifiers = config::VERIFIERS_FACTORY();
+    // Put the authenticator in a box
+    let authenticator = Box::new(config::AUTHENTICATOR);
 
     // Create the request
     let req = Request {
@@ -75,7 +77,9 @@ fn main() {
         executable,
         current_permissions,
         requested_permissions,
+        settings,
         verifiers,
+        authenticator,
         runner,
         // Logging functionality
         #[cfg(feature = "log")]
@@ -83,5 +87,9 @@ fn main() {
     };
 
     // Service the request
-    req.service().unwrap();
+    // It only returns if it failed, and the panic handler exits quietly
+    match req.service() {
+        Ok(never) => match never {},
+        Err(e) => panic!("{}", e),
+    }
 }
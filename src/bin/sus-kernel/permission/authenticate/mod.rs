@@ -0,0 +1,159 @@
+//! Module for making sure the user is who they say they are
+//!
+//! Like `sudo`, the kernel can ask for the user's own password before running
+//! anything. Unlike `sudo`, it only does if the [Options] that allowed the
+//! request turn on `authenticate`, so that existing policies keep working
+//! without a password. `root` is never asked, since it could run anything
+//! anyway.
+//!
+//! How the password is asked for depends on the [Settings]. By default, it's
+//! read from the terminal with echo turned off. With `sus -S`, it's read from
+//! standard input instead, and with `sus -A`, a helper program is run as the
+//! user to get it. With `sus -n`, it isn't asked for at all, so the request
+//! fails. See [prompt] for how each of those is done, and [shadow] for how the
+//! password is checked.
+
+pub mod prompt;
+pub mod shadow;
+
+use super::verify::Options;
+use super::Permission;
+use crate::config;
+use crate::setting::Settings;
+use prompt::Source;
+
+use nix::errno::Errno;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::thread;
+use std::time::Duration;
+
+/// Type for authentication functions
+///
+/// These functions take in the user's current [Permission]s and the ones they
+/// requested, along with the [Options] from whatever allowed the request and
+/// the [Settings] the user asked for. They return whether the user proved who
+/// they are, or didn't need to.
+pub type Authenticator = fn(&Permission, &Permission, &Options, &Settings) -> AuthResult;
+/// Abstract supertype of [Authenticator]
+///
+/// Like the other function types in this crate, this exists so that
+/// [Authenticator]s can be constructed at runtime, which might be useful for
+/// testing.
+pub type AbstractAuthenticator =
+    dyn FnMut(&Permission, &Permission, &Options, &Settings) -> AuthResult;
+
+/// Convinience type for the result of an [Authenticator]
+pub type AuthResult = Result<(), AuthError>;
+
+/// Function to check the user's password, if the [Options] ask for it
+///
+/// The user gets [PASSWD_TRIES][pt] tries. After each wrong one, there's a
+/// [PASSWD_FAIL_DELAY][pd] before they're told to try again. If it fails, the
+/// reason is printed, since the user can't do anything about it otherwise.
+///
+/// [pt]: config::PASSWD_TRIES
+/// [pd]: config::PASSWD_FAIL_DELAY
+pub fn from_password(
+    current: &Permission,
+    requested: &Permission,
+    opts: &Options,
+    settings: &Settings,
+) -> AuthResult {
+    if !opts.authenticate() || current.uid.is_root() {
+        return Ok(());
+    }
+    let res = authenticate(current, requested, opts, settings);
+    if let Err(e) = &res {
+        eprintln!("sus-kernel: {}", e);
+    }
+    res
+}
+
+/// Function to do the work of [from_password]
+fn authenticate(
+    current: &Permission,
+    requested: &Permission,
+    opts: &Options,
+    settings: &Settings,
+) -> AuthResult {
+    let source = Source::from_settings(settings);
+    if let Source::NonInteractive = source {
+        return Err(AuthError::Required);
+    }
+    let hash = shadow::Hash::lookup(current.uid)?;
+    let text = prompt::expand(
+        &prompt::prompt(settings).unwrap_or_else(|| config::DEFAULT_PASSPROMPT.to_string()),
+        current,
+        requested,
+    );
+
+    for attempt in 1..=config::PASSWD_TRIES {
+        let password = source.read(&text, opts)?;
+        if hash.matches(&password) {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_secs(config::PASSWD_FAIL_DELAY));
+        if attempt < config::PASSWD_TRIES {
+            eprintln!("Sorry, try again.");
+        }
+    }
+    Err(AuthError::Incorrect {
+        tries: config::PASSWD_TRIES,
+    })
+}
+
+/// Error for [Authenticator]s
+///
+/// The user may not have given the right password, or may not have been asked
+/// at all. It may also have been impossible to ask them, or to check what they
+/// gave. This `enum` provides the possibilities.
+#[derive(Debug)]
+pub enum AuthError {
+    /// A password is needed, but the user asked not to be prompted
+    Required,
+    /// There's no terminal to read the password from
+    NoTerminal,
+    /// The user didn't give a password before `passwd_timeout` ran out
+    Timeout,
+    /// A signal, like `SIGINT`, stopped the prompt
+    Interrupted,
+    /// Nothing was read, like at the end of standard input
+    NoPassword,
+    /// The password was wrong every time
+    Incorrect { tries: u32 },
+    /// The askpass program didn't give a password
+    Askpass { path: String },
+    /// The user's password couldn't be found
+    NoEntry,
+    /// Asking for or checking the password failed
+    Syscall { errno: Errno },
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            AuthError::Required => write!(f, "a password is required"),
+            AuthError::NoTerminal => write!(
+                f,
+                "a terminal is required to read the password; \
+                 either use -S to read it from standard input, or -A to use an askpass helper"
+            ),
+            AuthError::Timeout => write!(f, "timed out reading password"),
+            AuthError::Interrupted => write!(f, "password prompt interrupted"),
+            AuthError::NoPassword => write!(f, "no password was provided"),
+            AuthError::Incorrect { tries: 1 } => write!(f, "1 incorrect password attempt"),
+            AuthError::Incorrect { tries } => {
+                write!(f, "{} incorrect password attempts", tries)
+            }
+            AuthError::Askpass { path } => {
+                write!(f, "unable to get the password from {}", path)
+            }
+            AuthError::NoEntry => write!(f, "unable to find the password for this user"),
+            AuthError::Syscall { errno } => write!(f, "unable to read the password: {}", errno),
+        }
+    }
+}
+
+impl Error for AuthError {}
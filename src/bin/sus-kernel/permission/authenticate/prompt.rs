@@ -0,0 +1,526 @@
+//! Module for asking the user for their password
+//!
+//! There are a few places the password can come from, picked by the
+//! [Settings]. See [Source] for them. However it's read, it's given up on after
+//! the `passwd_timeout` from the [Options], or [DEFAULT_PASSWD_TIMEOUT][dt] if
+//! there isn't one. Zero means to wait forever.
+//!
+//! On a terminal, echo is turned off while the password is typed. If the
+//! [Options] set `pwfeedback`, a `*` is printed for each character instead, and
+//! the erase and kill characters work as usual. The terminal is always put
+//! back the way it was, even if the prompt is interrupted by a signal.
+//!
+//! [dt]: crate::config::DEFAULT_PASSWD_TIMEOUT
+
+use super::AuthError;
+use crate::config;
+use crate::executable::run::sanitize;
+use crate::permission::privilege;
+use crate::permission::verify::Options;
+use crate::permission::Permission;
+use crate::setting::{Setting, Settings};
+
+use nix::errno::Errno;
+use nix::fcntl;
+use nix::fcntl::OFlag;
+use nix::libc;
+use nix::poll;
+use nix::poll::{PollFd, PollFlags};
+use nix::sys::signal;
+use nix::sys::signal::{SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::sys::stat::Mode;
+use nix::sys::termios;
+use nix::sys::termios::{LocalFlags, SetArg, SpecialCharacterIndices, Termios};
+use nix::sys::wait;
+use nix::sys::wait::WaitStatus;
+use nix::unistd;
+use nix::unistd::{ForkResult, Uid};
+use std::ffi::{CStr, CString};
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant};
+
+/// The longest password that's read
+///
+/// Anything past this is dropped.
+const MAX_PASSWORD: usize = 1024;
+
+/// The signals that stop the prompt
+const INTERRUPTS: [Signal; 5] = [
+    Signal::SIGINT,
+    Signal::SIGQUIT,
+    Signal::SIGTSTP,
+    Signal::SIGHUP,
+    Signal::SIGTERM,
+];
+
+/// The last of the [INTERRUPTS] caught, or zero if there hasn't been one
+static INTERRUPTED: AtomicI32 = AtomicI32::new(0);
+
+/// A password the user gave
+///
+/// It's kept with a `NUL` on the end, so it can be handed to `crypt` without
+/// being copied. It's zeroed once it's dropped.
+pub struct Password(Vec<u8>);
+
+impl Password {
+    fn new() -> Password {
+        let mut buf = Vec::with_capacity(MAX_PASSWORD + 1);
+        buf.push(0);
+        Password(buf)
+    }
+
+    fn len(&self) -> usize {
+        self.0.len() - 1
+    }
+
+    /// Function to add a character, unless it's `NUL` or there's no room
+    fn push(&mut self, b: u8) -> bool {
+        if b == 0 || self.len() >= MAX_PASSWORD {
+            return false;
+        }
+        let end = self.len();
+        self.0.insert(end, b);
+        true
+    }
+
+    fn pop(&mut self) -> bool {
+        if self.len() == 0 {
+            return false;
+        }
+        let end = self.len() - 1;
+        self.0[end] = 0;
+        self.0.remove(end);
+        true
+    }
+
+    fn clear(&mut self) {
+        while self.pop() {}
+    }
+
+    pub fn as_cstr(&self) -> &CStr {
+        CStr::from_bytes_with_nul(&self.0).unwrap()
+    }
+}
+
+impl Drop for Password {
+    fn drop(&mut self) {
+        // Write through a pointer, so this isn't optimized away
+        for b in self.0.iter_mut() {
+            unsafe { std::ptr::write_volatile(b, 0) };
+        }
+    }
+}
+
+/// Where to get the password from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// Don't ask at all, as with `sus -n`
+    NonInteractive,
+    /// Run this program as the user, and read what it prints, as with `sus -A`
+    ///
+    /// It's given the prompt as its only argument.
+    Askpass(String),
+    /// Read from standard input, printing the prompt on standard error, as with
+    /// `sus -S`
+    Stdin,
+    /// Read from the controlling terminal
+    Terminal,
+}
+
+impl Source {
+    /// Function to pick a [Source] from what the user asked for
+    ///
+    /// If the user asked for more than one, not being asked at all wins, then
+    /// the askpass program, then standard input.
+    pub fn from_settings(settings: &Settings) -> Source {
+        let has = |f: &dyn Fn(&Setting) -> bool| settings.0.iter().any(f);
+        if has(&|s| *s == Setting::NonInteractive(true)) {
+            return Source::NonInteractive;
+        }
+        let askpass = settings.0.iter().rev().find_map(|s| match s {
+            Setting::Askpass(p) => Some(p.clone()),
+            _ => None,
+        });
+        if let Some(p) = askpass {
+            return Source::Askpass(p);
+        }
+        if has(&|s| *s == Setting::Stdin(true)) {
+            return Source::Stdin;
+        }
+        Source::Terminal
+    }
+
+    /// Function to ask for the password once
+    ///
+    /// The prompt should already be [expand]ed.
+    pub fn read(&self, prompt: &str, opts: &Options) -> Result<Password, AuthError> {
+        let timeout = opts
+            .passwd_timeout()
+            .unwrap_or(config::DEFAULT_PASSWD_TIMEOUT);
+        let deadline = if timeout == 0 {
+            None
+        } else {
+            Some(Instant::now() + Duration::from_secs(timeout))
+        };
+
+        let _handlers = Handlers::install().map_err(|en| AuthError::Syscall { errno: en })?;
+        match self {
+            Source::NonInteractive => Err(AuthError::Required),
+            Source::Askpass(path) => askpass(path, prompt, deadline),
+            Source::Stdin => {
+                write_all(libc::STDERR_FILENO, prompt.as_bytes());
+                let feedback = opts.pwfeedback() && unistd::isatty(libc::STDIN_FILENO) == Ok(true);
+                let res =
+                    from_terminal(libc::STDIN_FILENO, libc::STDERR_FILENO, feedback, deadline);
+                match res {
+                    // It might not be a terminal, and that's fine
+                    Err(AuthError::NoTerminal) => read_line(libc::STDIN_FILENO, deadline, None),
+                    _ => res,
+                }
+            }
+            Source::Terminal => {
+                let tty = fcntl::open(
+                    "/dev/tty",
+                    OFlag::O_RDWR | OFlag::O_NOCTTY | OFlag::O_CLOEXEC,
+                    Mode::empty(),
+                )
+                .map_err(|_| AuthError::NoTerminal)?;
+                write_all(tty, prompt.as_bytes());
+                let res = from_terminal(tty, tty, opts.pwfeedback(), deadline);
+                let _ = unistd::close(tty);
+                res
+            }
+        }
+    }
+}
+
+/// Function to get the prompt the user asked for, if they did
+pub fn prompt(settings: &Settings) -> Option<String> {
+    settings.0.iter().rev().find_map(|s| match s {
+        Setting::Prompt(p) => Some(p.clone()),
+        _ => None,
+    })
+}
+
+/// Function to fill in the escapes in a prompt
+///
+/// These are the same as `sudo`'s. `%u` and `%p` are the user's name, since
+/// it's their password being asked for. `%U` is the name of the user the
+/// command will run as. `%h` is the host name up to the first `.`, and `%H` is
+/// all of it. `%%` is a single `%`. Anything else is left alone.
+pub fn expand(prompt: &str, current: &Permission, requested: &Permission) -> String {
+    let name = |uid: Uid| {
+        users::get_user_by_uid(uid.as_raw()).map_or(format!("#{}", uid), |u| {
+            u.name().to_string_lossy().into_owned()
+        })
+    };
+    let mut buf = [0u8; 256];
+    let host = unistd::gethostname(&mut buf)
+        .map(|h| h.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut ret = String::new();
+    let mut chars = prompt.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            ret.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('u') | Some('p') => ret.push_str(&name(current.uid)),
+            Some('U') => ret.push_str(&name(requested.uid)),
+            Some('h') => ret.push_str(host.split('.').next().unwrap_or("")),
+            Some('H') => ret.push_str(&host),
+            Some('%') => ret.push('%'),
+            Some(o) => {
+                ret.push('%');
+                ret.push(o);
+            }
+            None => ret.push('%'),
+        }
+    }
+    ret
+}
+
+/// Handlers for the [INTERRUPTS], put back the way they were when dropped
+///
+/// They just note that the signal came in. They're installed without
+/// `SA_RESTART`, so whatever was waiting stops with [Errno::EINTR] and can see
+/// that.
+struct Handlers(Vec<(Signal, SigAction)>);
+
+impl Handlers {
+    fn install() -> Result<Handlers, Errno> {
+        extern "C" fn note(sig: libc::c_int) {
+            INTERRUPTED.store(sig, Ordering::SeqCst);
+        }
+        INTERRUPTED.store(0, Ordering::SeqCst);
+        let action = SigAction::new(SigHandler::Handler(note), SaFlags::empty(), SigSet::empty());
+        let mut ret = Handlers(Vec::new());
+        for sig in INTERRUPTS.iter() {
+            let old = unsafe { signal::sigaction(*sig, &action) }?;
+            ret.0.push((*sig, old));
+        }
+        Ok(ret)
+    }
+}
+
+impl Drop for Handlers {
+    fn drop(&mut self) {
+        for (sig, old) in self.0.iter() {
+            let _ = unsafe { signal::sigaction(*sig, old) };
+        }
+    }
+}
+
+/// A terminal with echo turned off, put back the way it was when dropped
+struct Quiet {
+    fd: RawFd,
+    old: Termios,
+}
+
+impl Drop for Quiet {
+    fn drop(&mut self) {
+        let _ = termios::tcsetattr(self.fd, SetArg::TCSADRAIN, &self.old);
+    }
+}
+
+/// How to show what's being typed with `pwfeedback`
+struct Feedback {
+    out: RawFd,
+    erase: u8,
+    kill: u8,
+}
+
+/// Function to read a password from a terminal
+///
+/// The prompt should already have been written to `out`. It fails with
+/// [AuthError::NoTerminal] if `fd` isn't a terminal, before anything is read.
+fn from_terminal(
+    fd: RawFd,
+    out: RawFd,
+    feedback: bool,
+    deadline: Option<Instant>,
+) -> Result<Password, AuthError> {
+    let old = termios::tcgetattr(fd).map_err(|_| AuthError::NoTerminal)?;
+    let mut quiet = old.clone();
+    quiet
+        .local_flags
+        .remove(LocalFlags::ECHO | LocalFlags::ECHOE | LocalFlags::ECHOK | LocalFlags::ECHONL);
+    if feedback {
+        // Get each character as it's typed, so it can be shown
+        quiet.local_flags.remove(LocalFlags::ICANON);
+        quiet.control_chars[SpecialCharacterIndices::VMIN as usize] = 1;
+        quiet.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+    }
+    termios::tcsetattr(fd, SetArg::TCSADRAIN, &quiet)
+        .map_err(|en| AuthError::Syscall { errno: en })?;
+    let guard = Quiet { fd, old };
+
+    let feedback = if feedback {
+        Some(Feedback {
+            out,
+            erase: guard.old.control_chars[SpecialCharacterIndices::VERASE as usize],
+            kill: guard.old.control_chars[SpecialCharacterIndices::VKILL as usize],
+        })
+    } else {
+        None
+    };
+    let res = read_line(fd, deadline, feedback.as_ref());
+    drop(guard);
+    // The newline wasn't echoed either
+    write_all(out, b"\n");
+    res
+}
+
+/// Function to read a line, without the newline
+///
+/// This reads a byte at a time, so nothing past the line is taken from `fd`.
+/// It stops at a newline, a carriage return, or the end of the file. If
+/// nothing at all was read before the end, it fails with
+/// [AuthError::NoPassword].
+fn read_line(
+    fd: RawFd,
+    deadline: Option<Instant>,
+    feedback: Option<&Feedback>,
+) -> Result<Password, AuthError> {
+    let mut ret = Password::new();
+    let mut any = false;
+    loop {
+        wait_readable(fd, deadline)?;
+        let mut b = [0u8; 1];
+        match unistd::read(fd, &mut b) {
+            Ok(0) if !any => return Err(AuthError::NoPassword),
+            Ok(0) => return Ok(ret),
+            Ok(_) => any = true,
+            Err(Errno::EINTR) | Err(Errno::EAGAIN) => continue,
+            Err(en) => return Err(AuthError::Syscall { errno: en }),
+        }
+        let b = b[0];
+        if b == b'\n' || b == b'\r' {
+            return Ok(ret);
+        }
+        match feedback {
+            None => {
+                ret.push(b);
+            }
+            // Without canonical mode, the end of file character is read too
+            Some(_) if b == 0x04 && ret.len() == 0 => return Err(AuthError::NoPassword),
+            Some(_) if b == 0x04 => return Ok(ret),
+            Some(f) if b == f.erase || b == 0x08 || b == 0x7f => {
+                if ret.pop() {
+                    write_all(f.out, b"\x08 \x08");
+                }
+            }
+            Some(f) if b == f.kill => {
+                for _ in 0..ret.len() {
+                    write_all(f.out, b"\x08 \x08");
+                }
+                ret.clear();
+            }
+            Some(f) => {
+                if ret.push(b) {
+                    write_all(f.out, b"*");
+                }
+            }
+        }
+    }
+}
+
+/// Function to wait until `fd` can be read from
+///
+/// It fails with [AuthError::Timeout] once the `deadline` passes, or with
+/// [AuthError::Interrupted] if one of the [INTERRUPTS] comes in.
+fn wait_readable(fd: RawFd, deadline: Option<Instant>) -> Result<(), AuthError> {
+    loop {
+        if INTERRUPTED.load(Ordering::SeqCst) != 0 {
+            return Err(AuthError::Interrupted);
+        }
+        let timeout = match deadline {
+            None => -1,
+            Some(d) => {
+                let left = d.saturating_duration_since(Instant::now());
+                if left == Duration::from_secs(0) {
+                    return Err(AuthError::Timeout);
+                }
+                left.as_millis().min(libc::c_int::MAX as u128) as libc::c_int
+            }
+        };
+        let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+        match poll::poll(&mut fds, timeout) {
+            Ok(0) | Err(Errno::EINTR) => continue,
+            Ok(_) => return Ok(()),
+            Err(en) => return Err(AuthError::Syscall { errno: en }),
+        }
+    }
+}
+
+/// Function to write everything, ignoring errors
+///
+/// This is only used for prompts and feedback, which the user can do without.
+fn write_all(fd: RawFd, mut buf: &[u8]) {
+    while !buf.is_empty() {
+        match unistd::write(fd, buf) {
+            Ok(0) => return,
+            Ok(n) => buf = &buf[n..],
+            Err(Errno::EINTR) => continue,
+            Err(_) => return,
+        }
+    }
+}
+
+/// Function to get the password from an askpass program
+///
+/// The program is run as the user, with the environment they ran `sus` with,
+/// and the prompt as its only argument. The first line it prints is taken as
+/// the password. If it prints nothing, or fails, or runs past the `deadline`,
+/// so does this.
+fn askpass(path: &str, prompt: &str, deadline: Option<Instant>) -> Result<Password, AuthError> {
+    let failed = || AuthError::Askpass {
+        path: path.to_string(),
+    };
+    let c_path = CString::new(path).map_err(|_| failed())?;
+    let c_prompt = CString::new(prompt).map_err(|_| failed())?;
+    let (rd, wr) =
+        unistd::pipe2(OFlag::O_CLOEXEC).map_err(|en| AuthError::Syscall { errno: en })?;
+
+    let child = match unsafe { unistd::fork() } {
+        Ok(ForkResult::Child) => {
+            let _ = unistd::close(rd);
+            // Become the user for good, so the program can't get root back
+            let res = privilege::regain_root()
+                .and_then(|_| unistd::setgid(unistd::getgid()))
+                .and_then(|_| unistd::setuid(unistd::getuid()))
+                .and_then(|_| unistd::dup2(wr, libc::STDOUT_FILENO));
+            if res.is_ok() {
+                let _ = unistd::execve(&c_path, &[&c_path, &c_prompt], &sanitize::saved_env());
+            }
+            unsafe { libc::_exit(127) }
+        }
+        Ok(ForkResult::Parent { child }) => child,
+        Err(en) => {
+            let _ = unistd::close(rd);
+            let _ = unistd::close(wr);
+            return Err(AuthError::Syscall { errno: en });
+        }
+    };
+    let _ = unistd::close(wr);
+
+    let res = read_line(rd, deadline, None);
+    let _ = unistd::close(rd);
+    if res.is_err() {
+        let _ = signal::kill(child, Signal::SIGTERM);
+    }
+    let ok = loop {
+        match wait::waitpid(child, None) {
+            Ok(WaitStatus::Exited(_, 0)) => break true,
+            Ok(WaitStatus::Exited(_, _)) | Ok(WaitStatus::Signaled(_, _, _)) => break false,
+            Ok(_) | Err(Errno::EINTR) => continue,
+            Err(_) => break false,
+        }
+    };
+    match res {
+        Ok(p) if ok => Ok(p),
+        Ok(_) | Err(AuthError::NoPassword) => Err(failed()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::unistd::Gid;
+    use std::collections::HashSet;
+
+    fn as_uid(uid: u32) -> Permission {
+        Permission {
+            uid: Uid::from_raw(uid),
+            primary_gid: Gid::from_raw(0),
+            secondary_gids: HashSet::new(),
+            capabilities: None,
+        }
+    }
+
+    #[test]
+    fn escapes_are_expanded() {
+        let mut buf = [0u8; 256];
+        let host = unistd::gethostname(&mut buf)
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        let short = host.split('.').next().unwrap();
+        // No user should have this UID, so it's shown as a number
+        let cur = as_uid(0);
+        let req = as_uid(4_000_000);
+        let expand = |p: &str| expand(p, &cur, &req);
+
+        assert_eq!(expand("%u %p"), "root root");
+        assert_eq!(expand("as %U"), "as #4000000");
+        assert_eq!(expand("%h|%H"), format!("{}|{}", short, host));
+        assert_eq!(expand("100%%"), "100%");
+        assert_eq!(expand("at the end %"), "at the end %");
+        assert_eq!(expand("%x%y %"), "%x%y %");
+        assert_eq!(expand("no escapes"), "no escapes");
+    }
+}
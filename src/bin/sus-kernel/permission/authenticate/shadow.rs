@@ -0,0 +1,121 @@
+//! Module for checking passwords against the system's password database
+//!
+//! The user's hash is looked up in the shadow database, which needs `root` to
+//! read. If they don't have an entry there, the one in the `passwd` database is
+//! used instead. What they typed is hashed the same way with `crypt`, and the
+//! two are compared.
+//!
+//! An empty hash, or a locked one like `!` or `*`, never matches. So, users
+//! without a password can't authenticate at all.
+
+use super::prompt::Password;
+use super::AuthError;
+use crate::permission::privilege;
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::unistd::Uid;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+#[link(name = "crypt")]
+extern "C" {
+    fn crypt(phrase: *const c_char, setting: *const c_char) -> *mut c_char;
+}
+
+/// How much room to give `getspnam_r` and `getpwuid_r` for strings
+const BUFFER_SIZE: usize = 16 * 1024;
+
+/// A user's password hash
+#[derive(Debug)]
+pub struct Hash(CString);
+
+impl Hash {
+    /// Function to find the hash for a user
+    pub fn lookup(uid: Uid) -> Result<Hash, AuthError> {
+        let (name, passwd) = passwd_entry(uid)?;
+        let shadow = privilege::with_root(|| shadow_entry(&name))?;
+        Ok(Hash(shadow.unwrap_or(passwd)))
+    }
+
+    /// Function to see if a [Password] is the one this is a hash of
+    ///
+    /// The comparison takes the same time no matter where the two differ.
+    pub fn matches(&self, password: &Password) -> bool {
+        let hash = self.0.as_bytes();
+        if hash.is_empty() || hash[0] == b'!' || hash[0] == b'*' {
+            return false;
+        }
+        let res = unsafe { crypt(password.as_cstr().as_ptr(), self.0.as_ptr()) };
+        if res.is_null() {
+            return false;
+        }
+        let res = unsafe { CStr::from_ptr(res) }.to_bytes();
+        res.len() == hash.len()
+            && res
+                .iter()
+                .zip(hash.iter())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+/// Function to get a user's name and the hash from their `passwd` entry
+fn passwd_entry(uid: Uid) -> Result<(CString, CString), AuthError> {
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as c_char; BUFFER_SIZE];
+    let mut res = ptr::null_mut();
+    let ret = unsafe {
+        libc::getpwuid_r(
+            uid.as_raw(),
+            &mut pwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut res,
+        )
+    };
+    if ret != 0 {
+        return Err(AuthError::Syscall {
+            errno: Errno::from_i32(ret),
+        });
+    }
+    if res.is_null() || pwd.pw_name.is_null() || pwd.pw_passwd.is_null() {
+        return Err(AuthError::NoEntry);
+    }
+    let name = unsafe { CStr::from_ptr(pwd.pw_name) }.to_owned();
+    let passwd = unsafe { CStr::from_ptr(pwd.pw_passwd) }.to_owned();
+    Ok((name, passwd))
+}
+
+/// Function to get the hash from a user's shadow entry, if they have one
+///
+/// This has to be called as `root`.
+fn shadow_entry(name: &CStr) -> Result<Option<CString>, AuthError> {
+    let mut spwd: libc::spwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as c_char; BUFFER_SIZE];
+    let mut res = ptr::null_mut();
+    let ret = unsafe {
+        libc::getspnam_r(
+            name.as_ptr(),
+            &mut spwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut res,
+        )
+    };
+    // Not having a shadow entry, or a shadow database, isn't an error
+    match ret {
+        0 => (),
+        libc::ENOENT => return Ok(None),
+        en => {
+            return Err(AuthError::Syscall {
+                errno: Errno::from_i32(en),
+            })
+        }
+    }
+    if res.is_null() || spwd.sp_pwdp.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(unsafe { CStr::from_ptr(spwd.sp_pwdp) }.to_owned()))
+}
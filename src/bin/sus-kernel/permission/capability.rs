@@ -0,0 +1,218 @@
+//! Module for Linux capabilities
+//!
+//! Instead of full `root`, a rule can grant just the [Capability]s a command
+//! needs. The command then runs as the target user with only those, and with
+//! the securebits locked so that neither it nor anything it runs can get the
+//! rest back. See [restrict] for how that's done.
+
+use nix::errno::Errno;
+use nix::libc;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// The names of the capabilities, in order of their numbers
+///
+/// These are from `linux/capability.h`, without the `CAP_` prefix.
+const NAMES: [&str; 41] = [
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+/// A single Linux capability
+///
+/// It's written as its name, like `CAP_NET_BIND_SERVICE`. The `CAP_` prefix is
+/// optional, and case doesn't matter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Capability(u32);
+
+impl Capability {
+    /// Function to get the [Capability] with a given name
+    pub fn from_name(name: &str) -> Option<Capability> {
+        let name = name.to_ascii_lowercase();
+        let name = name.strip_prefix("cap_").unwrap_or(&name);
+        NAMES
+            .iter()
+            .position(|n| *n == name)
+            .map(|i| Capability(i as u32))
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "cap_{}", NAMES[self.0 as usize])
+    }
+}
+
+impl Serialize for Capability {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.to_string().to_ascii_uppercase())
+    }
+}
+
+impl<'de> Deserialize<'de> for Capability {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(d)?;
+        Capability::from_name(&s)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown capability `{}`", s)))
+    }
+}
+
+// libc doesn't have these, so they're from `linux/capability.h` and
+// `linux/securebits.h`
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+const SECBIT_NOROOT: libc::c_ulong = 1 << 0;
+const SECBIT_NOROOT_LOCKED: libc::c_ulong = 1 << 1;
+const SECBIT_NO_SETUID_FIXUP: libc::c_ulong = 1 << 2;
+const SECBIT_NO_SETUID_FIXUP_LOCKED: libc::c_ulong = 1 << 3;
+const SECBIT_KEEP_CAPS_LOCKED: libc::c_ulong = 1 << 5;
+const SECBIT_NO_CAP_AMBIENT_RAISE: libc::c_ulong = 1 << 6;
+const SECBIT_NO_CAP_AMBIENT_RAISE_LOCKED: libc::c_ulong = 1 << 7;
+
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Function to set this process's capability sets
+///
+/// `inheritable` is the set of [Capability]s given, and the `permitted` and
+/// `effective` sets are those plus `extra`.
+fn capset(caps: &[Capability], extra: &[Capability]) -> Result<(), Errno> {
+    let header = CapHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [CapData::default(); 2];
+    for c in caps {
+        let (word, bit) = ((c.0 / 32) as usize, 1 << (c.0 % 32));
+        data[word].inheritable |= bit;
+        data[word].permitted |= bit;
+        data[word].effective |= bit;
+    }
+    for c in extra {
+        let (word, bit) = ((c.0 / 32) as usize, 1 << (c.0 % 32));
+        data[word].permitted |= bit;
+        data[word].effective |= bit;
+    }
+    Errno::result(unsafe { libc::syscall(libc::SYS_capset, &header, data.as_ptr()) })?;
+    Ok(())
+}
+
+/// Function to lock the securebits before switching away from `root`
+///
+/// This has to be called as `root`, before the user is changed. It keeps the
+/// switch from dropping capabilities, so that [restrict] can pick which ones
+/// stay. It also makes sure that being `root` doesn't mean having every
+/// capability, even after `execve`. All of these are locked, so they can't be
+/// turned off again.
+pub fn lock() -> Result<(), Errno> {
+    let bits = SECBIT_NOROOT
+        | SECBIT_NOROOT_LOCKED
+        | SECBIT_NO_SETUID_FIXUP
+        | SECBIT_NO_SETUID_FIXUP_LOCKED
+        | SECBIT_KEEP_CAPS_LOCKED;
+    Errno::result(unsafe { libc::prctl(libc::PR_SET_SECUREBITS, bits, 0, 0, 0) })?;
+    Ok(())
+}
+
+/// Function to keep only the given [Capability]s
+///
+/// This is called after the user is changed, with the securebits from [lock].
+/// It drops everything else from the bounding set, then raises the given
+/// [Capability]s in the ambient set, so that they're kept across `execve`.
+/// Finally, it stops any more from being raised and drops the rest of what
+/// this process has.
+pub fn restrict(caps: &[Capability]) -> Result<(), Errno> {
+    let setpcap = Capability::from_name("setpcap").unwrap();
+
+    // Keep setpcap until the end, since everything here needs it
+    capset(caps, &[setpcap])?;
+
+    // Drop the rest of the bounding set
+    // Stop at the first one the kernel doesn't know
+    for raw in 0..64 {
+        if caps.iter().any(|c| c.0 == raw) {
+            continue;
+        }
+        match Errno::result(unsafe {
+            libc::prctl(libc::PR_CAPBSET_DROP, raw as libc::c_ulong, 0, 0, 0)
+        }) {
+            Ok(_) => (),
+            Err(Errno::EINVAL) => break,
+            Err(en) => return Err(en),
+        }
+    }
+
+    // Raise the ambient set, then lock it
+    for c in caps {
+        Errno::result(unsafe {
+            libc::prctl(
+                libc::PR_CAP_AMBIENT,
+                libc::PR_CAP_AMBIENT_RAISE,
+                c.0 as libc::c_ulong,
+                0,
+                0,
+            )
+        })?;
+    }
+    let bits = SECBIT_NOROOT
+        | SECBIT_NOROOT_LOCKED
+        | SECBIT_NO_SETUID_FIXUP
+        | SECBIT_NO_SETUID_FIXUP_LOCKED
+        | SECBIT_KEEP_CAPS_LOCKED
+        | SECBIT_NO_CAP_AMBIENT_RAISE
+        | SECBIT_NO_CAP_AMBIENT_RAISE_LOCKED;
+    Errno::result(unsafe { libc::prctl(libc::PR_SET_SECUREBITS, bits, 0, 0, 0) })?;
+
+    // Drop setpcap
+    capset(caps, &[])
+}
@@ -36,5 +36,6 @@ pub fn from_environment() -> PermissionFactoryResult {
         uid: unistd::getuid(),
         primary_gid: unistd::getgid(),
         secondary_gids: HashSet::from_iter(secondary_gids_vec),
+        capabilities: None,
     })
 }
@@ -116,5 +116,6 @@ where
         uid,
         primary_gid,
         secondary_gids,
+        capabilities: None,
     })
 }
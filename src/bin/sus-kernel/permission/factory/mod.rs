@@ -17,6 +17,10 @@ pub use iterator::from_iterator;
 
 use super::Permission;
 
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
 /// Type for (automatic) [Permission] factories
 ///
 /// We need to be able to generate [Permission]s in different ways. We might
@@ -60,3 +64,27 @@ pub enum PermissionFactoryError {
     /// Parse error for a Secondary GID, where `content` is the failing string
     SecondaryGIDMalformed { content: String },
 }
+
+impl Display for PermissionFactoryError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            PermissionFactoryError::UIDNotFound => write!(f, "UID not found")?,
+            PermissionFactoryError::PrimaryGIDNotFound => write!(f, "Primary GID not found")?,
+            PermissionFactoryError::SecondaryGIDNotFound => {
+                write!(f, "Secondary GIDs not found")?;
+            }
+            PermissionFactoryError::UIDMalformed { content } => {
+                write!(f, "UID malformed - {:?}", content)?;
+            }
+            PermissionFactoryError::PrimaryGIDMalformed { content } => {
+                write!(f, "Primary GID malformed - {:?}", content)?;
+            }
+            PermissionFactoryError::SecondaryGIDMalformed { content } => {
+                write!(f, "Secondary GID malformed - {:?}", content)?;
+            }
+        };
+        Ok(())
+    }
+}
+
+impl Error for PermissionFactoryError {}
@@ -7,10 +7,16 @@
 //! This module serves to aggregate permissions and have methods to construct
 //! them from various sources. It's tied to the Unix permission model, where
 //! each user has a user id, a group id, and a list of secondary group ids.
+//! Optionally, the [Capability]s held can be limited too.
 
+pub mod authenticate;
+pub mod capability;
 pub mod factory;
+pub mod privilege;
+pub mod seccomp;
 pub mod verify;
 
+use capability::Capability;
 use nix::unistd::{Gid, Uid};
 use std::collections::HashSet;
 use std::fmt;
@@ -30,6 +36,11 @@ pub struct Permission {
     /// A set of secondary group ids, which may or may not contain the primary
     /// group id itself
     pub secondary_gids: HashSet<Gid>,
+    /// The only [Capability]s held, if they're limited
+    ///
+    /// If this is [None], whatever the user id comes with is held. That's
+    /// every capability for `root`, and none for anyone else.
+    pub capabilities: Option<Vec<Capability>>,
 }
 
 impl Display for Permission {
@@ -56,6 +67,11 @@ impl Display for Permission {
             self.primary_gid.as_raw(),
             sgid_vec.join(","),
         )?;
+        // Only write the capabilities if they're limited
+        if let Some(caps) = &self.capabilities {
+            let caps: Vec<_> = caps.iter().map(|c| c.to_string()).collect();
+            write!(f, " caps={}", caps.join(","))?;
+        }
         // Return
         Ok(())
     }
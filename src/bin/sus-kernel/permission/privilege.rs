@@ -0,0 +1,149 @@
+//! Module for giving up `root` when it isn't needed
+//!
+//! The kernel starts with an Effective UID of `root`, but most of what it does,
+//! like parsing its arguments and checking the policy, doesn't need it. If any
+//! of that goes wrong, it's better that it goes wrong as the user. So, the
+//! kernel drops to the Real UID with [drop_root] as soon as it can, and only
+//! goes back to `root` for the few things that need it.
+//!
+//! The Saved UID stays `root` throughout. That's what lets [with_root] and
+//! [regain_root] switch back.
+//!
+//! Changing the Effective UID can make the process dumpable again, which would
+//! let the user attach to it. So, every switch marks it as not dumpable after.
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::unistd;
+use nix::unistd::{Gid, Uid};
+
+/// Function to run with the user's UID from now on
+///
+/// This sets the Effective UID to the Real UID, keeping `root` as the Saved
+/// UID. If the user is `root`, nothing changes.
+pub fn drop_root() -> Result<(), Errno> {
+    unistd::seteuid(unistd::getuid())?;
+    undumpable()
+}
+
+/// Function to go back to `root` for good
+///
+/// This is for the final switch to the requested [Permission][p]s, which needs
+/// `root` to do. After that switch, there is nothing to go back to.
+///
+/// [p]: super::Permission
+pub fn regain_root() -> Result<(), Errno> {
+    if unistd::geteuid().is_root() {
+        return Ok(());
+    }
+    unistd::seteuid(Uid::from_raw(0))?;
+    undumpable()
+}
+
+/// Function to become `root` in every UID and GID, for good
+///
+/// The runners do this just before they fork. A process the user started keeps
+/// their Real UID, so they can still signal it. If they could kill a runner,
+/// what it runs would outlive its timeout. Once this is done, only `root` can.
+pub fn become_root() -> Result<(), Errno> {
+    let root = Uid::from_raw(0);
+    let group = Gid::from_raw(0);
+    unistd::setresgid(group, group, group)?;
+    unistd::setresuid(root, root, root)?;
+    undumpable()
+}
+
+/// Function to mark the process as not dumpable
+///
+/// This keeps the user from `ptrace`ing it, and keeps it from dumping core.
+/// Anything else that changes the Effective UID should call this after.
+pub fn undumpable() -> Result<(), Errno> {
+    Errno::result(unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) })?;
+    Ok(())
+}
+
+/// Function to run `f` as `root`, then go back to what we were
+///
+/// This is for things like opening the policy or the log, which the user
+/// shouldn't be able to do. If there's no `root` to go back to, as with the
+/// other binaries that use this code, `f` is just run as-is. It's then up to
+/// `f` to fail if it needed `root`.
+///
+/// If we can't go back afterwards, we'd be left running as `root`, so this
+/// panics instead.
+pub fn with_root<T, F: FnOnce() -> T>(f: F) -> T {
+    // Check if we can and need to switch
+    let switch = match unistd::getresuid() {
+        Ok(ids) => !ids.effective.is_root() && ids.saved.is_root(),
+        Err(_) => false,
+    };
+    if !switch || regain_root().is_err() {
+        return f();
+    }
+    let ret = f();
+    drop_root().expect("Failed to give up root");
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nix::sys::wait;
+    use nix::sys::wait::WaitStatus;
+    use nix::unistd::ForkResult;
+
+    fn dumpable() -> bool {
+        unsafe { libc::prctl(libc::PR_GET_DUMPABLE, 0, 0, 0, 0) != 0 }
+    }
+
+    fn set_dumpable() {
+        unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 1, 0, 0, 0) };
+    }
+
+    /// Function to switch around, returning which check failed, if any
+    ///
+    /// This changes the ids of every thread, so it's only run in a child.
+    fn switch() -> i32 {
+        // Act like we're Set-UID `root`, run by `nobody`, if we can
+        let root = unistd::geteuid().is_root();
+        let nobody = Uid::from_raw(65534);
+        if root && unistd::setresuid(nobody, Uid::from_raw(0), Uid::from_raw(0)).is_err() {
+            return 1;
+        }
+
+        set_dumpable();
+        if drop_root().is_err() || dumpable() {
+            return 2;
+        }
+
+        set_dumpable();
+        if !with_root(|| !root || unistd::geteuid().is_root()) || dumpable() {
+            return 3;
+        }
+
+        if root {
+            set_dumpable();
+            if regain_root().is_err() || dumpable() {
+                return 4;
+            }
+            set_dumpable();
+            if become_root().is_err() || dumpable() || !unistd::getuid().is_root() {
+                return 5;
+            }
+        }
+        0
+    }
+
+    #[test]
+    fn not_dumpable_after_switching() {
+        let child = match unsafe { unistd::fork() }.unwrap() {
+            ForkResult::Parent { child } => child,
+            ForkResult::Child => unsafe { libc::_exit(switch()) },
+        };
+        assert_eq!(
+            wait::waitpid(child, None).unwrap(),
+            WaitStatus::Exited(child, 0)
+        );
+    }
+}
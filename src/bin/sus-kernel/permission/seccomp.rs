@@ -0,0 +1,319 @@
+//! Module for limiting the system calls a process can make
+//!
+//! Some processes, like the [helper][hp] that checks the policy, only need a
+//! handful of system calls. This module builds a `seccomp` filter from a list
+//! of [Rule]s and installs it, so that if anything goes wrong in that process,
+//! it can't do much. Calls can also be allowed only with one particular
+//! argument, with [ArgRule]s.
+//!
+//! Calls can also be passed to another process to decide on, with
+//! [Action::Notify]. That process [receives][receive] each call and
+//! [responds][respond] to it. That's how `noexec` lets the [Executable][eb]
+//! itself start but nothing after it. They can also stop the process for
+//! whatever is tracing it, with [Action::Trace]. That's how `intercept` checks
+//! everything the [Executable][eb] runs.
+//!
+//! Once a filter is installed, it can't be removed. It's inherited by every
+//! child, and kept across `execve`.
+//!
+//! [hp]: super::verify::helper
+//! [eb]: crate::executable::Executable
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::poll;
+use nix::poll::{PollFd, PollFlags};
+use std::os::unix::io::RawFd;
+
+/// What to do when a system call is made
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+    /// Let the call through
+    Allow,
+    /// Kill the whole process
+    Kill,
+    /// Wait for whoever has the listener from [install_listener] to decide
+    Notify,
+    /// Stop for whatever is tracing this process with `ptrace` to look at. If
+    /// nothing is, the call fails with [Errno::ENOSYS].
+    Trace,
+}
+
+impl Action {
+    /// Function to get the value a filter returns for this [Action]
+    fn ret(self) -> u32 {
+        match self {
+            Action::Allow => libc::SECCOMP_RET_ALLOW,
+            Action::Kill => libc::SECCOMP_RET_KILL_PROCESS,
+            Action::Notify => SECCOMP_RET_USER_NOTIF,
+            Action::Trace => libc::SECCOMP_RET_TRACE,
+        }
+    }
+}
+
+/// A system call, and what to do when it's made
+pub type Rule = (libc::c_long, Action);
+/// A system call, the value its first argument has to be, and what to do when
+/// it's made with that value
+pub type ArgRule = (libc::c_long, u64, Action);
+
+// What libc doesn't have from `linux/seccomp.h`
+const SECCOMP_RET_USER_NOTIF: u32 = 0x7fc0_0000;
+const SECCOMP_SET_MODE_FILTER: libc::c_uint = 1;
+const SECCOMP_FILTER_FLAG_NEW_LISTENER: libc::c_uint = 1 << 3;
+const SECCOMP_IOCTL_NOTIF_RECV: libc::c_ulong = 0xc050_2100;
+const SECCOMP_IOCTL_NOTIF_SEND: libc::c_ulong = 0xc018_2101;
+const SECCOMP_USER_NOTIF_FLAG_CONTINUE: u32 = 1 << 0;
+
+/// What a call waiting on a listener was made with, from `struct seccomp_data`
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct NotifData {
+    pub nr: libc::c_int,
+    pub arch: u32,
+    pub instruction_pointer: u64,
+    pub args: [u64; 6],
+}
+
+/// A call waiting on a listener, from `struct seccomp_notif`
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Notif {
+    pub id: u64,
+    pub pid: u32,
+    pub flags: u32,
+    pub data: NotifData,
+}
+
+/// The answer to a [Notif], from `struct seccomp_notif_resp`
+#[repr(C)]
+struct NotifResp {
+    id: u64,
+    val: i64,
+    error: i32,
+    flags: u32,
+}
+
+// Classic BPF opcodes, which libc doesn't have
+// These are `BPF_LD | BPF_W | BPF_ABS`, `BPF_JMP | BPF_JEQ | BPF_K`,
+//  `BPF_JMP | BPF_JGE | BPF_K`, and `BPF_RET | BPF_K`
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_JMP_JEQ_K: u16 = 0x15;
+const BPF_JMP_JGE_K: u16 = 0x35;
+const BPF_RET_K: u16 = 0x06;
+
+// Where things are in `struct seccomp_data`
+// The first argument is 64 bits, so it's loaded in two halves
+const OFFSET_NR: u32 = 0;
+const OFFSET_ARCH: u32 = 4;
+#[cfg(target_endian = "little")]
+const OFFSET_ARG0: (u32, u32) = (16, 20);
+#[cfg(target_endian = "big")]
+const OFFSET_ARG0: (u32, u32) = (20, 16);
+
+// The architecture the system call numbers are for
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+
+/// Function to make a single filter instruction
+fn stmt(code: u16, jt: u8, jf: u8, k: u32) -> libc::sock_filter {
+    libc::sock_filter { code, jt, jf, k }
+}
+
+/// Function to install a filter on this process
+///
+/// Each system call is checked against the [Rule]s in order, and the
+/// [Action] of the first that matches is taken. If none do, `default` is
+/// taken. Calls made for a different architecture than the one this was built
+/// for always kill the process, since their numbers mean something else.
+///
+/// This also sets `no_new_privs`, which is needed to install a filter without
+/// `CAP_SYS_ADMIN`. It means nothing this process runs can gain privileges,
+/// say by running a Set-UID binary.
+///
+/// If this wasn't built for an architecture it knows, it fails with
+/// [Errno::ENOSYS] without installing anything.
+pub fn install(rules: &[Rule], default: Action) -> Result<(), Errno> {
+    install_with_args(&[], rules, default)
+}
+
+/// Function to install a filter on this process, with some [ArgRule]s
+///
+/// This is like [install], except the [ArgRule]s are checked before the
+/// [Rule]s. A call that matches an [ArgRule]'s system call but not its
+/// argument goes on to be checked against the rest.
+pub fn install_with_args(
+    arg_rules: &[ArgRule],
+    rules: &[Rule],
+    default: Action,
+) -> Result<(), Errno> {
+    AUDIT_ARCH.ok_or(Errno::ENOSYS)?;
+    no_new_privs()?;
+    install_as_root(arg_rules, rules, default)
+}
+
+/// Function to install a filter on this process, without `no_new_privs`
+///
+/// This is like [install_with_args], except it has to be called with
+/// `CAP_SYS_ADMIN`, or after [no_new_privs]. That way, whatever is tracing the
+/// process can still let it run Set-UID binaries.
+pub fn install_as_root(
+    arg_rules: &[ArgRule],
+    rules: &[Rule],
+    default: Action,
+) -> Result<(), Errno> {
+    let mut prog = filter(arg_rules, rules, default)?;
+    let fprog = libc::sock_fprog {
+        len: prog.len() as u16,
+        filter: prog.as_mut_ptr(),
+    };
+    Errno::result(unsafe {
+        libc::prctl(
+            libc::PR_SET_SECCOMP,
+            libc::SECCOMP_MODE_FILTER,
+            &fprog as *const libc::sock_fprog,
+        )
+    })?;
+    Ok(())
+}
+
+/// Function to install a filter on this process, and get a listener for it
+///
+/// This is like [install_as_root], except it returns the file descriptor
+/// that calls with [Action::Notify] are sent to. Like it, it has to be called
+/// with `CAP_SYS_ADMIN`, or after [no_new_privs].
+pub fn install_listener(
+    arg_rules: &[ArgRule],
+    rules: &[Rule],
+    default: Action,
+) -> Result<RawFd, Errno> {
+    let mut prog = filter(arg_rules, rules, default)?;
+    let fprog = libc::sock_fprog {
+        len: prog.len() as u16,
+        filter: prog.as_mut_ptr(),
+    };
+    let fd = Errno::result(unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            SECCOMP_SET_MODE_FILTER,
+            SECCOMP_FILTER_FLAG_NEW_LISTENER,
+            &fprog as *const libc::sock_fprog,
+        )
+    })?;
+    Ok(fd as RawFd)
+}
+
+/// Function to set `no_new_privs` on this process
+///
+/// Nothing this process runs can gain privileges after this, say by running a
+/// Set-UID binary. It also lets [install_listener] be called without
+/// `CAP_SYS_ADMIN`.
+pub fn no_new_privs() -> Result<(), Errno> {
+    Errno::result(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+    Ok(())
+}
+
+/// Function to wait for the next call on a listener from [install_listener]
+///
+/// It returns [None] once nothing is left that could make calls.
+pub fn receive(listener: RawFd) -> Option<Notif> {
+    loop {
+        // Waiting on the listener itself doesn't stop when everything using the
+        //  filter is gone, so wait to be able to read first
+        let mut fds = [PollFd::new(listener, PollFlags::POLLIN)];
+        match poll::poll(&mut fds, -1) {
+            Ok(_) => (),
+            Err(Errno::EINTR) => continue,
+            Err(_) => return None,
+        }
+        let revents = fds[0].revents().unwrap_or_else(PollFlags::empty);
+        if !revents.contains(PollFlags::POLLIN) {
+            return None;
+        }
+
+        let mut notif = Notif::default();
+        let res = Errno::result(unsafe {
+            libc::ioctl(listener, SECCOMP_IOCTL_NOTIF_RECV, &mut notif as *mut Notif)
+        });
+        match res {
+            Ok(_) => return Some(notif),
+            // The caller went away before we got to it
+            Err(Errno::EINTR) | Err(Errno::ENOENT) => continue,
+            Err(_) => return None,
+        }
+    }
+}
+
+/// Function to let a call through, or fail it with an error
+///
+/// Letting it through means the call is made as it is once this returns. So,
+/// if the caller could change what it was called with, looking at that first
+/// doesn't say what the call will do.
+pub fn respond(listener: RawFd, id: u64, res: Result<(), Errno>) {
+    let resp = match res {
+        Ok(()) => NotifResp {
+            id,
+            val: 0,
+            error: 0,
+            flags: SECCOMP_USER_NOTIF_FLAG_CONTINUE,
+        },
+        Err(en) => NotifResp {
+            id,
+            val: 0,
+            error: -(en as i32),
+            flags: 0,
+        },
+    };
+    unsafe {
+        libc::ioctl(
+            listener,
+            SECCOMP_IOCTL_NOTIF_SEND,
+            &resp as *const NotifResp,
+        );
+    }
+}
+
+/// Function to build the filter for [install_as_root] and
+/// [install_listener]
+fn filter(
+    arg_rules: &[ArgRule],
+    rules: &[Rule],
+    default: Action,
+) -> Result<Vec<libc::sock_filter>, Errno> {
+    let arch = AUDIT_ARCH.ok_or(Errno::ENOSYS)?;
+
+    // Check the architecture, then load the system call number
+    let mut prog = vec![
+        stmt(BPF_LD_W_ABS, 0, 0, OFFSET_ARCH),
+        stmt(BPF_JMP_JEQ_K, 1, 0, arch),
+        stmt(BPF_RET_K, 0, 0, Action::Kill.ret()),
+        stmt(BPF_LD_W_ABS, 0, 0, OFFSET_NR),
+    ];
+    // The x32 ABI shares its architecture with x86_64, but sets this bit
+    if cfg!(target_arch = "x86_64") {
+        prog.push(stmt(BPF_JMP_JGE_K, 0, 1, 0x4000_0000));
+        prog.push(stmt(BPF_RET_K, 0, 0, Action::Kill.ret()));
+    }
+    // Check each rule on arguments
+    // If any part doesn't match, reload the system call number and move on
+    for (nr, arg, action) in arg_rules {
+        prog.push(stmt(BPF_JMP_JEQ_K, 0, 6, *nr as u32));
+        prog.push(stmt(BPF_LD_W_ABS, 0, 0, OFFSET_ARG0.0));
+        prog.push(stmt(BPF_JMP_JEQ_K, 0, 3, *arg as u32));
+        prog.push(stmt(BPF_LD_W_ABS, 0, 0, OFFSET_ARG0.1));
+        prog.push(stmt(BPF_JMP_JEQ_K, 0, 1, (*arg >> 32) as u32));
+        prog.push(stmt(BPF_RET_K, 0, 0, action.ret()));
+        prog.push(stmt(BPF_LD_W_ABS, 0, 0, OFFSET_NR));
+    }
+    // Check each rule
+    for (nr, action) in rules {
+        prog.push(stmt(BPF_JMP_JEQ_K, 0, 1, *nr as u32));
+        prog.push(stmt(BPF_RET_K, 0, 0, action.ret()));
+    }
+    prog.push(stmt(BPF_RET_K, 0, 0, default.ret()));
+    Ok(prog)
+}
@@ -0,0 +1,272 @@
+//! Module for the [helper][hp] process itself
+//!
+//! This is what runs in the child after the kernel forks. It gives up
+//! everything it can and locks itself down, then loads the policy with files
+//! and ids from the kernel. It then locks itself down further before it looks
+//! at any requests.
+//!
+//! [hp]: super
+
+use super::message::{recv, send, Decision, Message, Query, Reply};
+use super::HelperError;
+use crate::config;
+use crate::executable::run::sanitize::open_fds;
+use crate::permission::privilege;
+use crate::permission::seccomp;
+use crate::permission::seccomp::{Action, ArgRule, Rule};
+use crate::permission::verify::sudoers::{parse_sudoers_from, verifiers};
+use crate::permission::verify::sudoers::{PolicySource, SudoersError};
+use crate::permission::verify::{VerifyError, VerifyResult};
+
+use nix::errno::Errno;
+use nix::fcntl;
+use nix::fcntl::OFlag;
+use nix::libc;
+use nix::sys::stat::Mode;
+use nix::unistd;
+use nix::unistd::{Gid, Uid};
+use std::ffi::OsString;
+use std::fs::File;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::io::RawFd;
+use std::path::Path;
+use std::sync::Arc;
+
+/// The system calls the helper may make while it loads the policy
+///
+/// That's enough to allocate memory, read the files it's passed, talk to the
+/// kernel, and exit. Anything else kills it. Debug builds of Rust check that
+/// a file is still open before closing it, which needs `fcntl`.
+const LOADING: [Rule; 17] = [
+    (libc::SYS_read, Action::Allow),
+    (libc::SYS_fcntl, Action::Allow),
+    (libc::SYS_close, Action::Allow),
+    (libc::SYS_recvmsg, Action::Allow),
+    (libc::SYS_sendmsg, Action::Allow),
+    (libc::SYS_brk, Action::Allow),
+    (libc::SYS_mmap, Action::Allow),
+    (libc::SYS_munmap, Action::Allow),
+    (libc::SYS_mremap, Action::Allow),
+    (libc::SYS_madvise, Action::Allow),
+    (libc::SYS_futex, Action::Allow),
+    (libc::SYS_getrandom, Action::Allow),
+    (libc::SYS_rt_sigreturn, Action::Allow),
+    (libc::SYS_rt_sigprocmask, Action::Allow),
+    (libc::SYS_sigaltstack, Action::Allow),
+    (libc::SYS_exit, Action::Allow),
+    (libc::SYS_exit_group, Action::Allow),
+];
+
+/// The only `prctl`s the helper may make while it loads the policy
+///
+/// These are what it takes to install [CHECKING] on top of [LOADING].
+const LOCKING: [ArgRule; 2] = [
+    (
+        libc::SYS_prctl,
+        libc::PR_SET_NO_NEW_PRIVS as u64,
+        Action::Allow,
+    ),
+    (libc::SYS_prctl, libc::PR_SET_SECCOMP as u64, Action::Allow),
+];
+
+/// The system calls the helper may make once the policy is loaded
+///
+/// This is [LOADING], except it has no more files to read, so neither reading
+/// nor `fcntl` is allowed. The two filters are stacked, so nothing can be added back here.
+const CHECKING: [Rule; 15] = [
+    (libc::SYS_close, Action::Allow),
+    (libc::SYS_recvmsg, Action::Allow),
+    (libc::SYS_sendmsg, Action::Allow),
+    (libc::SYS_brk, Action::Allow),
+    (libc::SYS_mmap, Action::Allow),
+    (libc::SYS_munmap, Action::Allow),
+    (libc::SYS_mremap, Action::Allow),
+    (libc::SYS_madvise, Action::Allow),
+    (libc::SYS_futex, Action::Allow),
+    (libc::SYS_getrandom, Action::Allow),
+    (libc::SYS_rt_sigreturn, Action::Allow),
+    (libc::SYS_rt_sigprocmask, Action::Allow),
+    (libc::SYS_sigaltstack, Action::Allow),
+    (libc::SYS_exit, Action::Allow),
+    (libc::SYS_exit_group, Action::Allow),
+];
+
+/// [PolicySource] that asks the kernel for files and ids over `sock`
+///
+/// If talking to the kernel fails, the error is kept in `failed` so the helper
+/// can give up instead of reporting a bad policy.
+struct RemoteSource {
+    sock: RawFd,
+    failed: Option<HelperError>,
+}
+
+impl RemoteSource {
+    /// Function to send `msg` and get the [Reply]
+    fn ask(&mut self, msg: &Message) -> Option<(Reply, Option<File>)> {
+        match send(self.sock, msg, None).and_then(|_| recv(self.sock)) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                self.failed = Some(e);
+                None
+            }
+        }
+    }
+
+    /// Function to send `msg` and get the id in the [Reply]
+    fn lookup(&mut self, msg: &Message) -> Option<u32> {
+        match self.ask(msg)? {
+            (Reply::Id(id), _) => id,
+            _ => {
+                self.failed = Some(HelperError::Protocol);
+                None
+            }
+        }
+    }
+
+    /// Function to make the error for a file, once talking to the kernel
+    /// failed
+    fn failure(&mut self, path: &Path) -> SudoersError {
+        self.failed.get_or_insert(HelperError::Protocol);
+        SudoersError::Io {
+            path: path.to_string_lossy().to_string(),
+            cause: Arc::new(std::io::Error::from_raw_os_error(libc::EIO)),
+        }
+    }
+}
+
+impl PolicySource for RemoteSource {
+    fn open(&mut self, path: &Path) -> Result<File, SudoersError> {
+        let msg = Message::Open(path.as_os_str().as_bytes().to_vec());
+        match self.ask(&msg) {
+            Some((Reply::File, Some(f))) => Ok(f),
+            Some((Reply::Error(e), _)) => Err(e.into()),
+            _ => Err(self.failure(path)),
+        }
+    }
+
+    fn list(&mut self, path: &Path) -> Result<Option<Vec<OsString>>, SudoersError> {
+        let msg = Message::List(path.as_os_str().as_bytes().to_vec());
+        match self.ask(&msg) {
+            Some((Reply::Names(n), _)) => Ok(Some(n.into_iter().map(OsString::from_vec).collect())),
+            Some((Reply::Missing, _)) => Ok(None),
+            Some((Reply::Error(e), _)) => Err(e.into()),
+            _ => Err(self.failure(path)),
+        }
+    }
+
+    fn uid(&mut self, name: &str) -> Option<Uid> {
+        self.lookup(&Message::User(name.to_string()))
+            .map(Uid::from_raw)
+    }
+
+    fn gid(&mut self, name: &str) -> Option<Gid> {
+        self.lookup(&Message::Group(name.to_string()))
+            .map(Gid::from_raw)
+    }
+
+    fn included(
+        &mut self,
+        path: &Path,
+        includes: &[String],
+        include_dirs: &[String],
+    ) -> Result<(), SudoersError> {
+        let msg = Message::Included {
+            path: path.as_os_str().as_bytes().to_vec(),
+            includes: includes.to_vec(),
+            include_dirs: include_dirs.to_vec(),
+        };
+        match send(self.sock, &msg, None) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                self.failed = Some(e);
+                Err(self.failure(path))
+            }
+        }
+    }
+}
+
+/// Function to run the helper in the child
+///
+/// This never returns. The child exits with `0` once the kernel hangs up, and
+/// `1` if anything goes wrong before then.
+pub fn run(sock: RawFd) -> ! {
+    let code = match serve(sock) {
+        Ok(()) => 0,
+        Err(_) => 1,
+    };
+    unsafe { libc::_exit(code) }
+}
+
+/// Function to do the work of the helper
+fn serve(sock: RawFd) -> Result<(), HelperError> {
+    confine(sock).map_err(|en| HelperError::Confine { errno: en })?;
+    // Lock down before parsing anything
+    seccomp::install_with_args(&LOCKING, &LOADING, Action::Kill)
+        .map_err(|en| HelperError::Confine { errno: en })?;
+
+    // Load the policy
+    // Give up if we couldn't get the files, rather than blaming the policy
+    let mut source = RemoteSource { sock, failed: None };
+    let loaded = parse_sudoers_from(config::SUDOER_PATH, &mut source);
+    if let Some(e) = source.failed {
+        return Err(e);
+    }
+    send(sock, &Message::Loaded, None)?;
+
+    // Lock down further before looking at any requests
+    seccomp::install(&CHECKING, Action::Kill).map_err(|en| HelperError::Confine { errno: en })?;
+
+    // Check every request against every rule, the same way the kernel would
+    // Stop when the kernel hangs up
+    let mut verifiers = verifiers(loaded);
+    loop {
+        let query: Query = match recv(sock) {
+            Ok((q, _)) => q,
+            Err(HelperError::Closed) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let (cur, req, exe) = query.into_request()?;
+        let mut res: VerifyResult = Err(VerifyError::NotAllowed { err: None });
+        for v in &mut verifiers {
+            res = res.or(v(&cur, &req, &exe));
+        }
+        send(sock, &Message::Decision(Decision::from(res)), None)?;
+    }
+}
+
+/// Function to give up everything the helper doesn't need
+///
+/// It closes every file but `sock`, points the standard streams at
+/// `/dev/null`, and switches entirely to [config::POLICY_HELPER_UID] and
+/// [config::POLICY_HELPER_GID]. It then marks itself as not dumpable again,
+/// since changing users can turn that back on.
+fn confine(sock: RawFd) -> Result<(), Errno> {
+    // Get root back to be able to switch
+    privilege::regain_root()?;
+
+    // Close everything else
+    // This has to be done as root, since we can't list our own files otherwise
+    for fd in open_fds() {
+        if fd > 2 && fd != sock {
+            let _ = unistd::close(fd);
+        }
+    }
+    let null = fcntl::open("/dev/null", OFlag::O_RDWR, Mode::empty())?;
+    for fd in 0..3 {
+        unistd::dup2(null, fd)?;
+    }
+    if null > 2 {
+        unistd::close(null)?;
+    }
+
+    // Switch users
+    let uid = Uid::from_raw(config::POLICY_HELPER_UID);
+    let gid = Gid::from_raw(config::POLICY_HELPER_GID);
+    unistd::setgroups(&[gid])?;
+    unistd::setresgid(gid, gid, gid)?;
+    unistd::setresuid(uid, uid, uid)?;
+
+    // Don't let the helper's user attach to us
+    Errno::result(unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) })?;
+    Ok(())
+}
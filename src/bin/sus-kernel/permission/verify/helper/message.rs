@@ -0,0 +1,311 @@
+//! Module for what the [helper][hp] and the kernel say to each other
+//!
+//! They talk over a `SOCK_SEQPACKET` socket, so every message arrives whole.
+//! Each one is a JSON value. Files are passed alongside a [Reply] as
+//! `SCM_RIGHTS` control messages.
+//!
+//! [hp]: super
+
+use super::HelperError;
+use crate::config;
+use crate::executable::Executable;
+use crate::permission::verify::sudoers::SudoersError;
+use crate::permission::verify::sudoers_type::Option;
+use crate::permission::verify::{Options, VerifyError, VerifyResult};
+use crate::permission::Permission;
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::socket;
+use nix::sys::socket::{ControlMessage, ControlMessageOwned, MsgFlags};
+use nix::sys::uio::IoVec;
+use nix::unistd::{Gid, Uid};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::fs::File;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::sync::Arc;
+
+/// What the [helper][hp] sends to the kernel
+///
+/// It asks for files with [Open][op] and [List][ls] while it loads the policy,
+/// and for the ids of names with [User][us] and [Group][gr]. Once it's read a
+/// file, it says what that file includes with [Included][in]. It says when
+/// it's done with [Loaded][ld]. After that, the only thing it may send is a
+/// [Decision] for each [Query] it gets.
+///
+/// [hp]: super
+/// [op]: Message::Open
+/// [ls]: Message::List
+/// [in]: Message::Included
+/// [us]: Message::User
+/// [gr]: Message::Group
+/// [ld]: Message::Loaded
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Message {
+    /// Open the file at this path
+    Open(Vec<u8>),
+    /// List the directory at this path
+    List(Vec<u8>),
+    /// Find the UID of the user with this name
+    User(String),
+    /// Find the GID of the group with this name
+    Group(String),
+    /// The file at this path, which was the last one opened, has these
+    /// `Includes` and `Include_Dirs`
+    ///
+    /// There's no [Reply] to this.
+    Included {
+        path: Vec<u8>,
+        includes: Vec<String>,
+        include_dirs: Vec<String>,
+    },
+    /// The policy is loaded, so no more files are needed
+    Loaded,
+    /// Whether the request is allowed
+    Decision(Decision),
+}
+
+/// What the kernel sends back for each [Message] while the policy loads
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Reply {
+    /// The file was opened, and is passed along with this
+    File,
+    /// The names in the directory
+    Names(Vec<Vec<u8>>),
+    /// The directory doesn't exist
+    Missing,
+    /// The id of the user or group, if it exists
+    Id(std::option::Option<u32>),
+    /// The file or directory couldn't be opened, or can't be trusted
+    Error(SourceError),
+}
+
+/// A request for the [helper][hp] to check, in a form that can be sent
+///
+/// The [Capability][cp]s of the [Permission]s aren't sent, since they're only
+/// set once a request is allowed.
+///
+/// [hp]: super
+/// [cp]: crate::permission::capability::Capability
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Query {
+    cur: Ids,
+    req: Ids,
+    path: Vec<u8>,
+    args: Vec<Vec<u8>>,
+}
+
+/// The ids in a [Permission]
+#[derive(Debug, Serialize, Deserialize)]
+struct Ids {
+    uid: u32,
+    gid: u32,
+    groups: Vec<u32>,
+}
+
+impl From<&Permission> for Ids {
+    fn from(p: &Permission) -> Self {
+        Ids {
+            uid: p.uid.as_raw(),
+            gid: p.primary_gid.as_raw(),
+            groups: p.secondary_gids.iter().map(|g| g.as_raw()).collect(),
+        }
+    }
+}
+
+impl From<Ids> for Permission {
+    fn from(i: Ids) -> Self {
+        Permission {
+            uid: Uid::from_raw(i.uid),
+            primary_gid: Gid::from_raw(i.gid),
+            secondary_gids: i.groups.into_iter().map(Gid::from_raw).collect(),
+            capabilities: None,
+        }
+    }
+}
+
+impl Query {
+    /// Function to make a [Query] for a request
+    pub fn new(cur: &Permission, req: &Permission, exe: &Executable) -> Self {
+        Query {
+            cur: Ids::from(cur),
+            req: Ids::from(req),
+            path: exe.path.as_bytes().to_vec(),
+            args: exe.args.iter().map(|a| a.as_bytes().to_vec()).collect(),
+        }
+    }
+
+    /// Function to turn a [Query] back into the request
+    ///
+    /// This fails if the path or any of the arguments has a `NUL` in it.
+    pub fn into_request(self) -> Result<(Permission, Permission, Executable), HelperError> {
+        let cstr = |b| CString::new(b).map_err(|_| HelperError::Protocol);
+        let exe = Executable {
+            path: cstr(self.path)?,
+            args: self.args.into_iter().map(cstr).collect::<Result<_, _>>()?,
+        };
+        Ok((self.cur.into(), self.req.into(), exe))
+    }
+}
+
+/// The [SudoersError]s opening a file can cause, in a form that can be sent
+#[derive(Debug, Serialize, Deserialize)]
+pub enum SourceError {
+    Io { path: String, errno: i32 },
+    Insecure { path: String, owner: u32, mode: u32 },
+}
+
+impl From<SudoersError> for SourceError {
+    fn from(e: SudoersError) -> Self {
+        match e {
+            SudoersError::Io { path, cause } => SourceError::Io {
+                path,
+                errno: cause.raw_os_error().unwrap_or(libc::EIO),
+            },
+            SudoersError::Insecure { path, owner, mode } => {
+                SourceError::Insecure { path, owner, mode }
+            }
+            // Opening doesn't cause anything else
+            e => SourceError::Io {
+                path: e.to_string(),
+                errno: libc::EIO,
+            },
+        }
+    }
+}
+
+impl From<SourceError> for SudoersError {
+    fn from(e: SourceError) -> Self {
+        match e {
+            SourceError::Io { path, errno } => SudoersError::Io {
+                path,
+                cause: Arc::new(std::io::Error::from_raw_os_error(errno)),
+            },
+            SourceError::Insecure { path, owner, mode } => {
+                SudoersError::Insecure { path, owner, mode }
+            }
+        }
+    }
+}
+
+/// Whether the request is allowed, in a form that can be sent
+///
+/// The errors in a [VerifyError] can't be sent as they are. Only their
+/// messages are, which is all the log needs.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Decision {
+    /// The request is allowed with these [Option]s
+    Allow(Vec<Option>),
+    /// The request is denied
+    Deny {
+        kind: DenyKind,
+        reason: std::option::Option<String>,
+    },
+}
+
+/// Which [VerifyError] a [Decision::Deny] is
+#[derive(Debug, Serialize, Deserialize)]
+pub enum DenyKind {
+    NotAllowed,
+    NotFound,
+    Malformed,
+    Insecure,
+}
+
+impl From<VerifyResult> for Decision {
+    fn from(res: VerifyResult) -> Self {
+        let (kind, err) = match res {
+            Ok(opts) => return Decision::Allow(opts.0),
+            Err(VerifyError::NotAllowed { err }) => (DenyKind::NotAllowed, err),
+            Err(VerifyError::NotFound { err }) => (DenyKind::NotFound, err),
+            Err(VerifyError::Malformed { err }) => (DenyKind::Malformed, err),
+            Err(VerifyError::Insecure { err }) => (DenyKind::Insecure, err),
+        };
+        Decision::Deny {
+            kind,
+            reason: err.map(|e| e.to_string()),
+        }
+    }
+}
+
+impl Decision {
+    /// Function to turn a [Decision] back into a [VerifyResult]
+    pub fn into_result(self) -> VerifyResult {
+        let (kind, reason) = match self {
+            Decision::Allow(opts) => return Ok(Options(opts)),
+            Decision::Deny { kind, reason } => (kind, reason),
+        };
+        let err = reason.map(|r| r.into());
+        Err(match kind {
+            DenyKind::NotAllowed => VerifyError::NotAllowed { err },
+            DenyKind::NotFound => VerifyError::NotFound { err },
+            DenyKind::Malformed => VerifyError::Malformed { err },
+            DenyKind::Insecure => VerifyError::Insecure { err },
+        })
+    }
+}
+
+/// Function to send a message, optionally passing a file descriptor with it
+///
+/// If the other end is gone, this fails with [Errno::EPIPE] instead of raising
+/// `SIGPIPE`.
+pub fn send<T: Serialize>(
+    sock: RawFd,
+    msg: &T,
+    fd: std::option::Option<RawFd>,
+) -> Result<(), HelperError> {
+    let data = serde_json::to_vec(msg).map_err(|_| HelperError::Protocol)?;
+    let fds: Vec<[RawFd; 1]> = fd.into_iter().map(|f| [f]).collect();
+    let cmsgs: Vec<ControlMessage> = fds.iter().map(|f| ControlMessage::ScmRights(f)).collect();
+    // nix doesn't have MSG_NOSIGNAL, but it's just a flag
+    let flags = unsafe { MsgFlags::from_bits_unchecked(libc::MSG_NOSIGNAL) };
+    socket::sendmsg(sock, &[IoVec::from_slice(&data)], &cmsgs, flags, None)
+        .map_err(|en| HelperError::Socket { errno: en })?;
+    Ok(())
+}
+
+/// Function to receive a message, along with any file passed with it
+///
+/// Messages longer than [config::POLICY_HELPER_MAX_MESSAGE] are refused. If the
+/// other end is gone, this fails with [HelperError::Closed].
+pub fn recv<T: DeserializeOwned>(
+    sock: RawFd,
+) -> Result<(T, std::option::Option<File>), HelperError> {
+    let mut buf = vec![0u8; config::POLICY_HELPER_MAX_MESSAGE];
+    let mut cmsg_buf = nix::cmsg_space!([RawFd; 1]);
+    let (bytes, fds, truncated) = {
+        let iov = [IoVec::from_mut_slice(&mut buf)];
+        let msg = loop {
+            match socket::recvmsg(sock, &iov, Some(&mut cmsg_buf), MsgFlags::MSG_CMSG_CLOEXEC) {
+                Err(Errno::EINTR) => continue,
+                r => break r.map_err(|en| HelperError::Socket { errno: en })?,
+            }
+        };
+        let mut fds = Vec::new();
+        for c in msg.cmsgs() {
+            if let ControlMessageOwned::ScmRights(f) = c {
+                fds.extend(f);
+            }
+        }
+        let truncated = msg
+            .flags
+            .intersects(MsgFlags::MSG_TRUNC | MsgFlags::MSG_CTRUNC);
+        (msg.bytes, fds, truncated)
+    };
+    // Keep at most one file
+    // Close anything else we were sent
+    let mut files = fds.into_iter().map(|f| unsafe { File::from_raw_fd(f) });
+    let file = files.next();
+    files.for_each(drop);
+
+    if bytes == 0 {
+        return Err(HelperError::Closed);
+    }
+    if truncated {
+        return Err(HelperError::Protocol);
+    }
+    let msg = serde_json::from_slice(&buf[..bytes]).map_err(|_| HelperError::Protocol)?;
+    Ok((msg, file))
+}
@@ -0,0 +1,460 @@
+//! Module for checking the policy in a separate process
+//!
+//! Parsing the policy and checking the request against it is the most involved
+//! thing the kernel does. If there's a bug in it, it shouldn't be one that can
+//! be turned into `root`. So, this module does it in a helper process instead.
+//!
+//! The kernel forks the helper once, which gives up `root` for good, switches
+//! to [config::POLICY_HELPER_UID], and installs a `seccomp` filter so it can
+//! only allocate memory, read what it's given, talk to the kernel, and exit.
+//! Only then does it parse anything. It can't open the policy itself, so it
+//! asks the kernel for each file, which the kernel opens and checks as `root`
+//! before passing it over. It can't look up the users and groups named in the
+//! policy either, so it asks the kernel for those too.
+//!
+//! The kernel only opens [config::SUDOER_PATH], and whatever that file's
+//! `Includes` and `Include_Dirs` lead to. It doesn't parse the files as `root`
+//! to find those. The helper says what each file includes once it's read it,
+//! and the kernel only checks that the file actually names them. It stops
+//! answering once the helper
+//! says the policy is loaded, or once the helper has made
+//! [config::POLICY_HELPER_MAX_REQUESTS] requests. After that, the helper locks
+//! itself down further, and answers each [Query] it's sent with a
+//! [Decision][dc]. So, a helper that goes wrong can't use the kernel to read
+//! anything else.
+//!
+//! [dc]: message::Decision
+
+pub mod child;
+pub mod message;
+
+use super::sudoers::SudoersError;
+use super::sudoers::{included_names, resolve_includes, LocalSource, PolicySource};
+use super::{Verifier, VerifyError, VerifyResult};
+use crate::config;
+use crate::executable::Executable;
+use crate::permission::Permission;
+use message::{recv, send, Message, Query, Reply, SourceError};
+
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::socket;
+use nix::sys::socket::{AddressFamily, SockFlag, SockType};
+use nix::sys::wait;
+use nix::sys::wait::WaitStatus;
+use nix::unistd;
+use nix::unistd::{ForkResult, Gid, Pid, Uid};
+use std::collections::HashSet;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{Error as IoError, Read, Seek, SeekFrom};
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Function to create the [Verifier]s for the policy, checked by the helper
+///
+/// There is only one. The helper is started and loads the policy when this is
+/// called, and then checks every request the [Verifier] is called with. It's
+/// stopped when the [Verifier] is dropped.
+///
+/// If the helper couldn't be started or stops working, requests are denied with
+/// a [NotFound][nf] error saying why.
+///
+/// [nf]: VerifyError::NotFound
+pub fn from_helper() -> Vec<Box<Verifier>> {
+    let mut helper = Helper::start();
+    vec![Box::new(move |cur, req, exe| match &mut helper {
+        Ok(h) => h.verify(cur, req, exe),
+        Err(e) => Err(not_found(e.clone())),
+    })]
+}
+
+/// Function to report a [HelperError] as a [VerifyError]
+fn not_found(e: HelperError) -> VerifyError {
+    VerifyError::NotFound {
+        err: Some(Box::new(e)),
+    }
+}
+
+/// A running helper, with the policy loaded
+///
+/// Dropping it hangs up on the helper, which makes it exit, then reaps it.
+struct Helper {
+    sock: RawFd,
+    /// The helper's PID, until it's reaped
+    child: Option<Pid>,
+    /// Why the helper stopped working, if it did
+    failed: Option<HelperError>,
+}
+
+impl Helper {
+    /// Function to start the helper and serve it the policy
+    fn start() -> Result<Helper, HelperError> {
+        let (ours, theirs) = socket::socketpair(
+            AddressFamily::Unix,
+            SockType::SeqPacket,
+            None,
+            SockFlag::SOCK_CLOEXEC,
+        )
+        .map_err(|en| HelperError::Socket { errno: en })?;
+
+        let child = match unsafe { unistd::fork() } {
+            Ok(ForkResult::Parent { child }) => child,
+            Ok(ForkResult::Child) => {
+                let _ = unistd::close(ours);
+                child::run(theirs)
+            }
+            Err(en) => {
+                let _ = unistd::close(ours);
+                let _ = unistd::close(theirs);
+                return Err(HelperError::Fork { errno: en });
+            }
+        };
+        let _ = unistd::close(theirs);
+
+        let mut helper = Helper {
+            sock: ours,
+            child: Some(child),
+            failed: None,
+        };
+        match Server::new().serve(ours) {
+            Ok(()) => Ok(helper),
+            // Say how it ended, since that's more useful than it hanging up
+            Err(e) => Err(helper.stop().err().unwrap_or(e)),
+        }
+    }
+
+    /// Function to check a request with the helper
+    ///
+    /// Once talking to the helper fails, it's stopped, and every later request
+    /// fails the same way.
+    fn verify(&mut self, cur: &Permission, req: &Permission, exe: &Executable) -> VerifyResult {
+        if self.failed.is_none() {
+            match self.ask(cur, req, exe) {
+                Ok(res) => return res,
+                Err(e) => self.failed = Some(self.stop().err().unwrap_or(e)),
+            }
+        }
+        Err(not_found(
+            self.failed.clone().unwrap_or(HelperError::Closed),
+        ))
+    }
+
+    /// Function to send a [Query] and get the [Decision]
+    fn ask(
+        &mut self,
+        cur: &Permission,
+        req: &Permission,
+        exe: &Executable,
+    ) -> Result<VerifyResult, HelperError> {
+        send(self.sock, &Query::new(cur, req, exe), None)?;
+        match recv(self.sock)?.0 {
+            Message::Decision(d) => Ok(d.into_result()),
+            _ => Err(HelperError::Protocol),
+        }
+    }
+
+    /// Function to hang up on the helper and reap it
+    ///
+    /// This fails with [HelperError::Failed] if the helper didn't exit cleanly.
+    fn stop(&mut self) -> Result<(), HelperError> {
+        let child = match self.child.take() {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let _ = unistd::close(self.sock);
+        let status = loop {
+            match wait::waitpid(child, None) {
+                Err(Errno::EINTR) => continue,
+                r => break r.map_err(|en| HelperError::Socket { errno: en })?,
+            }
+        };
+        match status {
+            WaitStatus::Exited(_, 0) => Ok(()),
+            s => Err(HelperError::Failed {
+                status: format!("{:?}", s),
+            }),
+        }
+    }
+}
+
+impl Drop for Helper {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// What the kernel has let the helper ask for while it loads the policy
+struct Server {
+    source: LocalSource,
+    /// The files the helper may open
+    files: HashSet<PathBuf>,
+    /// The directories the helper may list
+    dirs: HashSet<PathBuf>,
+    /// The last file the helper opened, and what's in it, until it says what
+    /// that file includes
+    last: Option<(PathBuf, Vec<u8>)>,
+    /// How many requests the helper has made
+    requests: usize,
+}
+
+impl Server {
+    /// Function to create a [Server] that only allows [config::SUDOER_PATH]
+    fn new() -> Self {
+        Server {
+            source: LocalSource { check_secure: true },
+            files: vec![PathBuf::from(config::SUDOER_PATH)]
+                .into_iter()
+                .collect(),
+            dirs: HashSet::new(),
+            last: None,
+            requests: 0,
+        }
+    }
+
+    /// Function to answer the helper's requests until the policy is loaded
+    fn serve(&mut self, sock: RawFd) -> Result<(), HelperError> {
+        loop {
+            let msg = recv(sock)?.0;
+            if let Message::Loaded = msg {
+                return Ok(());
+            }
+            self.requests += 1;
+            if self.requests > config::POLICY_HELPER_MAX_REQUESTS {
+                return Err(HelperError::Limit);
+            }
+            match msg {
+                Message::Open(path) => {
+                    let path = PathBuf::from(OsString::from_vec(path));
+                    if !self.files.contains(&path) {
+                        return Err(HelperError::Refused { path });
+                    }
+                    match self.open(&path) {
+                        Ok(f) => send(sock, &Reply::File, Some(f.as_raw_fd()))?,
+                        Err(e) => send(sock, &Reply::Error(SourceError::from(e)), None)?,
+                    }
+                }
+                Message::List(path) => {
+                    let path = PathBuf::from(OsString::from_vec(path));
+                    if !self.dirs.contains(&path) {
+                        return Err(HelperError::Refused { path });
+                    }
+                    let reply = match self.list(&path) {
+                        Ok(Some(n)) => Reply::Names(n.into_iter().map(|n| n.into_vec()).collect()),
+                        Ok(None) => Reply::Missing,
+                        Err(e) => Reply::Error(SourceError::from(e)),
+                    };
+                    send(sock, &reply, None)?;
+                }
+                Message::User(name) => {
+                    let reply = Reply::Id(self.source.uid(&name).map(Uid::as_raw));
+                    send(sock, &reply, None)?;
+                }
+                Message::Group(name) => {
+                    let reply = Reply::Id(self.source.gid(&name).map(Gid::as_raw));
+                    send(sock, &reply, None)?;
+                }
+                Message::Included {
+                    path,
+                    includes,
+                    include_dirs,
+                } => {
+                    let path = PathBuf::from(OsString::from_vec(path));
+                    self.include(&path, &includes, &include_dirs)?;
+                }
+                Message::Loaded | Message::Decision(_) => return Err(HelperError::Protocol),
+            }
+        }
+    }
+
+    /// Function to open a file, and keep what's in it to check what the helper
+    /// says it includes
+    ///
+    /// The file is read once here, then rewound so the helper reads it from
+    /// the start. Files larger than [config::POLICY_HELPER_MAX_FILE] fail with
+    /// `EFBIG`.
+    fn open(&mut self, path: &Path) -> Result<File, SudoersError> {
+        self.last = None;
+        let mut file = self.source.open(path)?;
+        let io_err = |e| SudoersError::Io {
+            path: path.to_string_lossy().to_string(),
+            cause: Arc::new(e),
+        };
+        let mut buf = Vec::new();
+        (&mut file)
+            .take(config::POLICY_HELPER_MAX_FILE + 1)
+            .read_to_end(&mut buf)
+            .map_err(io_err)?;
+        if buf.len() as u64 > config::POLICY_HELPER_MAX_FILE {
+            return Err(io_err(IoError::from_raw_os_error(libc::EFBIG)));
+        }
+        file.seek(SeekFrom::Start(0)).map_err(io_err)?;
+        self.last = Some((path.to_path_buf(), buf));
+        Ok(file)
+    }
+
+    /// Function to allow what the helper says the file at `path` includes
+    ///
+    /// That has to be the last file it opened, and it has to name each of
+    /// them. See [names]. Otherwise, the helper is lying, and this fails.
+    fn include(
+        &mut self,
+        path: &Path,
+        includes: &[String],
+        include_dirs: &[String],
+    ) -> Result<(), HelperError> {
+        let content = match self.last.take() {
+            Some((p, c)) if p == path => c,
+            _ => {
+                return Err(HelperError::Refused {
+                    path: path.to_path_buf(),
+                })
+            }
+        };
+        let (files, dirs) = resolve_includes(path, includes, include_dirs);
+        let named = includes.iter().chain(include_dirs);
+        for (name, resolved) in named.zip(files.iter().chain(&dirs)) {
+            if !names(&content, name) {
+                return Err(HelperError::Refused {
+                    path: resolved.clone(),
+                });
+            }
+        }
+        self.files.extend(files);
+        self.dirs.extend(dirs);
+        Ok(())
+    }
+
+    /// Function to list a directory, and allow the files in it that are read
+    ///
+    /// Only those files are sent back, since the helper skips the rest.
+    fn list(&mut self, dir: &Path) -> Result<Option<Vec<OsString>>, SudoersError> {
+        let names = match self.source.list(dir)? {
+            Some(n) => included_names(n),
+            None => return Ok(None),
+        };
+        self.files.extend(names.iter().map(|n| dir.join(n)));
+        Ok(Some(names))
+    }
+}
+
+/// Function to check if `content` has `name` in it as a JSON string
+///
+/// This is how the kernel checks what the helper says a file includes without
+/// parsing it. The string is looked for the way `serde_json` would write it, so
+/// an include that escapes characters it doesn't need to is refused.
+fn names(content: &[u8], name: &str) -> bool {
+    let quoted = match serde_json::to_vec(name) {
+        Ok(q) => q,
+        Err(_) => return false,
+    };
+    content.windows(quoted.len()).any(|w| w == &quoted[..])
+}
+
+/// Error for the helper
+///
+/// The helper might not start, might not be able to lock itself down, or might
+/// stop talking partway through. It might also ask for more than it should.
+///
+/// This can be cloned, since every request after the helper fails reports it.
+#[derive(Debug, Clone)]
+pub enum HelperError {
+    /// An error occurred when creating or using the socket to the helper
+    Socket { errno: Errno },
+    /// An error occurred when creating the helper
+    Fork { errno: Errno },
+    /// An error occurred when the helper was giving up its privileges
+    Confine { errno: Errno },
+    /// The other side hung up
+    Closed,
+    /// The other side sent something it shouldn't have
+    Protocol,
+    /// The helper asked for a file or directory outside the policy
+    Refused { path: PathBuf },
+    /// The helper made more than [config::POLICY_HELPER_MAX_REQUESTS] requests
+    Limit,
+    /// The helper didn't exit cleanly
+    Failed { status: String },
+}
+
+impl Display for HelperError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            HelperError::Socket { errno } => {
+                write!(f, "Failed to talk to the policy helper - {}", errno)?;
+            }
+            HelperError::Fork { errno } => {
+                write!(f, "Failed to start the policy helper - {}", errno)?;
+            }
+            HelperError::Confine { errno } => {
+                write!(f, "Failed to confine the policy helper - {}", errno)?;
+            }
+            HelperError::Closed => {
+                write!(f, "The policy helper stopped early")?;
+            }
+            HelperError::Protocol => {
+                write!(f, "The policy helper sent something unexpected")?;
+            }
+            HelperError::Refused { path } => {
+                write!(
+                    f,
+                    "The policy helper asked for {}, which the policy doesn't include",
+                    path.to_string_lossy()
+                )?;
+            }
+            HelperError::Limit => {
+                write!(f, "The policy helper made too many requests")?;
+            }
+            HelperError::Failed { status } => {
+                write!(f, "The policy helper failed - {}", status)?;
+            }
+        };
+        Ok(())
+    }
+}
+
+impl Error for HelperError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_named_includes_are_allowed() {
+        let path = PathBuf::from("/etc/sudoers.json");
+        let content = br#"{"Includes": ["extra.json"], "Include_Dirs": ["sudoers.d"]}"#.to_vec();
+        let opened = || Server {
+            last: Some((path.clone(), content.clone())),
+            ..Server::new()
+        };
+        let includes = vec!["extra.json".to_string()];
+        let include_dirs = vec!["sudoers.d".to_string()];
+
+        // What the file names is allowed, but only once
+        let mut server = opened();
+        server.include(&path, &includes, &include_dirs).unwrap();
+        assert!(server.files.contains(Path::new("/etc/extra.json")));
+        assert!(server.dirs.contains(Path::new("/etc/sudoers.d")));
+        assert!(server.include(&path, &includes, &include_dirs).is_err());
+
+        // Anything else isn't
+        let mut server = opened();
+        let shadow = vec!["/etc/shadow".to_string()];
+        assert!(server.include(&path, &shadow, &[]).is_err());
+        assert!(!server.files.contains(Path::new("/etc/shadow")));
+
+        // Neither is saying what some other file includes
+        let mut server = opened();
+        let other = Path::new("/etc/other.json");
+        assert!(server.include(other, &includes, &[]).is_err());
+    }
+}
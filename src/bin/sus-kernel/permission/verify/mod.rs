@@ -5,12 +5,14 @@
 //! that might need to be performed. This module holds the methods for doing
 //! that. It also defines common types for verification.
 
+pub mod helper;
 pub mod parsed_sudoers_type;
+pub mod secure;
 pub mod sudoers;
 pub mod sudoers_type;
 use super::Permission;
 use crate::executable::Executable;
-pub use sudoers::from_sudoers;
+pub use sudoers_type::Options;
 
 use std::error::Error;
 use std::fmt;
@@ -21,14 +23,26 @@ use std::fmt::{Display, Formatter};
 /// These functions should take in the user's current [Permission], as well as
 /// the [Permission] they request and the [Executable] the user wishes to run.
 /// They should then return a [VerifyResult] signalling whether the user is
-/// allowed to run it.
+/// allowed to run it. If they are, it also gives the [Options] that apply to
+/// the run.
 pub type Verifier = dyn FnMut(&Permission, &Permission, &Executable) -> VerifyResult;
 
+/// Type for functions that create the [Verifier]s to check
+///
+/// The kernel checks every [Verifier] returned, and lets the request through if
+/// any of them pass.
+pub type AutoVerifiersFactory = fn() -> Vec<Box<Verifier>>;
+
 /// Convinience type for the result of a [Verifier]
 ///
 /// Verification may succeed or fail, so the return value of a [Verifier] is a
 /// [Result]. For convinience, this type aliases to the expected return type.
-pub type VerifyResult = Result<(), VerifyError>;
+///
+/// On success, the [Options] of whatever allowed the request are returned. The
+/// [Runner][rn] uses these to decide how to run the [Executable].
+///
+/// [rn]: crate::executable::run::Runner
+pub type VerifyResult = Result<Options, VerifyError>;
 
 /// String to match on ALL keyword in sudoers
 pub const ALL: &str = "ALL";
@@ -48,6 +62,9 @@ pub enum VerifyError {
     NotFound { err: Option<Box<dyn Error>> },
     /// Some component needed for verification could not be parsed
     Malformed { err: Option<Box<dyn Error>> },
+    /// Some component needed for verification could be modified by someone
+    /// other than `root`, so it can't be trusted
+    Insecure { err: Option<Box<dyn Error>> },
 }
 
 impl Display for VerifyError {
@@ -58,6 +75,7 @@ impl Display for VerifyError {
             VerifyError::NotAllowed { err: e } => (e, "Access Denied"),
             VerifyError::NotFound { err: e } => (e, "Internal Error NotFound"),
             VerifyError::Malformed { err: e } => (e, "Internal Error Malformed"),
+            VerifyError::Insecure { err: e } => (e, "Internal Error Insecure"),
         };
         // Print out the message
         // Also print details if needed
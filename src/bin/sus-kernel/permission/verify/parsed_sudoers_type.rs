@@ -1,15 +1,17 @@
 // Since sudoers store users by username and not uid,
 // we use special types in this file to easily query sudoers using ids
+use super::sudoers::{PolicySource, SudoersError};
 use super::sudoers_type;
 use super::sudoers_type::Command;
 use super::sudoers_type::User::{Useralias, Usergroup, Username};
 use super::Permission;
 use super::ALL;
+use crate::config;
+use crate::executable::Executable;
 use nix::unistd::{Gid, Uid};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::collections::HashSet;
 use std::ffi::CString;
-use users::{get_group_by_name, get_user_by_name};
 
 #[derive(Debug)]
 pub struct AllowedCmd {
@@ -18,6 +20,7 @@ pub struct AllowedCmd {
     pub groups: HashSet<Gid>,
     pub allow_all_groups: bool,
     pub paths: HashSet<CString>,
+    pub edit_paths: HashSet<CString>,
     pub allow_all_cmds: bool,
     pub options: Vec<sudoers_type::Option>,
 }
@@ -28,6 +31,7 @@ impl AllowedCmd {
             users: HashSet::new(),
             groups: HashSet::new(),
             paths: HashSet::new(),
+            edit_paths: HashSet::new(),
             options: Vec::new(),
             allow_all_cmds: false,
             allow_all_users: false,
@@ -41,6 +45,15 @@ impl AllowedCmd {
             || self.allow_all_users
             || self.allow_all_groups
     }
+    // Editing with sudoedit is allowed only if every file is, since the
+    //  pseudo-command itself isn't a path
+    pub fn allows(&self, exe: &Executable) -> bool {
+        if exe.path.as_bytes() == config::SUDOEDIT_PATH.as_bytes() {
+            exe.args.len() > 1 && exe.args[1..].iter().all(|f| self.edit_paths.contains(f))
+        } else {
+            self.paths.contains(&exe.path)
+        }
+    }
 }
 #[derive(Debug)]
 pub struct Rule {
@@ -49,15 +62,6 @@ pub struct Rule {
     pub groups: HashSet<Gid>,
     pub allow_all_groups: bool,
     pub allowed_cmds: Vec<AllowedCmd>,
-    pub allow_all_cmds: bool,
-}
-
-fn get_uid_from_username(username: &str) -> Option<Uid> {
-    get_user_by_name(username).map(|user| Uid::from_raw(user.uid()))
-}
-
-fn get_gid_from_groupname(groupname: &str) -> Option<Gid> {
-    get_group_by_name(groupname).map(|user| Gid::from_raw(user.gid()))
 }
 
 impl Rule {
@@ -68,7 +72,6 @@ impl Rule {
             allowed_cmds: Vec::new(),
             allow_all_groups: false,
             allow_all_users: false,
-            allow_all_cmds: false,
         }
     }
 
@@ -82,8 +85,9 @@ impl Rule {
 
     pub fn from_userspec(
         userspec: &sudoers_type::UserSpec,
-        useraliases: &HashMap<String, Vec<sudoers_type::User>>,
-    ) -> Self {
+        useraliases: &BTreeMap<String, Vec<sudoers_type::User>>,
+        source: &mut dyn PolicySource,
+    ) -> Result<Self, SudoersError> {
         let mut rule = Rule::new();
         // Populate rule.users and rule.groups with uid and gid
         for user in &userspec.user_list {
@@ -91,23 +95,30 @@ impl Rule {
                 Username(username) => {
                     if username.eq(ALL) {
                         rule.allow_all_users = true;
-                    } else if let Some(uid) = get_uid_from_username(username) {
+                    } else if let Some(uid) = source.uid(username) {
                         rule.users.insert(uid);
                     }
                 }
                 Usergroup(groupname) => {
                     if groupname.eq(ALL) {
                         rule.allow_all_groups = true;
-                    } else if let Some(gid) = get_gid_from_groupname(groupname) {
+                    } else if let Some(gid) = source.gid(groupname) {
                         rule.groups.insert(gid);
                     }
                 }
                 Useralias(alias) => {
-                    for user in &useraliases[alias] {
+                    let aliased =
+                        useraliases
+                            .get(alias)
+                            .ok_or_else(|| SudoersError::UndefinedAlias {
+                                path: userspec.source.clone(),
+                                alias: alias.clone(),
+                            })?;
+                    for user in aliased {
                         if let Username(username) = user {
                             if username.eq(ALL) {
                                 rule.allow_all_users = true;
-                            } else if let Some(uid) = get_uid_from_username(username) {
+                            } else if let Some(uid) = source.uid(username) {
                                 rule.users.insert(uid);
                             }
                         }
@@ -121,7 +132,7 @@ impl Rule {
                 if let Username(username) = runasuser {
                     if username.eq(ALL) {
                         allowed_cmd.allow_all_users = true;
-                    } else if let Some(uid) = get_uid_from_username(username) {
+                    } else if let Some(uid) = source.uid(username) {
                         allowed_cmd.users.insert(uid);
                     }
                 }
@@ -130,7 +141,7 @@ impl Rule {
                 if let Usergroup(groupname) = runasgroup {
                     if groupname.eq(ALL) {
                         allowed_cmd.allow_all_users = true;
-                    } else if let Some(gid) = get_gid_from_groupname(groupname) {
+                    } else if let Some(gid) = source.gid(groupname) {
                         allowed_cmd.groups.insert(gid);
                     }
                 }
@@ -139,20 +150,27 @@ impl Rule {
                 allowed_cmd.options.push(option.clone());
             }
             for command in &cmd_spec.commands {
-                let Command::CmdPath(path) = command;
-                if path.to_str().unwrap().eq(ALL) {
-                    allowed_cmd.allow_all_cmds = true;
-                } else {
-                    allowed_cmd.paths.insert(path.clone());
+                match command {
+                    Command::CmdPath(path) if path.as_bytes().eq(ALL.as_bytes()) => {
+                        allowed_cmd.allow_all_cmds = true;
+                    }
+                    Command::CmdPath(path) => {
+                        allowed_cmd.paths.insert(path.clone());
+                    }
+                    Command::Sudoedit(files) => {
+                        allowed_cmd
+                            .edit_paths
+                            .extend(files.iter().filter_map(|f| CString::new(f.as_str()).ok()));
+                    }
                 }
             }
             rule.allowed_cmds.push(allowed_cmd);
         }
-        rule
+        Ok(rule)
     }
 }
 
 pub struct ParsedSudoers {
     pub rules: Vec<Rule>,
-    pub user_aliases: HashMap<String, Vec<Uid>>,
+    pub defaults: Vec<sudoers_type::Option>,
 }
@@ -0,0 +1,59 @@
+//! Check that policy files can only be modified by `root`
+//!
+//! The kernel trusts whatever the policy says. So, if anyone other than `root`
+//! can modify the policy, they can grant themselves anything. This module has
+//! functions to check that a file, and every directory leading up to it, is
+//! owned by [SUDOER_OWNER_UID][ou] and has none of the bits in
+//! [SUDOER_FORBIDDEN_MODE][fm] set. This mirrors the `sudoers_uid` and
+//! `sudoers_mode` checks done by `sudo`.
+//!
+//! [ou]: crate::config::SUDOER_OWNER_UID
+//! [fm]: crate::config::SUDOER_FORBIDDEN_MODE
+
+use super::sudoers::SudoersError;
+use crate::config;
+use crate::permission::privilege::with_root;
+
+use std::fs;
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Function to check the [Metadata] of a single file or directory
+///
+/// The [Metadata] is passed in instead of the path so that the caller can get
+/// it from a file they already have open. That way, the file can't be swapped
+/// out between the check and the read.
+pub fn check_metadata(path: &Path, meta: &Metadata) -> Result<(), SudoersError> {
+    if meta.uid() != config::SUDOER_OWNER_UID || meta.mode() & config::SUDOER_FORBIDDEN_MODE != 0 {
+        return Err(SudoersError::Insecure {
+            path: path.to_string_lossy().to_string(),
+            owner: meta.uid(),
+            mode: meta.mode(),
+        });
+    }
+    Ok(())
+}
+
+/// Function to check every directory containing `path`
+///
+/// This walks up from the parent of `path` to the root directory, checking
+/// each one with [check_metadata]. A relative `path` is checked relative to the
+/// current directory, so it's best to only pass absolute paths.
+pub fn check_parents(path: &Path) -> Result<(), SudoersError> {
+    for dir in path.ancestors().skip(1) {
+        // The last ancestor of a relative path is the empty path
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let meta = with_root(|| fs::metadata(dir)).map_err(|e| SudoersError::Io {
+            path: dir.to_string_lossy().to_string(),
+            cause: Arc::new(e),
+        })?;
+        check_metadata(dir, &meta)?;
+    }
+    Ok(())
+}
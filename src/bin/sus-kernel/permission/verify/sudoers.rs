@@ -1,45 +1,419 @@
-use super::sudoers_type::Sudoers;
+use super::parsed_sudoers_type::ParsedSudoers;
+use super::secure;
+use super::sudoers_type::{Options, Sudoers};
 use super::{Verifier, VerifyError};
 use crate::config;
+use crate::permission::privilege::with_root;
 use crate::permission::verify::VerifyResult;
 use nix::unistd::{Gid, Uid};
-use std::ffi::CString;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
 use std::fs::File;
 use std::io::BufReader;
+use std::io::ErrorKind;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-#[allow(dead_code)]
-#[derive(Debug)]
-struct Command {
-    run_as_users: Vec<Uid>,
-    run_as_groups: Vec<Gid>,
-    commands: Vec<CString>,
+/// Error for loading the sudoers policy
+///
+/// Loading the policy can fail in a few ways. Any of the files might not be
+/// readable, might be modifiable by someone other than `root`, might not be
+/// valid JSON, or might refer to things that are never defined. Every variant
+/// records which file the error happened in, since the policy can be split
+/// across many files.
+/// This `enum` keeps the underlying error around, as well as where in the
+/// policy it happened, so that it can be reported to the administrator.
+///
+/// The underlying errors are kept behind an [Arc] so that this type can be
+/// cloned. The verifier created on failure has to report the error every time
+/// it's called.
+#[derive(Debug, Clone)]
+pub enum SudoersError {
+    /// The policy file could not be opened or read
+    Io {
+        path: String,
+        cause: Arc<std::io::Error>,
+    },
+    /// The policy file or a directory containing it has the wrong owner or
+    /// mode, so it can't be trusted
+    Insecure { path: String, owner: u32, mode: u32 },
+    /// The policy file is not valid JSON or doesn't match the schema
+    Parse {
+        path: String,
+        cause: Arc<serde_json::Error>,
+    },
+    /// Includes were nested more than [config::SUDOER_MAX_INCLUDE_DEPTH] deep,
+    /// likely because a file includes itself
+    IncludeDepth { path: String },
+    /// A User Alias was referenced but never defined
+    UndefinedAlias { path: String, alias: String },
+    /// A User Alias was defined more than once
+    DuplicateAlias { path: String, alias: String },
 }
 
-#[derive(Debug)]
-struct Policy {
-    users: Vec<Uid>,
-    groups: Vec<Gid>,
-    cmd_specs: Vec<Command>,
+impl Display for SudoersError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SudoersError::Io { path, cause } => {
+                write!(f, "Failed to read {} - {}", path, cause)?;
+            }
+            // Tell the administrator how to fix it
+            SudoersError::Insecure { path, owner, .. } if *owner != config::SUDOER_OWNER_UID => {
+                write!(
+                    f,
+                    "{} is owned by uid {} - change its owner to uid {} with chown",
+                    path,
+                    owner,
+                    config::SUDOER_OWNER_UID
+                )?;
+            }
+            SudoersError::Insecure { path, mode, .. } => {
+                write!(
+                    f,
+                    "{} has mode {:04o} - clear the bits {:04o} with chmod",
+                    path,
+                    mode & 0o7777,
+                    config::SUDOER_FORBIDDEN_MODE
+                )?;
+            }
+            // The cause already says where in the file the error is
+            SudoersError::Parse { path, cause } => {
+                write!(f, "Failed to parse {} - {}", path, cause)?;
+            }
+            SudoersError::IncludeDepth { path } => {
+                write!(f, "Includes nested too deeply at {}", path)?;
+            }
+            SudoersError::UndefinedAlias { path, alias } => {
+                write!(f, "Undefined User Alias in {} - {}", path, alias)?;
+            }
+            SudoersError::DuplicateAlias { path, alias } => {
+                write!(f, "Duplicate User Alias in {} - {}", path, alias)?;
+            }
+        };
+        Ok(())
+    }
+}
+
+impl Error for SudoersError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SudoersError::Io { cause, .. } => Some(cause.as_ref()),
+            SudoersError::Parse { cause, .. } => Some(cause.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<SudoersError> for VerifyError {
+    /// Convert a [SudoersError] to the [VerifyError] to report
+    ///
+    /// If we couldn't read the file, that's a [NotFound][nf]. If we could but
+    /// don't trust it, that's [Insecure][is]. Anything else means the file was
+    /// there but had bad content, so it's [Malformed][mf].
+    ///
+    /// [nf]: VerifyError::NotFound
+    /// [is]: VerifyError::Insecure
+    /// [mf]: VerifyError::Malformed
+    fn from(e: SudoersError) -> Self {
+        match e {
+            SudoersError::Io { .. } => VerifyError::NotFound {
+                err: Some(Box::new(e)),
+            },
+            SudoersError::Insecure { .. } => VerifyError::Insecure {
+                err: Some(Box::new(e)),
+            },
+            _ => VerifyError::Malformed {
+                err: Some(Box::new(e)),
+            },
+        }
+    }
+}
+
+/// Function to read and parse a sudoers policy, getting the files from
+/// `source`
+///
+/// This does all the work of loading the policy, including following includes
+/// and resolving the names in it to ids. It never panics. Instead, it returns a
+/// [SudoersError] describing what went wrong and where. The files don't have to
+/// be opened by the process parsing them. See [LocalSource] for when they are.
+pub fn parse_sudoers_from(
+    path: &str,
+    source: &mut dyn PolicySource,
+) -> Result<ParsedSudoers, SudoersError> {
+    // Collect all the fragments into one
+    let mut sudoer = Sudoers::default();
+    read_with_includes(Path::new(path), 0, source, &mut sudoer)?;
+    // Parse sudoer further and retrieve uids and gids
+    sudoer.retrieve_ids(source)
+}
+
+/// Trait for where the files of the policy come from
+///
+/// Opening the policy needs `root`, but parsing it doesn't. This lets the
+/// parsing happen somewhere other than where the files are opened, like in the
+/// [helper][hp] process. Whatever implements this is responsible for checking
+/// that the files can be trusted.
+///
+/// The names of users and groups in the policy are looked up through it too.
+/// That can mean reading files or talking to a daemon, which the helper isn't
+/// allowed to do.
+///
+/// [hp]: super::helper
+pub trait PolicySource {
+    /// Open the file at `path` for reading
+    fn open(&mut self, path: &Path) -> Result<File, SudoersError>;
+    /// List the names of everything in the directory at `path`
+    ///
+    /// If the directory doesn't exist, [None] is returned.
+    fn list(&mut self, path: &Path) -> Result<Option<Vec<OsString>>, SudoersError>;
+    /// Find the UID of the user called `name`, if there is one
+    fn uid(&mut self, name: &str) -> Option<Uid>;
+    /// Find the GID of the group called `name`, if there is one
+    fn gid(&mut self, name: &str) -> Option<Gid>;
+    /// Say what the file at `path`, which was just opened, includes
+    ///
+    /// This is called before any of them are opened or listed. Nothing needs
+    /// to be done with it unless something else is opening the files.
+    fn included(
+        &mut self,
+        _path: &Path,
+        _includes: &[String],
+        _include_dirs: &[String],
+    ) -> Result<(), SudoersError> {
+        Ok(())
+    }
+}
+
+/// [PolicySource] that opens the files in this process
+///
+/// If `check_secure` is set, it checks the ownership and mode of each file and
+/// directory, as well as every directory containing them, before trusting
+/// them. See the [secure] module. The kernel must never skip them. Not
+/// checking exists so that policies can be syntax checked before they're
+/// installed, say in a checkout owned by a normal user.
+pub struct LocalSource {
+    pub check_secure: bool,
 }
 
-#[allow(dead_code)]
-pub fn from_sudoers() -> Vec<Box<Verifier>> {
+impl PolicySource for LocalSource {
+    fn open(&mut self, path: &Path) -> Result<File, SudoersError> {
+        let path_str = path.to_string_lossy().to_string();
+        // Check the directories leading up to the file
+        if self.check_secure {
+            secure::check_parents(path)?;
+        }
+        // Open the file
+        let io_err = |e| SudoersError::Io {
+            path: path_str.clone(),
+            cause: Arc::new(e),
+        };
+        // Only root can read it, so switch while opening it
+        let file = with_root(|| File::open(path)).map_err(io_err)?;
+        // Check the file itself
+        // Use the open file so it can't be swapped out from under us
+        if self.check_secure {
+            secure::check_metadata(path, &file.metadata().map_err(io_err)?)?;
+        }
+        Ok(file)
+    }
+
+    fn list(&mut self, dir: &Path) -> Result<Option<Vec<OsString>>, SudoersError> {
+        let dir_str = dir.to_string_lossy().to_string();
+        let io_err = |e| SudoersError::Io {
+            path: dir_str.clone(),
+            cause: Arc::new(e),
+        };
+        // Skip it if it's not there
+        let meta = match with_root(|| fs::metadata(dir)) {
+            Ok(m) => m,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(io_err(e)),
+        };
+        // Check the directory before trusting its listing
+        if self.check_secure {
+            secure::check_parents(dir)?;
+            secure::check_metadata(dir, &meta)?;
+        }
+        let entries = with_root(|| fs::read_dir(dir)).map_err(io_err)?;
+        let mut names = Vec::new();
+        for entry in entries {
+            names.push(entry.map_err(io_err)?.file_name());
+        }
+        Ok(Some(names))
+    }
+
+    fn uid(&mut self, name: &str) -> Option<Uid> {
+        users::get_user_by_name(name).map(|u| Uid::from_raw(u.uid()))
+    }
+
+    fn gid(&mut self, name: &str) -> Option<Gid> {
+        users::get_group_by_name(name).map(|g| Gid::from_raw(g.gid()))
+    }
+}
+
+/// Function to read a single file of the policy
+///
+/// The file is opened with the [PolicySource], then parsed as JSON. It doesn't
+/// follow any of the includes in the file. Every [UserSpec][us] read is tagged
+/// with `path` so that later errors can point back to it.
+///
+/// [us]: super::sudoers_type::UserSpec
+fn read_fragment(path: &Path, source: &mut dyn PolicySource) -> Result<Sudoers, SudoersError> {
+    let path_str = path.to_string_lossy().to_string();
+    let file = source.open(path)?;
+    // Parse it with serde_json
+    // Keep the location of the error if it's a parse error. IO errors while
+    //  reading are reported as such.
+    let reader = BufReader::new(file);
+    let mut sudoer: Sudoers = serde_json::from_reader(reader).map_err(|e| {
+        if e.is_io() {
+            SudoersError::Io {
+                path: path_str.clone(),
+                cause: Arc::new(e.into()),
+            }
+        } else {
+            SudoersError::Parse {
+                path: path_str.clone(),
+                cause: Arc::new(e),
+            }
+        }
+    })?;
+    // Remember where everything came from
+    for user_spec in &mut sudoer.user_specs {
+        user_spec.source = path_str.clone();
+    }
+    Ok(sudoer)
+}
+
+/// Function to read a file and everything it includes into `into`
+///
+/// Files are read depth-first. The content of a file comes first, then each of
+/// its `Includes` in order, then each of its `Include_Dirs` in order. Relative
+/// paths are taken relative to the directory of the including file.
+///
+/// For directories, the files in them are read in the order [included_names]
+/// gives. A directory that doesn't exist is skipped.
+fn read_with_includes(
+    path: &Path,
+    depth: usize,
+    source: &mut dyn PolicySource,
+    into: &mut Sudoers,
+) -> Result<(), SudoersError> {
+    // Fail if we've gone too deep
+    if depth > config::SUDOER_MAX_INCLUDE_DEPTH {
+        return Err(SudoersError::IncludeDepth {
+            path: path.to_string_lossy().to_string(),
+        });
+    }
+
+    // Read this file and pull out the includes before merging
+    let mut sudoer = read_fragment(path, source)?;
+    let includes = std::mem::take(&mut sudoer.includes);
+    let include_dirs = std::mem::take(&mut sudoer.include_dirs);
+    if !includes.is_empty() || !include_dirs.is_empty() {
+        source.included(path, &includes, &include_dirs)?;
+    }
+    into.merge(sudoer, &path.to_string_lossy())?;
+
+    // Do the files
+    let (includes, include_dirs) = resolve_includes(path, &includes, &include_dirs);
+    for inc in includes {
+        read_with_includes(&inc, depth + 1, source, into)?;
+    }
+
+    // Do the directories
+    for dir in include_dirs {
+        // Skip it if it's not there
+        let entries = match source.list(&dir)? {
+            Some(e) => e,
+            None => continue,
+        };
+        for name in included_names(entries) {
+            read_with_includes(&dir.join(name), depth + 1, source, into)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Function to find where the `Includes` and `Include_Dirs` of the file at
+/// `path` point
+///
+/// Relative paths are taken relative to the directory of the including file.
+pub fn resolve_includes(
+    path: &Path,
+    includes: &[String],
+    include_dirs: &[String],
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let base = path.parent().unwrap_or_else(|| Path::new("/"));
+    (
+        includes.iter().map(|i| base.join(i)).collect(),
+        include_dirs.iter().map(|i| base.join(i)).collect(),
+    )
+}
+
+/// Function to pick which of the names in an `Include_Dirs` directory to read
+///
+/// Like `sudo`, any file with a `.` or `~` in its name is skipped, so that
+/// editor backups and package manager leftovers aren't read. The rest are
+/// returned in lexical order.
+pub fn included_names(names: Vec<OsString>) -> Vec<OsString> {
+    let mut names: Vec<_> = names
+        .into_iter()
+        .filter(|n| !n.as_bytes().iter().any(|b| *b == b'.' || *b == b'~'))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Function to create a [Verifier] that denies everything
+///
+/// This is used when the policy couldn't be loaded. Every call reports the
+/// [SudoersError] so that it ends up in the log. It's logged even without the
+/// `log_fail_msg` feature, since it isn't [NotAllowed][na]. The only exception
+/// is if [config::SUDOER_FAILURE_ALLOW_ROOT] is set, in which case `root` is
+/// still let through with no [Options].
+///
+/// [na]: VerifyError::NotAllowed
+fn deny_all(e: SudoersError) -> Box<Verifier> {
+    Box::new(move |curr_perm, _, _| -> VerifyResult {
+        if config::SUDOER_FAILURE_ALLOW_ROOT && curr_perm.uid.is_root() {
+            return Ok(Options::default());
+        }
+        Err(e.clone().into())
+    })
+}
+
+/// Function to create [Verifier]s from a loaded policy
+///
+/// There is one [Verifier] for each rule. If the policy couldn't be loaded,
+/// there is a single one that denies everything and reports why.
+pub fn verifiers(loaded: Result<ParsedSudoers, SudoersError>) -> Vec<Box<Verifier>> {
+    // If we couldn't load it, deny everything and report why
+    let parsed_sudoer = match loaded {
+        Ok(ps) => ps,
+        Err(e) => return vec![deny_all(e)],
+    };
     // Declare vector of verifiers to return
+    // Every one of them needs the Defaults
     let mut verifiers = Vec::new();
-    // Parse sudoers.json using serde_json
-    let file = File::open(config::SUDOER_PATH).unwrap();
-    let reader = BufReader::new(file);
-    let sudoer: Sudoers = serde_json::from_reader(reader).unwrap();
-    // Parse sudoer further and retrieve uids and gids
-    let parsed_sudoer = sudoer.retrieve_ids();
+    let defaults = parsed_sudoer.defaults;
     for rule in parsed_sudoer.rules {
+        let defaults = defaults.clone();
         let x: Box<Verifier> = Box::new(move |curr_perm, req_perm, exe| -> VerifyResult {
             if rule.is_relevant(curr_perm) {
                 for allowed_cmd in &rule.allowed_cmds {
-                    if (allowed_cmd.is_relevant(req_perm) && allowed_cmd.paths.contains(&exe.path))
+                    if (allowed_cmd.is_relevant(req_perm) && allowed_cmd.allows(exe))
                         || allowed_cmd.allow_all_cmds
                     {
-                        return Ok(());
+                        // The command's options override the Defaults
+                        let mut options = defaults.clone();
+                        options.extend(allowed_cmd.options.iter().cloned());
+                        return Ok(Options(options));
                     }
                 }
             }
@@ -49,3 +423,137 @@ pub fn from_sudoers() -> Vec<Box<Verifier>> {
     }
     verifiers
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// [LocalSource] that remembers which files were opened, in order
+    struct Recording {
+        local: LocalSource,
+        opened: Vec<PathBuf>,
+    }
+
+    impl PolicySource for Recording {
+        fn open(&mut self, path: &Path) -> Result<File, SudoersError> {
+            self.opened.push(path.to_path_buf());
+            self.local.open(path)
+        }
+
+        fn list(&mut self, path: &Path) -> Result<Option<Vec<OsString>>, SudoersError> {
+            self.local.list(path)
+        }
+
+        fn uid(&mut self, name: &str) -> Option<Uid> {
+            self.local.uid(name)
+        }
+
+        fn gid(&mut self, name: &str) -> Option<Gid> {
+            self.local.gid(name)
+        }
+    }
+
+    /// Function to write `files` into a new directory, and load the policy
+    /// starting at the first one
+    fn load(name: &str, files: &[(&str, &str)]) -> (Vec<PathBuf>, Result<(), SudoersError>) {
+        let dir = std::env::temp_dir().join(format!("sus-{}-{}", name, std::process::id()));
+        for (file, content) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let mut source = Recording {
+            local: LocalSource {
+                check_secure: false,
+            },
+            opened: Vec::new(),
+        };
+        let start = dir.join(files[0].0);
+        let res = parse_sudoers_from(&start.to_string_lossy(), &mut source).map(|_| ());
+        fs::remove_dir_all(&dir).unwrap();
+        let opened = source
+            .opened
+            .iter()
+            .map(|p| p.strip_prefix(&dir).unwrap().to_path_buf())
+            .collect();
+        (opened, res)
+    }
+
+    #[test]
+    fn includes_are_relative_to_the_including_file() {
+        let (files, dirs) = resolve_includes(
+            Path::new("/etc/sus/sudoers.json"),
+            &["extra.json".to_string(), "/opt/sus.json".to_string()],
+            &["sudoers.d".to_string(), "../shared".to_string()],
+        );
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from("/etc/sus/extra.json"),
+                PathBuf::from("/opt/sus.json")
+            ]
+        );
+        assert_eq!(
+            dirs,
+            vec![
+                PathBuf::from("/etc/sus/sudoers.d"),
+                PathBuf::from("/etc/sus/../shared")
+            ]
+        );
+    }
+
+    #[test]
+    fn included_names_are_filtered_and_sorted() {
+        let names = ["b", "a~", "c.json", "a", "10", "9", ".hidden"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        assert_eq!(
+            included_names(names),
+            vec![
+                OsString::from("10"),
+                OsString::from("9"),
+                OsString::from("a"),
+                OsString::from("b")
+            ]
+        );
+    }
+
+    #[test]
+    fn includes_are_read_depth_first() {
+        let (opened, res) = load(
+            "include-order",
+            &[
+                (
+                    "sudoers.json",
+                    r#"{"Includes": ["one.json"], "Include_Dirs": ["d", "missing"]}"#,
+                ),
+                ("one.json", r#"{"Includes": ["two.json"]}"#),
+                ("two.json", "{}"),
+                ("d/b", "{}"),
+                ("d/a", "{}"),
+                ("d/a.bak", "not json"),
+                ("d/b~", "not json"),
+            ],
+        );
+        res.unwrap();
+        let expected: Vec<PathBuf> = ["sudoers.json", "one.json", "two.json", "d/a", "d/b"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(opened, expected);
+    }
+
+    #[test]
+    fn include_cycles_stop_at_the_depth_limit() {
+        let (opened, res) = load(
+            "include-cycle",
+            &[
+                ("a.json", r#"{"Includes": ["b.json"]}"#),
+                ("b.json", r#"{"Includes": ["a.json"]}"#),
+            ],
+        );
+        assert!(matches!(res, Err(SudoersError::IncludeDepth { .. })));
+        assert_eq!(opened.len(), config::SUDOER_MAX_INCLUDE_DEPTH + 1);
+    }
+}
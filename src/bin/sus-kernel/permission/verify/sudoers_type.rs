@@ -1,6 +1,8 @@
 use super::parsed_sudoers_type::{ParsedSudoers, Rule};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use super::sudoers::{PolicySource, SudoersError};
+use crate::permission::capability::Capability;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
 use std::ffi::CString;
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -23,11 +25,393 @@ pub enum Option {
     Setenv(bool),
     #[serde(rename = "authenticate")]
     Authenticate(bool),
+    // Accepted for compatibility with sudo, but not acted on
+    #[serde(rename = "env_reset")]
+    EnvReset(bool),
+    #[serde(rename = "mail_badpass")]
+    MailBadpass(bool),
+    #[serde(rename = "secure_path")]
+    SecurePath(String),
+    // How the kernel runs the command
+    #[serde(rename = "runner")]
+    Runner(RunnerKind),
+    // Record the command's input or output
+    #[serde(rename = "log_input")]
+    LogInput(bool),
+    #[serde(rename = "log_output")]
+    LogOutput(bool),
+    // Run the command on a new terminal, even if it isn't recorded
+    #[serde(rename = "use_pty")]
+    UsePty(bool),
+    // What the command inherits from the user
+    #[serde(rename = "closefrom")]
+    Closefrom(i32),
+    #[serde(rename = "closefrom_override")]
+    ClosefromOverride(bool),
+    #[serde(rename = "umask")]
+    #[serde(serialize_with = "serialize_octal")]
+    #[serde(deserialize_with = "deserialize_octal")]
+    Umask(u32),
+    // Kill the command if it runs for longer than this many seconds
+    #[serde(rename = "command_timeout")]
+    CommandTimeout(u64),
+    #[serde(rename = "user_command_timeouts")]
+    UserCommandTimeouts(bool),
+    // Where to run the command, where `*` lets the user pick with `sus -D`
+    #[serde(rename = "runcwd")]
+    Runcwd(String),
+    // Where to `chroot` before running the command
+    #[serde(rename = "runchroot")]
+    Runchroot(String),
+    // Resource limits for the command, starting from the target user's login
+    //  limits if `login_rlimits` is set
+    #[serde(rename = "rlimit")]
+    Rlimit(Rlimit),
+    #[serde(rename = "login_rlimits")]
+    LoginRlimits(bool),
+    // Scheduling priority for the command
+    #[serde(rename = "nice")]
+    Nice(i32),
+    #[serde(rename = "ionice")]
+    Ionice(Ionice),
+    // Run the command in its own cgroup, which setting any of the limits
+    //  also does
+    #[serde(rename = "cgroup")]
+    Cgroup(bool),
+    #[serde(rename = "cgroup_memory_max")]
+    CgroupMemoryMax(String),
+    #[serde(rename = "cgroup_cpu_max")]
+    CgroupCpuMax(String),
+    #[serde(rename = "cgroup_pids_max")]
+    CgroupPidsMax(String),
+    // Don't let the command run anything else
+    #[serde(rename = "noexec")]
+    Noexec(bool),
+    // Only let the command read and write under these paths
+    #[serde(rename = "landlock")]
+    Landlock(Landlock),
+    // Whether to refuse to run the command if the kernel can't do that,
+    //  which is the default
+    #[serde(rename = "landlock_required")]
+    LandlockRequired(bool),
+    // Run the command with only these capabilities, as whichever user it runs
+    //  as
+    #[serde(rename = "capabilities")]
+    Capabilities(Vec<Capability>),
+    // Run the command in new namespaces
+    #[serde(rename = "namespaces")]
+    Namespaces(Namespaces),
+    // Check everything the command runs against the policy too
+    #[serde(rename = "intercept")]
+    Intercept(bool),
+    // Log everything the command runs
+    #[serde(rename = "log_subcmds")]
+    LogSubcmds(bool),
+    // Run in the background, detached from the terminal, as with `sus -b`
+    #[serde(rename = "background")]
+    Background(bool),
+    // How long to wait for a password, in seconds, or zero to wait forever
+    #[serde(rename = "passwd_timeout")]
+    PasswdTimeout(u64),
+    // Print a `*` for each character of the password typed
+    #[serde(rename = "pwfeedback")]
+    Pwfeedback(bool),
+}
+// Modes are written in octal, like `"0022"`
+fn serialize_octal<S: Serializer>(m: &u32, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&format!("{:04o}", m))
+}
+fn deserialize_octal<'de, D: Deserializer<'de>>(d: D) -> Result<u32, D::Error> {
+    let s = String::deserialize(d)?;
+    match u32::from_str_radix(&s, 8) {
+        Ok(m) if m <= 0o777 => Ok(m),
+        _ => Err(serde::de::Error::custom(format!("bad mode `{}`", s))),
+    }
+}
+// Limits are written as numbers, or `"infinity"` for no limit
+fn serialize_limit<S: Serializer>(l: &std::option::Option<u64>, s: S) -> Result<S::Ok, S::Error> {
+    match l {
+        Some(n) => s.serialize_u64(*n),
+        None => s.serialize_str("infinity"),
+    }
+}
+fn deserialize_limit<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<std::option::Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Limit {
+        Number(u64),
+        Word(String),
+    }
+    match Limit::deserialize(d)? {
+        Limit::Number(n) => Ok(Some(n)),
+        Limit::Word(w) if w == "infinity" => Ok(None),
+        Limit::Word(w) => Err(serde::de::Error::custom(format!("bad limit `{}`", w))),
+    }
+}
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Rlimit {
+    pub resource: RlimitResource,
+    #[serde(serialize_with = "serialize_limit")]
+    #[serde(deserialize_with = "deserialize_limit")]
+    pub soft: std::option::Option<u64>,
+    #[serde(serialize_with = "serialize_limit")]
+    #[serde(deserialize_with = "deserialize_limit")]
+    pub hard: std::option::Option<u64>,
+}
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum RlimitResource {
+    #[serde(rename = "as")]
+    As,
+    #[serde(rename = "core")]
+    Core,
+    #[serde(rename = "cpu")]
+    Cpu,
+    #[serde(rename = "data")]
+    Data,
+    #[serde(rename = "fsize")]
+    Fsize,
+    #[serde(rename = "locks")]
+    Locks,
+    #[serde(rename = "memlock")]
+    Memlock,
+    #[serde(rename = "nofile")]
+    Nofile,
+    #[serde(rename = "nproc")]
+    Nproc,
+    #[serde(rename = "rss")]
+    Rss,
+    #[serde(rename = "stack")]
+    Stack,
+}
+impl RlimitResource {
+    pub const ALL: [RlimitResource; 11] = [
+        RlimitResource::As,
+        RlimitResource::Core,
+        RlimitResource::Cpu,
+        RlimitResource::Data,
+        RlimitResource::Fsize,
+        RlimitResource::Locks,
+        RlimitResource::Memlock,
+        RlimitResource::Nofile,
+        RlimitResource::Nproc,
+        RlimitResource::Rss,
+        RlimitResource::Stack,
+    ];
+    // The same names are used by sudo and `limits.conf`
+    pub fn name(self) -> &'static str {
+        match self {
+            RlimitResource::As => "as",
+            RlimitResource::Core => "core",
+            RlimitResource::Cpu => "cpu",
+            RlimitResource::Data => "data",
+            RlimitResource::Fsize => "fsize",
+            RlimitResource::Locks => "locks",
+            RlimitResource::Memlock => "memlock",
+            RlimitResource::Nofile => "nofile",
+            RlimitResource::Nproc => "nproc",
+            RlimitResource::Rss => "rss",
+            RlimitResource::Stack => "stack",
+        }
+    }
+    pub fn from_name(name: &str) -> std::option::Option<RlimitResource> {
+        RlimitResource::ALL
+            .iter()
+            .copied()
+            .find(|r| r.name() == name)
+    }
+}
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Ionice {
+    pub class: IoniceClass,
+    // Lower is higher priority, from 0 to 7
+    #[serde(default = "default_ionice_level")]
+    pub level: u8,
+}
+fn default_ionice_level() -> u8 {
+    4
+}
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum IoniceClass {
+    #[serde(rename = "realtime")]
+    Realtime,
+    #[serde(rename = "best-effort")]
+    BestEffort,
+    #[serde(rename = "idle")]
+    Idle,
+}
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Landlock {
+    // Everything under these may be read and executed
+    #[serde(default)]
+    pub read: Vec<String>,
+    // Everything under these may also be written, created, and removed
+    #[serde(default)]
+    pub write: Vec<String>,
+}
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Namespaces {
+    #[serde(default)]
+    pub mount: bool,
+    // The command is PID 1's only child, with `/proc` mounted to match, which
+    //  needs a new mount namespace too
+    #[serde(default)]
+    pub pid: bool,
+    #[serde(default)]
+    pub ipc: bool,
+    // Each of these also needs a new mount namespace
+    #[serde(default)]
+    pub private_tmp: bool,
+    #[serde(default)]
+    pub read_only_home: bool,
+}
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum RunnerKind {
+    // Replace the kernel with the command
+    #[serde(rename = "exec")]
+    Exec,
+    // Fork, wait for the command, and log how it ended
+    #[serde(rename = "supervise")]
+    Supervise,
+}
+// The options in effect for a request
+// These are the Defaults followed by the options of the matching command, so
+//  later options override earlier ones
+#[derive(Debug, Clone, Default)]
+pub struct Options(pub Vec<Option>);
+// Get the value of the last Option of a kind, if there is one
+// Later Options override earlier ones, so that's the one that applies
+macro_rules! last {
+    ($opts:expr, $kind:ident) => {
+        $opts.0.iter().rev().find_map(|o| match o {
+            Option::$kind(v) => Some(v),
+            _ => None,
+        })
+    };
+}
+
+impl Options {
+    pub fn runner(&self) -> std::option::Option<RunnerKind> {
+        last!(self, Runner).copied()
+    }
+    pub fn log_input(&self) -> bool {
+        last!(self, LogInput) == Some(&true)
+    }
+    pub fn log_output(&self) -> bool {
+        last!(self, LogOutput) == Some(&true)
+    }
+    pub fn use_pty(&self) -> bool {
+        last!(self, UsePty) == Some(&true)
+    }
+    pub fn closefrom(&self) -> std::option::Option<i32> {
+        last!(self, Closefrom).copied()
+    }
+    pub fn closefrom_override(&self) -> bool {
+        last!(self, ClosefromOverride) == Some(&true)
+    }
+    pub fn umask(&self) -> std::option::Option<u32> {
+        last!(self, Umask).copied()
+    }
+    // A timeout of zero means there isn't one
+    pub fn command_timeout(&self) -> std::option::Option<u64> {
+        last!(self, CommandTimeout).copied().filter(|t| *t > 0)
+    }
+    pub fn runcwd(&self) -> std::option::Option<&str> {
+        last!(self, Runcwd).map(String::as_str)
+    }
+    pub fn runchroot(&self) -> std::option::Option<&str> {
+        last!(self, Runchroot).map(String::as_str)
+    }
+    // Every limit applies, in order, so later ones for the same resource win
+    pub fn rlimits(&self) -> Vec<Rlimit> {
+        self.0
+            .iter()
+            .filter_map(|o| match o {
+                Option::Rlimit(r) => Some(*r),
+                _ => None,
+            })
+            .collect()
+    }
+    pub fn login_rlimits(&self) -> bool {
+        last!(self, LoginRlimits) == Some(&true)
+    }
+    pub fn nice(&self) -> std::option::Option<i32> {
+        last!(self, Nice).copied()
+    }
+    pub fn ionice(&self) -> std::option::Option<Ionice> {
+        last!(self, Ionice).copied()
+    }
+    pub fn cgroup(&self) -> bool {
+        last!(self, Cgroup) == Some(&true)
+            || self.cgroup_memory_max().is_some()
+            || self.cgroup_cpu_max().is_some()
+            || self.cgroup_pids_max().is_some()
+    }
+    pub fn cgroup_memory_max(&self) -> std::option::Option<&str> {
+        last!(self, CgroupMemoryMax).map(String::as_str)
+    }
+    pub fn cgroup_cpu_max(&self) -> std::option::Option<&str> {
+        last!(self, CgroupCpuMax).map(String::as_str)
+    }
+    pub fn cgroup_pids_max(&self) -> std::option::Option<&str> {
+        last!(self, CgroupPidsMax).map(String::as_str)
+    }
+    pub fn noexec(&self) -> bool {
+        last!(self, Noexec) == Some(&true)
+    }
+    pub fn landlock(&self) -> std::option::Option<&Landlock> {
+        last!(self, Landlock)
+    }
+    pub fn landlock_required(&self) -> bool {
+        last!(self, LandlockRequired) != Some(&false)
+    }
+    pub fn capabilities(&self) -> std::option::Option<Vec<Capability>> {
+        let mut ret = last!(self, Capabilities)?.clone();
+        ret.sort_unstable();
+        ret.dedup();
+        Some(ret)
+    }
+    pub fn namespaces(&self) -> std::option::Option<Namespaces> {
+        last!(self, Namespaces).copied()
+    }
+    pub fn intercept(&self) -> bool {
+        last!(self, Intercept) == Some(&true)
+    }
+    pub fn log_subcmds(&self) -> bool {
+        last!(self, LogSubcmds) == Some(&true)
+    }
+    pub fn authenticate(&self) -> bool {
+        // Unlike sudo, only ask for a password if told to
+        // Policies written before passwords were checked shouldn't start
+        //  prompting
+        last!(self, Authenticate) == Some(&true)
+    }
+    pub fn passwd_timeout(&self) -> std::option::Option<u64> {
+        last!(self, PasswdTimeout).copied()
+    }
+    pub fn pwfeedback(&self) -> bool {
+        last!(self, Pwfeedback) == Some(&true)
+    }
+    pub fn background(&self) -> bool {
+        last!(self, Background) == Some(&true)
+    }
+    pub fn user_command_timeouts(&self) -> bool {
+        last!(self, UserCommandTimeouts) == Some(&true)
+    }
 }
 #[derive(Deserialize, Serialize, Debug)]
 pub enum Command {
     #[serde(rename = "command")]
+    #[serde(serialize_with = "serialize_cstring")]
     CmdPath(CString),
+    // The files `sudoedit` may edit, as absolute paths
+    #[serde(rename = "sudoedit")]
+    Sudoedit(Vec<String>),
+}
+// CStrings serialize to arrays of bytes by default, but we read them as strings
+fn serialize_cstring<S: Serializer>(c: &CString, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&c.to_string_lossy())
 }
 #[derive(Deserialize, Serialize, Debug)]
 pub struct CmdSpec {
@@ -35,6 +419,7 @@ pub struct CmdSpec {
     pub run_as_users: Vec<User>,
     #[serde(rename = "runasgroups")]
     #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub run_as_groups: Vec<User>,
     #[serde(rename = "Options")]
     pub options: Vec<Option>,
@@ -49,27 +434,80 @@ pub struct UserSpec {
     pub host_list: Vec<Host>,
     #[serde(rename = "Cmnd_Specs")]
     pub cmd_specs: Vec<CmdSpec>,
+    // The file this spec was read from, for error reporting
+    #[serde(skip)]
+    pub source: String,
+}
+
+// Options that apply to every rule
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Defaults {
+    #[serde(rename = "Options")]
+    pub options: Vec<Option>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Sudoers {
+    #[serde(rename = "Defaults")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub defaults: Vec<Defaults>,
+    // Other files and directories of files to read, like `#include` and
+    // `#includedir` in sudo
+    #[serde(rename = "Includes")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub includes: Vec<String>,
+    #[serde(rename = "Include_Dirs")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include_dirs: Vec<String>,
+    // Kept sorted so that output is always the same
     #[serde(rename = "User_Aliases")]
-    pub user_aliases: HashMap<String, Vec<User>>,
+    #[serde(default)]
+    pub user_aliases: BTreeMap<String, Vec<User>>,
     #[serde(rename = "User_Specs")]
+    #[serde(default)]
     pub user_specs: Vec<UserSpec>,
 }
 
 impl Sudoers {
-    pub fn retrieve_ids(self) -> ParsedSudoers {
+    // Add the aliases and rules from a fragment read from `path`
+    // Aliases are shared between all the fragments, so they may only be defined
+    // once
+    pub fn merge(&mut self, other: Sudoers, path: &str) -> Result<(), SudoersError> {
+        for (name, users) in other.user_aliases {
+            if self.user_aliases.contains_key(&name) {
+                return Err(SudoersError::DuplicateAlias {
+                    path: path.to_string(),
+                    alias: name,
+                });
+            }
+            self.user_aliases.insert(name, users);
+        }
+        self.defaults.extend(other.defaults);
+        self.user_specs.extend(other.user_specs);
+        Ok(())
+    }
+
+    // The names are looked up with `source`, since the process parsing the
+    // policy may not be able to
+    pub fn retrieve_ids(
+        self,
+        source: &mut dyn PolicySource,
+    ) -> Result<ParsedSudoers, SudoersError> {
         let mut ps = ParsedSudoers {
             rules: Vec::new(),
-            user_aliases: HashMap::new(),
+            defaults: Vec::new(),
         };
+        for defaults in self.defaults {
+            ps.defaults.extend(defaults.options);
+        }
         for user_spec in self.user_specs {
-            let rule = Rule::from_userspec(&user_spec, &self.user_aliases);
+            let rule = Rule::from_userspec(&user_spec, &self.user_aliases, source)?;
             ps.rules.push(rule);
         }
-        ps
+        Ok(ps)
     }
 }
 
@@ -9,25 +9,33 @@
 use crate::executable::run::AbstractRunner;
 use crate::executable::run::RunError;
 use crate::executable::Executable;
+use crate::permission::authenticate::AbstractAuthenticator;
 use crate::permission::verify::Verifier;
 use crate::permission::verify::VerifyError;
 use crate::permission::verify::VerifyResult;
 use crate::permission::Permission;
+use crate::setting::Settings;
 
 #[cfg(feature = "log")]
 use crate::log::{AbstractLogger, LogError};
 
 use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
 
 /// Structure representing a user request
 ///
 /// The structure is composed of information needed to service the request. It
 /// contains the [Executable] the user wishes to run, as well as the
 /// [Permission]s the user wishes to run it as. It also has the current
-/// [Permission]s of the user. Finally, it has a list of [Verifier][vf]s to check,
-/// and an [Runner][rn] to run the [Executable].
+/// [Permission]s of the user, and any other [Settings] they asked for. Finally,
+/// it has a list of [Verifier][vf]s to check, an [Authenticator][au] to make
+/// sure the user is who they say they are, and an [Runner][rn] to run the
+/// [Executable].
 ///
 /// [vf]: crate::permission::verify::Verifier
+/// [au]: crate::permission::authenticate::Authenticator
 /// [rn]: crate::executable::run::Runner
 pub struct Request {
     /// The [Executable] to run
@@ -36,7 +44,22 @@ pub struct Request {
     /// The current [Permission]s of the user
     pub current_permissions: Permission,
     /// The [Permission]s to run the [Executable] with
+    ///
+    /// Once the [Verifier][vf]s pass, its [capabilities][cp] are set to the
+    /// ones the [Options][op] grant.
+    ///
+    /// [vf]: crate::permission::verify::Verifier
+    /// [cp]: Permission::capabilities
+    /// [op]: crate::permission::verify::Options
     pub requested_permissions: Permission,
+    /// Anything else the user asked for
+    ///
+    /// These are only used if the [Options][op] from the [Verifier][vf]s allow
+    /// them.
+    ///
+    /// [op]: crate::permission::verify::Options
+    /// [vf]: crate::permission::verify::Verifier
+    pub settings: Settings,
 
     /// A list of [Verifier][vf]s to check
     ///
@@ -46,6 +69,14 @@ pub struct Request {
     ///
     /// [vf]: crate::permission::verify::Verifier
     pub verifiers: Vec<Box<Verifier>>,
+    /// How to make sure the user is who they say they are
+    ///
+    /// This is only called if the [Verifier][vf]s pass, with the [Options][op]
+    /// they gave.
+    ///
+    /// [vf]: crate::permission::verify::Verifier
+    /// [op]: crate::permission::verify::Options
+    pub authenticator: Box<AbstractAuthenticator>,
     /// How to run the [Executable]
     pub runner: Box<AbstractRunner>,
 
@@ -85,9 +116,29 @@ impl Request {
                 );
                 res = res.or(verifier_result);
             }
+            // Check the settings against whatever allowed it
             // Return
-            res
+            res.and_then(|opts| self.settings.apply(opts))
         };
+        // The verifiers are done, so let them clean up before running
+        self.verifiers.clear();
+        // Make sure it's really the user, if whatever allowed it asks
+        let verify_res = verify_res.and_then(|opts| {
+            (self.authenticator)(
+                &self.current_permissions,
+                &self.requested_permissions,
+                &opts,
+                &self.settings,
+            )
+            .map(|_| opts)
+            .map_err(|e| VerifyError::NotAllowed {
+                err: Some(Box::new(e)),
+            })
+        });
+        // Only keep the capabilities whatever allowed it grants
+        if let Ok(opts) = &verify_res {
+            self.requested_permissions.capabilities = opts.capabilities();
+        }
         // Log the attempt result
         // Fail out immediately if we can't
         #[cfg(feature = "log")]
@@ -101,9 +152,10 @@ impl Request {
             .map_err(|e| RequestError::Log { cause: e })?;
         }
         // Fail out if we didn't verify
-        verify_res.map_err(|e| RequestError::Verify { cause: e })?;
+        // Otherwise, keep the options to run with
+        let options = verify_res.map_err(|e| RequestError::Verify { cause: e })?;
         // Execute and unwrap
-        (self.runner)(&self.requested_permissions, &self.executable)
+        (self.runner)(&self.requested_permissions, &self.executable, &options)
             .map_err(|e| RequestError::Run { cause: e })
     }
 }
@@ -135,3 +187,17 @@ pub enum RequestError {
     /// An error occurred when trying to run the [Executable]
     Run { cause: RunError },
 }
+
+impl Display for RequestError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RequestError::Verify { cause } => write!(f, "{}", cause)?,
+            #[cfg(feature = "log")]
+            RequestError::Log { cause } => write!(f, "Failed to log - {}", cause)?,
+            RequestError::Run { cause } => write!(f, "{}", cause)?,
+        };
+        Ok(())
+    }
+}
+
+impl Error for RequestError {}
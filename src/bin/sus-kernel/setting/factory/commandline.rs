@@ -0,0 +1,23 @@
+//! Parse [Settings][st] from the command line
+//!
+//! This module implements a method to parse [Settings][st] from command line
+//! arguments. The `sus` binary passes them to the kernel this way.
+//!
+//! [st]: super::Settings
+
+use super::from_iterator;
+use super::SettingsFactoryResult;
+
+use crate::config;
+
+/// Function to make [Settings][st] from command line arguments
+///
+/// It's essentially a wrapper around [from_iterator](super::from_iterator),
+/// passing in [SETTINGS_COMMANDLINE_IDX][sci] from the configuration file for
+/// `idx`.
+///
+/// [st]: super::Settings
+/// [sci]: crate::config::SETTINGS_COMMANDLINE_IDX
+pub fn from_commandline() -> SettingsFactoryResult {
+    from_iterator(std::env::args(), config::SETTINGS_COMMANDLINE_IDX)
+}
@@ -0,0 +1,69 @@
+//! Parse [Settings] from an [Iterator]
+//!
+//! This module implements a method to parse [Settings] from an [Iterator], like
+//! the command line arguments.
+
+use super::super::{Setting, Settings};
+use super::SettingsFactoryError;
+use super::SettingsFactoryResult;
+
+/// Function to make [Settings] from an [Iterator]
+///
+/// This function will consume the [Iterator] it's given, and parse the entry at
+/// `idx`. It expects a comma separated list of `name=value` pairs, like
+/// `closefrom=5`. A backslash takes the next character literally, so values
+/// can contain commas. An empty string means no [Settings].
+///
+/// Unknown names are an error, rather than being ignored. They might be
+/// something the user expected to be checked.
+pub fn from_iterator<I, S>(it: I, idx: usize) -> SettingsFactoryResult
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+{
+    // Collect the iterator into a vector
+    let args: Vec<S> = it.collect();
+    let s = args
+        .get(idx)
+        .ok_or(SettingsFactoryError::NotFound)?
+        .as_ref();
+
+    let mut ret = Vec::new();
+    for entry in split(s) {
+        let malformed = || SettingsFactoryError::Malformed {
+            content: entry.clone(),
+        };
+        let (name, value) = entry.split_once('=').ok_or_else(malformed)?;
+        ret.push(match name {
+            "closefrom" => Setting::Closefrom(value.parse().map_err(|_| malformed())?),
+            "command_timeout" => Setting::CommandTimeout(value.parse().map_err(|_| malformed())?),
+            "cwd" => Setting::Cwd(value.to_string()),
+            "background" => Setting::Background(value.parse().map_err(|_| malformed())?),
+            "non_interactive" => Setting::NonInteractive(value.parse().map_err(|_| malformed())?),
+            "stdin" => Setting::Stdin(value.parse().map_err(|_| malformed())?),
+            "prompt" => Setting::Prompt(value.to_string()),
+            "askpass" => Setting::Askpass(value.to_string()),
+            _ => return Err(malformed()),
+        });
+    }
+    Ok(Settings(ret))
+}
+
+/// Function to split a list on unescaped commas, removing the escapes
+fn split(s: &str) -> Vec<String> {
+    let mut ret = Vec::new();
+    if s.is_empty() {
+        return ret;
+    }
+    let mut cur = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => cur.extend(chars.next()),
+            ',' => ret.push(std::mem::take(&mut cur)),
+            _ => cur.push(c),
+        }
+    }
+    ret.push(cur);
+    ret
+}
@@ -0,0 +1,62 @@
+//! Module containing methods of creating [Settings]
+//!
+//! Like [Executable][eb]s and [Permission][pm]s, [Settings] could come from
+//! different places. This module provides the ways to create them, along with
+//! the result to be returned, and any errors.
+//!
+//! [eb]: crate::executable::Executable
+//! [pm]: crate::permission::Permission
+
+pub mod commandline;
+pub mod iterator;
+pub use commandline::from_commandline;
+pub use iterator::from_iterator;
+
+use super::Settings;
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// Type for (automatic) [Settings] factories
+///
+/// These are "Settings Factories," in the same sense as for
+/// [Executable][eb]s. We define one of these to be "Automatic" if it takes no
+/// arguments.
+///
+/// The [main](crate::main) function can use [AutoSettingsFactory]s during
+/// runtime to create [Settings].
+///
+/// [eb]: crate::executable::factory::AutoExecutableFactory
+pub type AutoSettingsFactory = fn() -> SettingsFactoryResult;
+
+/// Convinience type for the result of a [Settings] factory
+pub type SettingsFactoryResult = Result<Settings, SettingsFactoryError>;
+
+/// Error for [Settings] factories
+///
+/// The [Settings] might not be given at all, or they might not be parseable.
+#[derive(Debug)]
+pub enum SettingsFactoryError {
+    /// The list of [Settings] could not be located
+    NotFound,
+    /// A [Setting][st] could not be parsed, where `content` is the failing
+    /// string. Unknown [Setting][st]s are also reported this way.
+    ///
+    /// [st]: super::Setting
+    Malformed { content: String },
+}
+
+impl Display for SettingsFactoryError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SettingsFactoryError::NotFound => write!(f, "Settings not found")?,
+            SettingsFactoryError::Malformed { content } => {
+                write!(f, "Setting malformed - {:?}", content)?;
+            }
+        };
+        Ok(())
+    }
+}
+
+impl Error for SettingsFactoryError {}
@@ -0,0 +1,192 @@
+//! Module for settings the user asks for
+//!
+//! Besides what to run and who to run it as, the user can ask for some things
+//! to be done differently. For instance, `sus -C` asks for more file
+//! descriptors to be left open, `sus -T` asks for the command to be killed
+//! after a while, `sus -D` asks for it to run in another directory, and
+//! `sus -b` asks for it to run in the background. Others, like `sus -S`, change
+//! how the user is asked for their password. This module represents those
+//! [Settings].
+//!
+//! [Settings] come from the user, so they aren't trusted. Each [Setting] has to
+//! be allowed by the policy, unless it can't get the user anything more, like
+//! `sus -b`. If it is, it's turned into an [Option] added after the [Options]
+//! from the policy, so that it takes priority.
+
+pub mod factory;
+
+use crate::permission::verify::sudoers_type::Option;
+use crate::permission::verify::Options;
+use crate::permission::verify::VerifyError;
+use crate::permission::verify::VerifyResult;
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// A single setting the user asked for
+#[derive(Debug, Clone, PartialEq)]
+pub enum Setting {
+    /// Close file descriptors from this one up, instead of what the policy
+    /// says
+    Closefrom(i32),
+    /// Kill the [Executable][eb] after this many seconds
+    ///
+    /// [eb]: crate::executable::Executable
+    CommandTimeout(u64),
+    /// Run the [Executable][eb] in this directory
+    ///
+    /// [eb]: crate::executable::Executable
+    Cwd(String),
+    /// Run the [Executable][eb] in the background, detached from the terminal
+    ///
+    /// [eb]: crate::executable::Executable
+    Background(bool),
+    /// Fail instead of asking for a password
+    NonInteractive(bool),
+    /// Read the password from standard input instead of the terminal
+    Stdin(bool),
+    /// Ask for the password with this prompt, which can have escapes like `%u`
+    Prompt(String),
+    /// Ask for the password by running this program as the user
+    Askpass(String),
+}
+
+impl Display for Setting {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Setting::Closefrom(n) => write!(f, "closefrom={}", n)?,
+            Setting::CommandTimeout(t) => write!(f, "command_timeout={}", t)?,
+            Setting::Cwd(d) => write!(f, "cwd={}", d)?,
+            Setting::Background(b) => write!(f, "background={}", b)?,
+            Setting::NonInteractive(b) => write!(f, "non_interactive={}", b)?,
+            Setting::Stdin(b) => write!(f, "stdin={}", b)?,
+            Setting::Prompt(p) => write!(f, "prompt={}", p)?,
+            Setting::Askpass(p) => write!(f, "askpass={}", p)?,
+        };
+        Ok(())
+    }
+}
+
+/// Structure representing all the [Setting]s the user asked for
+#[derive(Debug, Clone, Default)]
+pub struct Settings(pub Vec<Setting>);
+
+impl Settings {
+    /// Function to check the [Settings] against the [Options] from the policy
+    ///
+    /// If every [Setting] is allowed, the [Options] are returned with the
+    /// [Setting]s added. Otherwise, a [NotAllowed][na] error is returned.
+    ///
+    /// [na]: VerifyError::NotAllowed
+    pub fn apply(&self, mut opts: Options) -> VerifyResult {
+        for setting in &self.0 {
+            let err = |e: SettingError| VerifyError::NotAllowed {
+                err: Some(Box::new(e)),
+            };
+            match setting {
+                Setting::Closefrom(n) => {
+                    if !opts.closefrom_override() {
+                        return Err(err(SettingError::NotAllowed {
+                            setting: setting.clone(),
+                        }));
+                    }
+                    // The standard streams can't be closed
+                    if *n < 3 {
+                        return Err(err(SettingError::Invalid {
+                            setting: setting.clone(),
+                        }));
+                    }
+                    opts.0.push(Option::Closefrom(*n));
+                }
+                Setting::CommandTimeout(t) => {
+                    if !opts.user_command_timeouts() {
+                        return Err(err(SettingError::NotAllowed {
+                            setting: setting.clone(),
+                        }));
+                    }
+                    // Zero would mean no timeout at all
+                    if *t == 0 {
+                        return Err(err(SettingError::Invalid {
+                            setting: setting.clone(),
+                        }));
+                    }
+                    // The user can only make the policy's timeout shorter
+                    let t = match opts.command_timeout() {
+                        Some(p) => p.min(*t),
+                        None => *t,
+                    };
+                    opts.0.push(Option::CommandTimeout(t));
+                }
+                Setting::Cwd(d) => {
+                    // The policy has to allow any directory, or this one
+                    match opts.runcwd() {
+                        Some(p) if p == "*" || p == d => (),
+                        _ => {
+                            return Err(err(SettingError::NotAllowed {
+                                setting: setting.clone(),
+                            }))
+                        }
+                    }
+                    // The kernel doesn't run in the user's directory, so
+                    //  relative paths are ambiguous
+                    if !d.starts_with('/') {
+                        return Err(err(SettingError::Invalid {
+                            setting: setting.clone(),
+                        }));
+                    }
+                    opts.0.push(Option::Runcwd(d.clone()));
+                }
+                Setting::Background(b) => {
+                    // This only changes when the user gets their shell back, so
+                    //  it's always allowed
+                    // The user can only turn it on, though, not override a
+                    //  policy that runs the command in the background
+                    if *b {
+                        opts.0.push(Option::Background(true));
+                    }
+                }
+                Setting::NonInteractive(_)
+                | Setting::Stdin(_)
+                | Setting::Prompt(_)
+                | Setting::Askpass(_) => {
+                    // These only change how the password is asked for, not
+                    //  what runs or how
+                }
+            }
+        }
+        Ok(opts)
+    }
+}
+
+/// Error for [Settings::apply]
+///
+/// The user may ask for a [Setting] the policy doesn't allow, or for one that
+/// doesn't make sense.
+#[derive(Debug)]
+pub enum SettingError {
+    /// The policy doesn't let the user change this
+    NotAllowed { setting: Setting },
+    /// The value given can't be used
+    Invalid { setting: Setting },
+}
+
+impl Display for SettingError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SettingError::NotAllowed { setting } => {
+                write!(f, "Setting {} is not allowed", setting)?;
+            }
+            SettingError::Invalid { setting } => {
+                write!(f, "Setting {} is invalid", setting)?;
+            }
+        };
+        Ok(())
+    }
+}
+
+impl Error for SettingError {
+    fn source(&self) -> std::option::Option<&(dyn Error + 'static)> {
+        None
+    }
+}
@@ -0,0 +1,143 @@
+//! Print a recorded session as plain text
+//!
+//! Terminal output is full of control sequences for colors, cursor movement,
+//! and window titles, which get in the way of searching it. This module strips
+//! them out, and applies carriage returns and backspaces the way a terminal
+//! would, so that each line reads the way it last looked on the screen.
+
+use crate::error::ReplayError;
+use crate::option::CommandLineOptions;
+use crate::session::{Event, Session};
+
+use std::io;
+use std::io::Write;
+
+/// Where in an escape sequence we are
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    /// Not in an escape sequence
+    Text,
+    /// After `ESC`
+    Escape,
+    /// In a Control Sequence, after `ESC [`
+    Control,
+    /// In a string, like an Operating System Command after `ESC ]`
+    String,
+    /// After `ESC` in a string, which might end it
+    StringEscape,
+}
+
+/// Structure that turns terminal output into plain text
+struct Cleaner {
+    state: State,
+    /// The line being built
+    line: Vec<u8>,
+    /// Where in `line` the next byte goes
+    cursor: usize,
+}
+
+impl Cleaner {
+    fn new() -> Cleaner {
+        Cleaner {
+            state: State::Text,
+            line: Vec::new(),
+            cursor: 0,
+        }
+    }
+
+    /// Function to process `data`, writing out every line it completes
+    fn feed(&mut self, data: &[u8], out: &mut impl Write) -> io::Result<()> {
+        for &b in data {
+            self.state = match (self.state, b) {
+                (State::Text, 0x1b) => State::Escape,
+                (State::Text, b'\n') => {
+                    self.line.push(b'\n');
+                    out.write_all(&self.line)?;
+                    self.line.clear();
+                    self.cursor = 0;
+                    State::Text
+                }
+                (State::Text, b'\r') => {
+                    self.cursor = 0;
+                    State::Text
+                }
+                (State::Text, 0x08) => {
+                    // Back up over a whole character
+                    while self.cursor > 0 {
+                        self.cursor -= 1;
+                        if self.line[self.cursor] & 0xc0 != 0x80 {
+                            break;
+                        }
+                    }
+                    State::Text
+                }
+                (State::Text, b) if b == b'\t' || (b >= 0x20 && b != 0x7f) => {
+                    if self.cursor < self.line.len() {
+                        self.line[self.cursor] = b;
+                    } else {
+                        self.line.push(b);
+                    }
+                    self.cursor += 1;
+                    State::Text
+                }
+                (State::Text, _) => State::Text,
+
+                (State::Escape, b'[') => State::Control,
+                (State::Escape, b']') | (State::Escape, b'P') | (State::Escape, b'X') => {
+                    State::String
+                }
+                (State::Escape, b'^') | (State::Escape, b'_') => State::String,
+                // Intermediate bytes, like in `ESC ( B`
+                (State::Escape, 0x20..=0x2f) => State::Escape,
+                (State::Escape, _) => State::Text,
+
+                (State::Control, 0x40..=0x7e) => State::Text,
+                (State::Control, _) => State::Control,
+
+                (State::String, 0x07) => State::Text,
+                (State::String, 0x1b) => State::StringEscape,
+                (State::String, _) => State::String,
+                (State::StringEscape, b'\\') => State::Text,
+                (State::StringEscape, _) => State::String,
+            };
+        }
+        Ok(())
+    }
+
+    /// Function to write out the last line, if it wasn't finished
+    fn finish(&mut self, out: &mut impl Write) -> io::Result<()> {
+        if !self.line.is_empty() {
+            self.line.push(b'\n');
+            out.write_all(&self.line)?;
+            self.line.clear();
+            self.cursor = 0;
+        }
+        Ok(())
+    }
+}
+
+/// Function to print `session` as plain text
+///
+/// The selected streams are printed in the order they were recorded.
+pub fn dump(session: &Session, opts: &CommandLineOptions) -> Result<(), ReplayError> {
+    let events = session.events()?;
+    let mut data = session.data(&opts.streams)?;
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let out_err = |cause| ReplayError::Io {
+        path: "-".to_string(),
+        cause,
+    };
+    let mut cleaner = Cleaner::new();
+
+    for event in events {
+        if let Event::Data { stream, len, .. } = event {
+            if let Some(buf) = data.next(stream, len)? {
+                cleaner.feed(&buf, &mut out).map_err(out_err)?;
+            }
+        }
+    }
+    cleaner.finish(&mut out).map_err(out_err)?;
+    out.flush().map_err(out_err)
+}
@@ -0,0 +1,59 @@
+//! Errors that `sus-replay` can report
+//!
+//! Reading recordings can fail in many ways, especially for old ones copied
+//! from other machines. This module defines the [ReplayError] type for that.
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// Type for reporting errors in `sus-replay`
+#[derive(Debug)]
+pub enum ReplayError {
+    /// An IO operation on `path` failed
+    Io { path: String, cause: std::io::Error },
+    /// A file in a recording didn't have the expected format
+    Malformed { path: String, message: String },
+    /// No session with the given ID or path was found
+    NotFound { id: String },
+    /// A time given on the command line couldn't be parsed
+    BadTime { string: String },
+    /// A user or group given on the command line doesn't exist
+    BadIdentity { string: String },
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io { path, cause } => {
+                write!(f, "Failed to access {} - {}", path, cause)?;
+            }
+            ReplayError::Malformed { path, message } => {
+                write!(f, "Bad recording at {} - {}", path, message)?;
+            }
+            ReplayError::NotFound { id } => {
+                write!(f, "No session {}", id)?;
+            }
+            ReplayError::BadTime { string } => {
+                write!(
+                    f,
+                    "Bad time `{}` - expected seconds since the epoch or YYYY-MM-DD[ HH:MM[:SS]]",
+                    string
+                )?;
+            }
+            ReplayError::BadIdentity { string } => {
+                write!(f, "No such user or group `{}`", string)?;
+            }
+        };
+        Ok(())
+    }
+}
+
+impl Error for ReplayError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReplayError::Io { cause, .. } => Some(cause),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! List, replay, and dump recorded sessions
+//!
+//! When `log_input` or `log_output` is set, the kernel records what happens
+//! while a command runs. This binary is modeled after `sudoreplay`, and is how
+//! those recordings are looked at afterwards. It can list the sessions, filtered
+//! by who ran what and when, replay one on the terminal as it happened, or dump
+//! one as plain text to be searched.
+//!
+//! Recordings made by `sudo` can be read too, including compressed ones.
+
+mod config;
+mod dump;
+mod error;
+mod option;
+mod play;
+mod session;
+mod time;
+
+// The kernel's modules
+// Much of them goes unused here
+#[allow(dead_code, unused_imports)]
+#[path = "../sus-kernel/executable/mod.rs"]
+mod executable;
+#[allow(dead_code, unused_imports)]
+#[path = "../sus-kernel/log/mod.rs"]
+mod log;
+#[allow(dead_code, unused_imports)]
+#[path = "../sus-kernel/permission/mod.rs"]
+mod permission;
+#[allow(dead_code, unused_imports)]
+#[path = "../sus-kernel/setting/mod.rs"]
+mod setting;
+
+use nix::unistd::{Gid, Uid};
+use std::path::Path;
+use std::process::exit;
+use structopt::StructOpt;
+
+use error::ReplayError;
+use option::CommandLineOptions;
+use session::{Identity, Session};
+
+/// The entrypoint of the binary
+///
+/// As is standard practice in Rust, most of the work is done by internal
+/// libraries, and this function simply calls into those libraries. It does
+/// what the user asked for, printing out an error if there was one.
+fn main() {
+    let opts = CommandLineOptions::from_args();
+
+    let base = Path::new(&opts.dir);
+    let res = match &opts.session {
+        _ if opts.list => list(base, &opts),
+        Some(id) => session::find(base, id).and_then(|s| {
+            if opts.dump {
+                dump::dump(&s, &opts)
+            } else {
+                play::play(&s, &opts)
+            }
+        }),
+        // Checked by `structopt`
+        None => unreachable!(),
+    };
+    if let Err(e) = res {
+        eprintln!("Error: {}", e);
+        exit(1);
+    }
+}
+
+/// Function to print every session that matches the filters given
+///
+/// Each session is printed on its own line, in a format like `sudoreplay -l`.
+fn list(base: &Path, opts: &CommandLineOptions) -> Result<(), ReplayError> {
+    // Resolve the filters up front, so typos are reported
+    let user = opts.user.as_deref().map(user_id).transpose()?;
+    let runas = opts.runas.as_deref().map(user_id).transpose()?;
+    let group = opts.group.as_deref().map(group_id).transpose()?;
+    let from = opts.from.as_deref().map(time::parse).transpose()?;
+    let to = opts.to.as_deref().map(time::parse).transpose()?;
+
+    for s in session::find_all(base)? {
+        // A filter that wasn't given is [None], and lets everything through
+        let checks = [
+            user.map(|u| is(&s.user, u)),
+            runas.map(|u| is(&s.runas, u)),
+            group.map(|g| matches!(&s.group, Some(sg) if is(sg, g))),
+            opts.command
+                .as_ref()
+                .map(|c| s.command.contains(c.as_str())),
+            from.map(|f| s.time >= f),
+            to.map(|t| s.time < t),
+        ];
+        let keep = checks.iter().all(|c| c.unwrap_or(true));
+        if keep {
+            print_session(&s);
+        }
+    }
+    Ok(())
+}
+
+/// Function to print a line describing a [Session]
+fn print_session(s: &Session) {
+    let group = match &s.group {
+        Some(g) => format!("GROUP={} ; ", g.name),
+        None => String::new(),
+    };
+    let tty = match &s.tty {
+        Some(t) => format!("TTY={} ; ", t),
+        None => String::new(),
+    };
+    let cwd = match &s.cwd {
+        Some(c) => format!("CWD={} ; ", c),
+        None => String::new(),
+    };
+    println!(
+        "{} : {} : {}{}USER={} ; {}TSID={} ; COMMAND={}",
+        time::format(s.time),
+        s.user.name,
+        tty,
+        cwd,
+        s.runas.name,
+        group,
+        s.id,
+        s.command
+    );
+}
+
+/// Function to check whether an [Identity] is the one with the ID `id`
+fn is<I: PartialEq + Copy>(ident: &Identity<I>, id: I) -> bool {
+    ident.id == Some(id)
+}
+
+/// Function to get a user ID from a name or `#uid`
+fn user_id(s: &str) -> Result<Uid, ReplayError> {
+    let bad = || ReplayError::BadIdentity {
+        string: s.to_string(),
+    };
+    match s.strip_prefix('#') {
+        Some(n) => n.parse().map(Uid::from_raw).map_err(|_| bad()),
+        None => users::get_user_by_name(s)
+            .map(|u| Uid::from_raw(u.uid()))
+            .ok_or_else(bad),
+    }
+}
+
+/// Function to get a group ID from a name or `#gid`
+fn group_id(s: &str) -> Result<Gid, ReplayError> {
+    let bad = || ReplayError::BadIdentity {
+        string: s.to_string(),
+    };
+    match s.strip_prefix('#') {
+        Some(n) => n.parse().map(Gid::from_raw).map_err(|_| bad()),
+        None => users::get_group_by_name(s)
+            .map(|g| Gid::from_raw(g.gid()))
+            .ok_or_else(bad),
+    }
+}
@@ -0,0 +1,120 @@
+//! Parse command line options from the user
+//!
+//! This module defines a [CommandLineOptions] struct, which is populated from
+//! the command line using the `structopt` library. It's modeled after the
+//! options `sudoreplay` takes.
+
+use structopt::clap::AppSettings;
+use structopt::StructOpt;
+
+use crate::config;
+use crate::executable::run::iolog::{Stream, STREAMS};
+
+/// Function to parse a [Stream] by name
+fn parse_stream(s: &str) -> Result<Stream, String> {
+    STREAMS
+        .iter()
+        .copied()
+        .find(|st| st.name() == s.trim())
+        .ok_or_else(|| {
+            format!(
+                "Unknown stream `{}` - use stdin, stdout, stderr, ttyin, or ttyout",
+                s
+            )
+        })
+}
+
+/// Function to parse a speed, which must be positive
+fn parse_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(f) if f > 0.0 && f.is_finite() => Ok(f),
+        _ => Err(format!("Bad speed `{}` - use a positive number", s)),
+    }
+}
+
+/// The `sus-replay` interface
+///
+/// List, replay, and dump sessions recorded with `log_input` or `log_output`.
+// We have to convince `clap` to not put the version. See:
+// <https://github.com/TeXitoi/structopt/issues/81
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "sus-replay",
+    global_settings = &[AppSettings::DisableVersion]
+)]
+pub struct CommandLineOptions {
+    /// The directory the sessions are recorded in
+    #[structopt(short = "d", default_value = config::IOLOG_DIR)]
+    pub dir: String,
+
+    /// List the recorded sessions instead of replaying one
+    #[structopt(short = "l")]
+    pub list: bool,
+
+    /// Only list sessions run by this user, given by name or as `#uid`
+    #[structopt(long, requires = "list")]
+    pub user: Option<String>,
+
+    /// Only list sessions run as this user, given by name or as `#uid`
+    #[structopt(long, requires = "list")]
+    pub runas: Option<String>,
+
+    /// Only list sessions run as this group, given by name or as `#gid`
+    #[structopt(long, requires = "list")]
+    pub group: Option<String>,
+
+    /// Only list sessions whose command contains this
+    #[structopt(long, requires = "list")]
+    pub command: Option<String>,
+
+    /// Only list sessions started at or after this time
+    ///
+    /// Times are either seconds since the epoch, or `YYYY-MM-DD[ HH:MM[:SS]]`
+    /// in UTC.
+    #[structopt(long, requires = "list")]
+    pub from: Option<String>,
+
+    /// Only list sessions started before this time
+    #[structopt(long, requires = "list")]
+    pub to: Option<String>,
+
+    /// How many times faster than real time to replay
+    #[structopt(short = "s", default_value = "1", parse(try_from_str = parse_speed))]
+    pub speed: f64,
+
+    /// The longest to wait between events, in seconds, or 0 for no limit
+    #[structopt(short = "m")]
+    pub max_wait: Option<f64>,
+
+    /// The streams to show, separated by commas
+    #[structopt(
+        short = "f",
+        require_delimiter = true,
+        default_value = "stdout,stderr,ttyout",
+        parse(try_from_str = parse_stream)
+    )]
+    pub streams: Vec<Stream>,
+
+    /// Print the session as plain text instead of replaying it
+    ///
+    /// Terminal control sequences are removed, so the output can be searched
+    /// with tools like `grep`.
+    #[structopt(long)]
+    pub dump: bool,
+
+    /// The ID of the session, or the path to its directory
+    #[structopt(required_unless = "list")]
+    pub session: Option<String>,
+}
+
+impl CommandLineOptions {
+    /// The longest to wait between events, or [None] for no limit
+    pub fn max_wait(&self) -> Option<f64> {
+        let m = self.max_wait.unwrap_or(config::DEFAULT_MAX_WAIT);
+        if m > 0.0 {
+            Some(m)
+        } else {
+            None
+        }
+    }
+}
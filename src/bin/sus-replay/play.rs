@@ -0,0 +1,155 @@
+//! Replay a recorded session on the terminal
+//!
+//! The recorded output is written out with the same delays between events as
+//! when it was recorded, scaled by the speed. When standard input is a
+//! terminal, the replay can be controlled from the keyboard:
+//!   * Space pauses and resumes
+//!   * `<` and `>` halve and double the speed
+//!   * Enter skips to the next event
+//!   * `q` or `^C` quits
+//!
+//! The terminal isn't resized to match the recording. If it's smaller than the
+//! one recorded, a warning is printed, since the output may look off.
+
+use crate::error::ReplayError;
+use crate::option::CommandLineOptions;
+use crate::session::{Event, Session};
+
+use nix::libc;
+use nix::poll::{PollFd, PollFlags};
+use nix::sys::termios;
+use nix::sys::termios::{LocalFlags, OutputFlags, SetArg, SpecialCharacterIndices, Termios};
+use nix::unistd;
+use std::io;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Structure that puts the terminal back the way it was when dropped
+struct TermGuard(Termios);
+
+impl Drop for TermGuard {
+    fn drop(&mut self) {
+        let _ = termios::tcsetattr(0, SetArg::TCSADRAIN, &self.0);
+    }
+}
+
+/// Function to replay `session`
+pub fn play(session: &Session, opts: &CommandLineOptions) -> Result<(), ReplayError> {
+    let events = session.events()?;
+
+    let mut data = session.data(&opts.streams)?;
+
+    // Warn if the terminal is too small
+    let (mut rows, mut cols) = session.size;
+    for e in &events {
+        if let Event::Window {
+            rows: r, cols: c, ..
+        } = e
+        {
+            rows = rows.max(*r);
+            cols = cols.max(*c);
+        }
+    }
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(1, libc::TIOCGWINSZ, &mut ws) } == 0
+        && (ws.ws_row < rows || ws.ws_col < cols)
+    {
+        eprintln!(
+            "Warning: The terminal is {}x{}, but the session needs {}x{}",
+            ws.ws_col, ws.ws_row, cols, rows
+        );
+    }
+
+    // Read keys one at a time, without echoing them
+    // Signals are turned off too, so `^C` can be handled like `q` and the
+    //  terminal is always restored
+    // The output was already processed by the terminal it was recorded on, so
+    //  don't process it again
+    let guard = match termios::tcgetattr(0) {
+        Err(_) => None,
+        Ok(orig) => {
+            let mut t = orig.clone();
+            t.local_flags
+                .remove(LocalFlags::ICANON | LocalFlags::ECHO | LocalFlags::ISIG);
+            t.output_flags.remove(OutputFlags::OPOST);
+            t.control_chars[SpecialCharacterIndices::VMIN as usize] = 1;
+            t.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+            let _ = termios::tcsetattr(0, SetArg::TCSADRAIN, &t);
+            Some(TermGuard(orig))
+        }
+    };
+    let interactive = guard.is_some();
+
+    let mut speed = opts.speed;
+    let mut paused = false;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let out_err = |cause| ReplayError::Io {
+        path: "-".to_string(),
+        cause,
+    };
+
+    for event in events {
+        // Wait, in recorded time
+        let mut remaining = event.delay().as_secs_f64();
+        if let Some(m) = opts.max_wait() {
+            remaining = remaining.min(m);
+        }
+        if interactive {
+            while paused || remaining > 0.0 {
+                let timeout = if paused {
+                    -1
+                } else {
+                    (remaining / speed * 1000.0).ceil().min(i32::MAX as f64) as i32
+                };
+                let start = Instant::now();
+                let key = wait_key(timeout);
+                if !paused {
+                    remaining -= start.elapsed().as_secs_f64() * speed;
+                }
+                match key {
+                    None => (),
+                    Some(b' ') => paused = !paused,
+                    Some(b'<') => speed /= 2.0,
+                    Some(b'>') => speed *= 2.0,
+                    Some(b'\r') | Some(b'\n') => {
+                        paused = false;
+                        remaining = 0.0;
+                    }
+                    Some(b'q') | Some(0x03) => return Ok(()),
+                    Some(_) => (),
+                }
+            }
+        } else if remaining > 0.0 {
+            // A slow enough speed can make this longer than a Duration holds
+            let secs = (remaining / speed).min(u32::MAX as f64);
+            std::thread::sleep(Duration::from_secs_f64(secs));
+        }
+
+        // Show it
+        if let Event::Data { stream, len, .. } = event {
+            if let Some(buf) = data.next(stream, len)? {
+                out.write_all(&buf).map_err(out_err)?;
+                out.flush().map_err(out_err)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Function to wait up to `timeout` milliseconds for a key
+///
+/// A negative `timeout` waits forever. It returns the key pressed, if any.
+fn wait_key(timeout: i32) -> Option<u8> {
+    let mut fds = [PollFd::new(0, PollFlags::POLLIN)];
+    match nix::poll::poll(&mut fds, timeout) {
+        Ok(n) if n > 0 => {
+            let mut key = [0u8];
+            match unistd::read(0, &mut key) {
+                Ok(1) => Some(key[0]),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,443 @@
+//! Find and read recorded sessions
+//!
+//! Recordings are directories containing a `timing` file, one file for each
+//! stream of data, and a file describing the session. This is the layout the
+//! kernel writes, and the layout `sudo` has used for its I/O logs for a long
+//! time, so both can be read. See [iolog][il] for the details.
+//!
+//! `sudo` describes the session either in `log.json`, or in the older `log`
+//! file. Its first line is `time:user:runas_user:runas_group:tty`, optionally
+//! followed by `:lines:columns`. The second line is the working directory, and
+//! the third is the command. Either way, the files may also be compressed with
+//! `gzip`, which `sudo` does by default.
+//!
+//! [il]: crate::executable::run::iolog
+
+use crate::error::ReplayError;
+use crate::executable::run::iolog::{Stream, STREAMS, WINDOW_EVENT};
+
+use flate2::read::MultiGzDecoder;
+use nix::unistd::{Gid, Uid};
+use serde_json::Value;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A user or group in a recording
+///
+/// Old recordings only have names, and recordings from the kernel only have
+/// IDs. Whichever is missing is looked up on this machine, if possible.
+#[derive(Debug, Clone)]
+pub struct Identity<I> {
+    /// The name to show
+    pub name: String,
+    /// The ID, if it's known
+    pub id: Option<I>,
+}
+
+impl Identity<Uid> {
+    /// Function to get a user from a name, an ID, or both
+    fn user(name: Option<&str>, id: Option<u32>) -> Option<Self> {
+        let id = id
+            .map(Uid::from_raw)
+            .or_else(|| users::get_user_by_name(name?).map(|u| Uid::from_raw(u.uid())));
+        let name = match (name, id) {
+            (Some(n), _) => n.to_string(),
+            (None, Some(i)) => users::get_user_by_uid(i.as_raw()).map_or(format!("#{}", i), |u| {
+                u.name().to_string_lossy().to_string()
+            }),
+            (None, None) => return None,
+        };
+        Some(Identity { name, id })
+    }
+}
+
+impl Identity<Gid> {
+    /// Function to get a group from a name, an ID, or both
+    fn group(name: Option<&str>, id: Option<u32>) -> Option<Self> {
+        let id = id
+            .map(Gid::from_raw)
+            .or_else(|| users::get_group_by_name(name?).map(|g| Gid::from_raw(g.gid())));
+        let name = match (name, id) {
+            (Some(n), _) => n.to_string(),
+            (None, Some(i)) => users::get_group_by_gid(i.as_raw()).map_or(format!("#{}", i), |g| {
+                g.name().to_string_lossy().to_string()
+            }),
+            (None, None) => return None,
+        };
+        Some(Identity { name, id })
+    }
+}
+
+/// Information about a recorded session
+#[derive(Debug)]
+pub struct Session {
+    /// The ID of the session
+    ///
+    /// For the usual layout, this is the six characters of the directory
+    /// names. Otherwise, it's the path relative to the base directory.
+    pub id: String,
+    /// The directory the recording is in
+    pub dir: PathBuf,
+    /// When the session started, in seconds since the epoch
+    pub time: i64,
+    /// Who ran the command
+    pub user: Identity<Uid>,
+    /// Who the command ran as
+    pub runas: Identity<Uid>,
+    /// What group the command ran as, if it was recorded
+    pub group: Option<Identity<Gid>>,
+    /// The terminal the user was on, if it was recorded
+    pub tty: Option<String>,
+    /// The working directory, if it was recorded
+    pub cwd: Option<String>,
+    /// The command, with its arguments
+    pub command: String,
+    /// The size of the terminal at the start, in rows and columns
+    pub size: (u16, u16),
+}
+
+/// An event in the `timing` file
+#[derive(Debug)]
+pub enum Event {
+    /// `len` bytes were written on `stream`
+    Data {
+        stream: Stream,
+        delay: Duration,
+        len: usize,
+    },
+    /// The terminal changed size
+    Window {
+        delay: Duration,
+        rows: u16,
+        cols: u16,
+    },
+    /// Something that doesn't affect the output, like the command being
+    /// suspended
+    Other { delay: Duration },
+}
+
+impl Event {
+    /// The time since the last event
+    pub fn delay(&self) -> Duration {
+        match self {
+            Event::Data { delay, .. } | Event::Window { delay, .. } | Event::Other { delay } => {
+                *delay
+            }
+        }
+    }
+}
+
+/// The data recorded for some of the [Stream]s of a session
+///
+/// Each [Stream] is read from the start, a chunk for each [Event::Data].
+pub struct Data {
+    dir: PathBuf,
+    /// The file for each [Stream], indexed by its event number
+    ///
+    /// A [Stream] that wasn't asked for or wasn't recorded has [None].
+    readers: Vec<Option<Box<dyn Read>>>,
+}
+
+impl Data {
+    /// Function to read the next `len` bytes recorded on `stream`
+    ///
+    /// It returns [None] if the [Stream] isn't being read.
+    pub fn next(&mut self, stream: Stream, len: usize) -> Result<Option<Vec<u8>>, ReplayError> {
+        let r = match &mut self.readers[stream as usize] {
+            None => return Ok(None),
+            Some(r) => r,
+        };
+        let mut buf = Vec::with_capacity(len);
+        r.by_ref()
+            .take(len as u64)
+            .read_to_end(&mut buf)
+            .map_err(|cause| ReplayError::Io {
+                path: self.dir.join(stream.name()).to_string_lossy().to_string(),
+                cause,
+            })?;
+        Ok(Some(buf))
+    }
+}
+
+/// Function to open a file of a recording
+///
+/// The file is decompressed if it starts with the `gzip` magic number. It
+/// returns [None] if the file doesn't exist, since `sudo` only creates files
+/// for the streams it records.
+pub fn open(dir: &Path, name: &str) -> Result<Option<Box<dyn Read>>, ReplayError> {
+    let path = dir.join(name);
+    let io_err = |cause| ReplayError::Io {
+        path: path.to_string_lossy().to_string(),
+        cause,
+    };
+    let file = match File::open(&path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(io_err(e)),
+    };
+    let mut reader = BufReader::new(file);
+    let gzipped = reader
+        .fill_buf()
+        .map_err(io_err)?
+        .starts_with(&[0x1f, 0x8b]);
+    Ok(Some(if gzipped {
+        Box::new(MultiGzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    }))
+}
+
+/// Function to read a whole file of a recording as text
+fn read_text(dir: &Path, name: &str) -> Result<Option<String>, ReplayError> {
+    let mut ret = String::new();
+    match open(dir, name)? {
+        None => Ok(None),
+        Some(mut r) => {
+            r.read_to_string(&mut ret)
+                .map_err(|cause| ReplayError::Io {
+                    path: dir.join(name).to_string_lossy().to_string(),
+                    cause,
+                })?;
+            Ok(Some(ret))
+        }
+    }
+}
+
+impl Session {
+    /// Function to read the session recorded in `dir`
+    ///
+    /// It returns [None] if `dir` isn't a recording at all.
+    pub fn load(dir: &Path, id: String) -> Result<Option<Session>, ReplayError> {
+        if !dir.join("timing").is_file() {
+            return Ok(None);
+        }
+        if let Some(json) = read_text(dir, "log.json")? {
+            let v: Value = serde_json::from_str(&json).map_err(|e| ReplayError::Malformed {
+                path: dir.join("log.json").to_string_lossy().to_string(),
+                message: e.to_string(),
+            })?;
+            return Ok(Some(Session::from_json(dir, id, &v)));
+        }
+        match read_text(dir, "log")? {
+            Some(text) => Session::from_legacy(dir, id, &text).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Function to read a session described by `log.json`
+    ///
+    /// Anything missing is left out, so that the session can still be played.
+    fn from_json(dir: &Path, id: String, v: &Value) -> Session {
+        let str_of = |k: &str| v.get(k).and_then(Value::as_str);
+        let int_of = |k: &str| v.get(k).and_then(Value::as_u64);
+        let id_of = |k: &str| int_of(k).map(|i| i as u32);
+
+        // The arguments include the name of the command
+        let path = str_of("command").unwrap_or("").to_string();
+        let args: Vec<&str> = v
+            .get("runargv")
+            .and_then(Value::as_array)
+            .map(|a| a.iter().skip(1).filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        let command = std::iter::once(path.as_str())
+            .chain(args)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Session {
+            id,
+            dir: dir.to_path_buf(),
+            time: v
+                .pointer("/timestamp/seconds")
+                .and_then(Value::as_i64)
+                .unwrap_or(0),
+            user: Identity::user(str_of("submituser"), id_of("submituid")).unwrap_or(Identity {
+                name: "unknown".to_string(),
+                id: None,
+            }),
+            // sudo leaves out the user when it's root
+            runas: Identity::user(str_of("runuser"), id_of("runuid"))
+                .unwrap_or_else(|| Identity::user(None, Some(0)).unwrap()),
+            group: Identity::group(str_of("rungroup"), id_of("rungid")),
+            tty: str_of("ttyname").map(str::to_string),
+            cwd: str_of("cwd").map(str::to_string),
+            command,
+            size: (
+                int_of("lines").unwrap_or(24) as u16,
+                int_of("columns").unwrap_or(80) as u16,
+            ),
+        }
+    }
+
+    /// Function to read a session described by the older `log` file
+    fn from_legacy(dir: &Path, id: String, text: &str) -> Result<Session, ReplayError> {
+        let bad = |message: &str| ReplayError::Malformed {
+            path: dir.join("log").to_string_lossy().to_string(),
+            message: message.to_string(),
+        };
+        let mut lines = text.lines();
+        let fields: Vec<&str> = lines
+            .next()
+            .ok_or_else(|| bad("empty"))?
+            .split(':')
+            .collect();
+        if fields.len() < 5 {
+            return Err(bad("not enough fields on the first line"));
+        }
+        let non_empty = |i: usize| Some(fields[i]).filter(|s| !s.is_empty());
+        let size_of = |i: usize, default| {
+            fields
+                .get(i)
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(default)
+        };
+
+        Ok(Session {
+            id,
+            dir: dir.to_path_buf(),
+            time: fields[0].parse().map_err(|_| bad("bad time"))?,
+            user: Identity::user(Some(fields[1]), None).unwrap(),
+            runas: Identity::user(Some(fields[2]), None).unwrap(),
+            group: Identity::group(non_empty(3), None),
+            tty: non_empty(4).map(str::to_string),
+            cwd: lines.next().map(str::to_string),
+            command: lines.next().unwrap_or("").to_string(),
+            size: (size_of(5, 24), size_of(6, 80)),
+        })
+    }
+
+    /// Function to open the data recorded for the given [Stream]s
+    pub fn data(&self, streams: &[Stream]) -> Result<Data, ReplayError> {
+        let mut readers = Vec::new();
+        for s in STREAMS {
+            readers.push(if streams.contains(&s) {
+                open(&self.dir, s.name())?
+            } else {
+                None
+            });
+        }
+        Ok(Data {
+            dir: self.dir.clone(),
+            readers,
+        })
+    }
+
+    /// Function to read the `timing` file of the session
+    pub fn events(&self) -> Result<Vec<Event>, ReplayError> {
+        let path = self.dir.join("timing").to_string_lossy().to_string();
+        let bad = |n: usize| ReplayError::Malformed {
+            path: path.clone(),
+            message: format!("bad line {}", n + 1),
+        };
+        let text = read_text(&self.dir, "timing")?.unwrap_or_default();
+
+        let mut ret = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
+            }
+            if fields.len() < 3 {
+                return Err(bad(n));
+            }
+            let kind: u32 = fields[0].parse().map_err(|_| bad(n))?;
+            // Anything a Duration can't hold is as bad as a negative delay
+            let delay = fields[1]
+                .parse::<f64>()
+                .ok()
+                .filter(|d| *d >= 0.0 && *d < u64::MAX as f64)
+                .map(Duration::from_secs_f64)
+                .ok_or_else(|| bad(n))?;
+            let num = |i: usize| fields.get(i).and_then(|s| s.parse::<usize>().ok());
+            ret.push(match kind {
+                k if (k as usize) < STREAMS.len() => Event::Data {
+                    stream: STREAMS[k as usize],
+                    delay,
+                    len: num(2).ok_or_else(|| bad(n))?,
+                },
+                WINDOW_EVENT => Event::Window {
+                    delay,
+                    rows: num(2).ok_or_else(|| bad(n))? as u16,
+                    cols: num(3).ok_or_else(|| bad(n))? as u16,
+                },
+                _ => Event::Other { delay },
+            });
+        }
+        Ok(ret)
+    }
+}
+
+/// Function to find every session recorded under `base`
+///
+/// Directories are searched recursively, without following symbolic links.
+/// The sessions are returned in the order they started.
+pub fn find_all(base: &Path) -> Result<Vec<Session>, ReplayError> {
+    let mut ret = Vec::new();
+    walk(base, base, &mut ret)?;
+    ret.sort_by(|a, b| (a.time, &a.id).cmp(&(b.time, &b.id)));
+    Ok(ret)
+}
+
+/// Function to add every session under `dir` to `into`
+fn walk(base: &Path, dir: &Path, into: &mut Vec<Session>) -> Result<(), ReplayError> {
+    if let Some(s) = Session::load(dir, id_of(base, dir))? {
+        into.push(s);
+        return Ok(());
+    }
+    let io_err = |cause| ReplayError::Io {
+        path: dir.to_string_lossy().to_string(),
+        cause,
+    };
+    for entry in fs::read_dir(dir).map_err(io_err)? {
+        let entry = entry.map_err(io_err)?;
+        if entry.file_type().map_err(io_err)?.is_dir() {
+            walk(base, &entry.path(), into)?;
+        }
+    }
+    Ok(())
+}
+
+/// Function to get the ID of the session in `dir`
+///
+/// For the usual `XX/XX/XX` layout, it's the six characters. Otherwise, it's
+/// the path relative to `base`.
+fn id_of(base: &Path, dir: &Path) -> String {
+    let rel = dir.strip_prefix(base).unwrap_or(dir);
+    let parts: Vec<String> = rel
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    let usual = parts.len() == 3
+        && parts
+            .iter()
+            .all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_alphanumeric()));
+    if usual {
+        parts.concat()
+    } else {
+        rel.to_string_lossy().to_string()
+    }
+}
+
+/// Function to find a single session
+///
+/// `id` can be the ID of a session under `base`, a path relative to `base`, or
+/// the path to the recording itself.
+pub fn find(base: &Path, id: &str) -> Result<Session, ReplayError> {
+    let mut candidates = Vec::new();
+    if id.len() == 6 && id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        candidates.push(base.join(&id[0..2]).join(&id[2..4]).join(&id[4..6]));
+    }
+    candidates.push(base.join(id));
+    candidates.push(PathBuf::from(id));
+
+    for dir in candidates {
+        if let Some(s) = Session::load(&dir, id.to_string())? {
+            return Ok(s);
+        }
+    }
+    Err(ReplayError::NotFound { id: id.to_string() })
+}
@@ -0,0 +1,96 @@
+//! Convert between timestamps and calendar dates
+//!
+//! Recordings store times as seconds since the epoch, but people think in
+//! dates. This module converts between the two, always in UTC, so that the
+//! output doesn't depend on where it's run.
+//!
+//! The conversions use the algorithms from Howard Hinnant's `chrono`-compatible
+//! date library, which work for any date in the proleptic Gregorian calendar.
+
+use crate::error::ReplayError;
+
+/// Function to get the number of days since the epoch for a date
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Function to get the date for a number of days since the epoch
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400;
+    (if m <= 2 { y + 1 } else { y }, m, d)
+}
+
+/// Function to format seconds since the epoch as `YYYY-MM-DD HH:MM:SS`
+pub fn format(secs: i64) -> String {
+    let (y, m, d) = civil_from_days(secs.div_euclid(86400));
+    let s = secs.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        y,
+        m,
+        d,
+        s / 3600,
+        s / 60 % 60,
+        s % 60
+    )
+}
+
+/// Function to parse a time given on the command line
+///
+/// It can either be a number of seconds since the epoch, or a date like
+/// `YYYY-MM-DD`, optionally followed by a space or `T` and `HH:MM` or
+/// `HH:MM:SS`.
+pub fn parse(s: &str) -> Result<i64, ReplayError> {
+    let bad = || ReplayError::BadTime {
+        string: s.to_string(),
+    };
+    if let Ok(secs) = s.parse::<i64>() {
+        return Ok(secs);
+    }
+
+    // Split into the date and the time
+    let (date, time) = match s.find([' ', 'T']) {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, ""),
+    };
+    let num = |p: &str| p.parse::<i64>().map_err(|_| bad());
+
+    let date: Vec<&str> = date.split('-').collect();
+    if date.len() != 3 {
+        return Err(bad());
+    }
+    let (y, m, d) = (num(date[0])?, num(date[1])?, num(date[2])?);
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return Err(bad());
+    }
+
+    let time: Vec<&str> = match time {
+        "" => Vec::new(),
+        t => t.split(':').collect(),
+    };
+    let mut hms = [0i64; 3];
+    if time.len() == 1 || time.len() > 3 {
+        return Err(bad());
+    }
+    for (i, part) in time.iter().enumerate() {
+        hms[i] = num(part)?;
+    }
+    if hms[0] > 23 || hms[1] > 59 || hms[2] > 60 {
+        return Err(bad());
+    }
+
+    Ok(days_from_civil(y, m, d) * 86400 + hms[0] * 3600 + hms[1] * 60 + hms[2])
+}
@@ -0,0 +1,256 @@
+//! Edit and check the policy file
+//!
+//! This module has the two things `sus-visudo` can do. It can [check] a policy
+//! for errors, or it can [edit] one. Editing is done on a temporary copy, which
+//! is only installed over the original once it loads without error. The policy
+//! is locked the whole time, so two administrators can't overwrite each other's
+//! changes.
+//!
+//! Both use the same code the kernel does to load the policy. So, anything
+//! these functions accept, the kernel will accept too.
+
+use crate::config;
+use crate::error::VisudoError;
+use crate::option::CommandLineOptions;
+use crate::permission::verify::sudoers::{parse_sudoers_from, LocalSource};
+
+use nix::errno::Errno;
+use nix::fcntl;
+use nix::fcntl::FlockArg;
+use nix::sys::stat;
+use nix::sys::stat::Mode;
+use nix::unistd;
+use nix::unistd::{Gid, Uid};
+use std::env;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::process::Command;
+
+/// Function to check the policy for errors
+///
+/// This just tries to load the policy, including everything it includes. The
+/// owner and mode of the files are checked unless the user gave a file
+/// explicitly.
+pub fn check(opts: &CommandLineOptions) -> Result<(), VisudoError> {
+    parse_sudoers_from(
+        opts.path(),
+        &mut LocalSource {
+            check_secure: opts.check_secure(),
+        },
+    )
+    .map(|_| ())
+    .map_err(|e| VisudoError::Policy { cause: e })
+}
+
+/// Function to edit the policy
+///
+/// This locks the policy, copies it to a temporary file, and runs the user's
+/// editor on that. When the editor exits, the result is loaded the same way the
+/// kernel would. If that fails, the user can choose to edit again or to give
+/// up. Otherwise, the temporary file is given the right owner and mode, then
+/// atomically renamed over the policy.
+///
+/// Nothing is installed if the file wasn't changed.
+pub fn edit(opts: &CommandLineOptions) -> Result<(), VisudoError> {
+    let path = opts.path();
+    let io_err = |e| VisudoError::Io {
+        path: path.to_string(),
+        cause: e,
+    };
+
+    // Open and lock the policy
+    // Create it if it doesn't exist, so there's something to lock
+    let mut policy = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .mode(config::SUDOER_INSTALL_MODE)
+        .open(path)
+        .map_err(io_err)?;
+    fcntl::flock(policy.as_raw_fd(), FlockArg::LockExclusiveNonblock).map_err(|en| {
+        if en == Errno::EWOULDBLOCK {
+            VisudoError::Busy {
+                path: path.to_string(),
+            }
+        } else {
+            VisudoError::SyscallFailure {
+                name: "flock",
+                err: en,
+            }
+        }
+    })?;
+
+    // Copy it to the temporary file
+    // Any temporary file that's already there is stale, since we hold the lock
+    let mut original = Vec::new();
+    policy.read_to_end(&mut original).map_err(io_err)?;
+    let temp = TempFile::create(&format!("{}{}", path, config::TEMP_SUFFIX), &original)?;
+
+    // Edit until it loads or the user gives up
+    loop {
+        run_editor(&temp.path)?;
+
+        // Don't do anything if nothing changed
+        let edited = fs::read(&temp.path).map_err(|e| temp.io_err(e))?;
+        if edited == original {
+            println!("{}: unchanged", path);
+            return Ok(());
+        }
+
+        match parse_sudoers_from(
+            &temp.path,
+            &mut LocalSource {
+                check_secure: opts.check_secure(),
+            },
+        ) {
+            Ok(_) => break,
+            Err(e) => {
+                println!("Error: {}", e);
+                if !ask_edit_again().map_err(|e| temp.io_err(e))? {
+                    println!("{}: unchanged", path);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    // Set the owner and mode
+    // If the user gave a file explicitly, just keep its mode
+    let temp_file = File::open(&temp.path).map_err(|e| temp.io_err(e))?;
+    let mode = if opts.check_secure() {
+        unistd::fchown(
+            temp_file.as_raw_fd(),
+            Some(Uid::from_raw(config::SUDOER_OWNER_UID)),
+            Some(Gid::from_raw(config::SUDOER_INSTALL_GID)),
+        )
+        .map_err(|en| VisudoError::SyscallFailure {
+            name: "fchown",
+            err: en,
+        })?;
+        config::SUDOER_INSTALL_MODE
+    } else {
+        policy.metadata().map_err(io_err)?.mode()
+    };
+    stat::fchmod(temp_file.as_raw_fd(), Mode::from_bits_truncate(mode)).map_err(|en| {
+        VisudoError::SyscallFailure {
+            name: "fchmod",
+            err: en,
+        }
+    })?;
+    temp_file.sync_all().map_err(|e| temp.io_err(e))?;
+
+    // Install it
+    // Sync the directory too so the rename sticks
+    fs::rename(&temp.path, path).map_err(io_err)?;
+    if let Some(parent) = Path::new(path).parent() {
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        File::open(parent)
+            .and_then(|d| d.sync_all())
+            .map_err(io_err)?;
+    }
+    Ok(())
+}
+
+/// Function to run the user's editor on `path`
+///
+/// Like `sudo`, the editor is taken from `SUDO_EDITOR`, `VISUAL`, or `EDITOR`,
+/// in that order. It's split on whitespace so that it can have arguments. If
+/// none of them are set, [config::DEFAULT_EDITOR] is used.
+fn run_editor(path: &str) -> Result<(), VisudoError> {
+    // Find the editor
+    let editor = ["SUDO_EDITOR", "VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|v| env::var(v).ok())
+        .find(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| config::DEFAULT_EDITOR.to_string());
+
+    // Run it
+    let mut words = editor.split_whitespace();
+    let status = Command::new(words.next().unwrap_or(config::DEFAULT_EDITOR))
+        .args(words)
+        .arg(path)
+        .status()
+        .map_err(|e| VisudoError::EditorNotRun {
+            editor: editor.clone(),
+            cause: e,
+        })?;
+    if !status.success() {
+        return Err(VisudoError::EditorFailed { editor, status });
+    }
+    Ok(())
+}
+
+/// Function to ask the user whether they want to edit again
+///
+/// It keeps asking until it gets an answer. It returns `true` if the user wants
+/// to edit again, and `false` if they want to leave without saving, including
+/// if there's no more input.
+fn ask_edit_again() -> io::Result<bool> {
+    let stdin = io::stdin();
+    loop {
+        print!("What now? (e)dit again or e(x)it without saving: ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            println!();
+            return Ok(false);
+        }
+        match line.trim() {
+            "e" => return Ok(true),
+            "x" => return Ok(false),
+            _ => (),
+        }
+    }
+}
+
+/// A temporary file that is removed when dropped
+///
+/// The file is created with mode `0600` so nobody else can see the policy
+/// while it's being edited. If it's renamed away, removing it does nothing.
+struct TempFile {
+    path: String,
+}
+
+impl TempFile {
+    /// Create the file at `path` with the given contents, replacing any that's
+    /// already there
+    fn create(path: &str, contents: &[u8]) -> Result<Self, VisudoError> {
+        // Remove anything that's there and create it fresh
+        // Don't follow anything that's been put in its place
+        let _ = fs::remove_file(path);
+        let ret = TempFile {
+            path: path.to_string(),
+        };
+        let mut f = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)
+            .map_err(|e| ret.io_err(e))?;
+        f.write_all(contents).map_err(|e| ret.io_err(e))?;
+        Ok(ret)
+    }
+
+    /// Convenience function for IO errors on this file
+    fn io_err(&self, e: io::Error) -> VisudoError {
+        VisudoError::Io {
+            path: self.path.clone(),
+            cause: e,
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,78 @@
+//! Errors that `sus-visudo` can report
+//!
+//! Everything this binary does can fail in some way, and the user needs to be
+//! told why. This module defines the [VisudoError] type for that.
+
+use crate::permission::verify::sudoers::SudoersError;
+
+use nix::errno::Errno;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::process::ExitStatus;
+
+/// Type for reporting errors in `sus-visudo`
+///
+/// Either we failed to interact with the file system, the editor failed, or
+/// the policy itself had a problem.
+#[derive(Debug)]
+pub enum VisudoError {
+    /// The policy file is locked by someone else editing it
+    Busy { path: String },
+    /// An IO operation on `path` failed
+    Io { path: String, cause: std::io::Error },
+    /// Generic failure of a system call
+    SyscallFailure { name: &'static str, err: Errno },
+
+    /// The editor could not be started
+    EditorNotRun {
+        editor: String,
+        cause: std::io::Error,
+    },
+    /// The editor exited unsuccessfully
+    EditorFailed { editor: String, status: ExitStatus },
+
+    /// The policy failed to load
+    Policy { cause: SudoersError },
+}
+
+impl Display for VisudoError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            VisudoError::Busy { path } => {
+                write!(
+                    f,
+                    "{} is being edited by someone else - try again later",
+                    path
+                )?;
+            }
+            VisudoError::Io { path, cause } => {
+                write!(f, "Failed to access {} - {}", path, cause)?;
+            }
+            VisudoError::SyscallFailure { name, err } => {
+                write!(f, "Failed to make system call - `{}` {}", name, err)?;
+            }
+            VisudoError::EditorNotRun { editor, cause } => {
+                write!(f, "Failed to run editor {} - {}", editor, cause)?;
+            }
+            VisudoError::EditorFailed { editor, status } => {
+                write!(f, "Editor {} failed - {}", editor, status)?;
+            }
+            VisudoError::Policy { cause } => {
+                write!(f, "{}", cause)?;
+            }
+        };
+        Ok(())
+    }
+}
+
+impl Error for VisudoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VisudoError::Io { cause, .. } => Some(cause),
+            VisudoError::EditorNotRun { cause, .. } => Some(cause),
+            VisudoError::Policy { cause } => Some(cause),
+            _ => None,
+        }
+    }
+}
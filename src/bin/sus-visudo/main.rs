@@ -0,0 +1,56 @@
+//! Safely edit the SUS policy
+//!
+//! A mistake in the policy file locks everyone out. This binary is modeled
+//! after `visudo`. It edits the policy on a locked, temporary copy, and only
+//! installs it once it loads without error. It can also just check a policy,
+//! which is useful for testing policies before they are deployed.
+//!
+//! To make sure the checks match what the kernel does, this binary is built
+//! with the kernel's own modules for loading the policy.
+
+mod config;
+mod edit;
+mod error;
+mod option;
+
+// The kernel's modules
+// Much of them goes unused here
+#[allow(dead_code, unused_imports)]
+#[path = "../sus-kernel/executable/mod.rs"]
+mod executable;
+#[allow(dead_code, unused_imports)]
+#[path = "../sus-kernel/log/mod.rs"]
+mod log;
+#[allow(dead_code, unused_imports)]
+#[path = "../sus-kernel/permission/mod.rs"]
+mod permission;
+#[allow(dead_code, unused_imports)]
+#[path = "../sus-kernel/setting/mod.rs"]
+mod setting;
+
+use std::process::exit;
+use structopt::StructOpt;
+
+use option::CommandLineOptions;
+
+/// The entrypoint of the binary
+///
+/// As is standard practice in Rust, most of the work is done by internal
+/// libraries, and this function simply calls into those libraries. It checks
+/// or edits the policy, printing out errors if there were any.
+fn main() {
+    let opts = CommandLineOptions::from_args();
+
+    // Do what the user asked
+    let res = if opts.check {
+        edit::check(&opts).map(|_| println!("{}: parsed OK", opts.path()))
+    } else {
+        edit::edit(&opts)
+    };
+
+    // Print errors
+    if let Err(e) = res {
+        println!("Error: {}", e);
+        exit(1);
+    }
+}
@@ -0,0 +1,50 @@
+//! Parse command line options from the user
+//!
+//! This module defines a [CommandLineOptions] struct, which is populated from
+//! the command line using the `structopt` library. It's modeled after the
+//! options `visudo` takes.
+
+use structopt::clap::AppSettings;
+use structopt::StructOpt;
+
+use crate::config;
+
+/// The `sus-visudo` interface
+///
+/// Edit the policy file safely, or just check it for errors.
+// We have to convince `clap` to not put the version. See:
+// <https://github.com/TeXitoi/structopt/issues/81
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "sus-visudo",
+    global_settings = &[AppSettings::DisableVersion]
+)]
+pub struct CommandLineOptions {
+    /// Only check the policy for errors, without editing it
+    #[structopt(short = "c")]
+    pub check: bool,
+
+    /// The policy file to work on, instead of the installed one
+    ///
+    /// When checking a file given here, its owner and mode are not checked.
+    /// This allows checking a policy before it is installed.
+    #[structopt(short = "f")]
+    pub file: Option<String>,
+}
+
+impl CommandLineOptions {
+    /// Function to get the path of the policy file to work on
+    pub fn path(&self) -> &str {
+        match &self.file {
+            None => config::SUDOER_PATH,
+            Some(f) => f,
+        }
+    }
+
+    /// Function to get whether to check the owner and mode of policy files
+    ///
+    /// This is only skipped if the user gave a file explicitly.
+    pub fn check_secure(&self) -> bool {
+        self.file.is_none()
+    }
+}
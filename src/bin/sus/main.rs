@@ -31,13 +31,11 @@ fn main() {
         Ok(o) => o,
     };
     // Execute and print any errors
-    let res = opts.execute();
-    if let Err(e) = res {
-        println!("Error: {}", e);
-        exit(101);
-    }
-
-    // We should never be able to reach here
-    // The execution should happen before
-    println!("Successfully failed");
+    // This only returns if it failed, since the kernel replaces us otherwise
+    let e = match opts.execute() {
+        Ok(never) => match never {},
+        Err(e) => e,
+    };
+    println!("Error: {}", e);
+    exit(101);
 }
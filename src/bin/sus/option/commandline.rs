@@ -5,8 +5,9 @@
 //! converted to a list of arguments to pass to the kernel via `exec`.
 
 use std::collections::HashSet;
-use std::ffi::CString;
-use std::os::unix::ffi::OsStringExt;
+use std::ffi::{CString, OsStr};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Component, Path, PathBuf};
 
 use structopt::clap::AppSettings;
 use structopt::StructOpt;
@@ -19,6 +20,7 @@ use which;
 
 use super::OptionsError;
 use super::OptionsLike;
+use crate::config;
 
 /// The `sus` interface
 ///
@@ -52,7 +54,71 @@ pub struct CommandLineOptions {
     #[structopt(short = "i")]
     shell_login: bool,
 
-    /// The binary to execute and the arguments to give it
+    /// Edit the files given, instead of running a command
+    ///
+    /// Each file is copied for you to edit with your own editor, as yourself,
+    /// then copied back. The policy must allow each file with `sudoedit`.
+    #[structopt(short = "e", conflicts_with_all = &["shell", "shell-login"])]
+    edit: bool,
+
+    /// Run the command in the background
+    ///
+    /// You get your shell back right away. The command has no terminal, so it
+    /// can't be managed with job control, and it can't read from the terminal.
+    #[structopt(short = "b", conflicts_with = "edit")]
+    background: bool,
+
+    /// Fail instead of asking for a password
+    ///
+    /// This is for scripts, which shouldn't hang waiting for a password. If the
+    /// policy doesn't need one, the command runs as usual.
+    #[structopt(short = "n")]
+    non_interactive: bool,
+
+    /// Read the password from standard input instead of the terminal
+    ///
+    /// The prompt is written to standard error. The password ends at a newline.
+    #[structopt(short = "S")]
+    stdin: bool,
+
+    /// Ask for the password with this prompt
+    ///
+    /// `%u` and `%p` are replaced with your name, `%U` with the name of the
+    /// user to run as, `%h` with the host name up to the first `.`, `%H` with
+    /// all of it, and `%%` with a `%`.
+    #[structopt(short = "p")]
+    prompt: Option<String>,
+
+    /// Get the password from the program in `SUDO_ASKPASS`
+    ///
+    /// It's run as you, with the prompt as its only argument, and should print
+    /// the password on its standard output.
+    #[structopt(short = "A", conflicts_with = "stdin")]
+    askpass: bool,
+
+    /// Close file descriptors from this one up, instead of the policy's default
+    ///
+    /// It must be at least 3, and the policy must allow it with
+    /// `closefrom_override`.
+    #[structopt(short = "C")]
+    closefrom: Option<i32>,
+
+    /// Kill the command if it runs for longer than this many seconds
+    ///
+    /// The policy must allow it with `user_command_timeouts`. If the policy
+    /// sets a timeout too, the shorter one is used.
+    #[structopt(short = "T")]
+    command_timeout: Option<u64>,
+
+    /// Run the command in this directory
+    ///
+    /// The policy must allow it with `runcwd`, either set to `*` or to this
+    /// directory.
+    #[structopt(short = "D")]
+    chdir: Option<String>,
+
+    /// The binary to execute and the arguments to give it, or the files to
+    /// edit with `-e`
     #[structopt(parse(try_from_str = CString::new))]
     command: Vec<CString>,
 }
@@ -180,15 +246,27 @@ impl OptionsLike for CommandLineOptions {
     /// the `PATH` environment variable. If we can't find it, we error out.
     ///
     /// Additionally, if the user wants to run in a shell, we honor that by
-    /// returning "/bin/sh".
+    /// returning "/bin/sh". If they want to edit files, we return the kernel's
+    /// pseudo-command for that instead.
     fn binary(&self) -> Result<CString, OptionsError> {
+        // Editing doesn't run anything the user named
+        if self.edit {
+            if self.command.is_empty() {
+                return Err(OptionsError::BadParse {
+                    string: Some("-e needs at least one file".to_string()),
+                });
+            }
+            return CString::new(config::KERNEL_SUDOEDIT_PATH)
+                .map_err(|_| OptionsError::BadParse { string: None });
+        }
+
         // If the user wants to run a shell, give a hard-coded result
         if self.shell || self.shell_login {
             return CString::new("/bin/sh").map_err(|_| OptionsError::BadParse { string: None });
         }
 
         // Parse the command to a &str
-        let cmd = match self.command.get(0) {
+        let cmd = match self.command.first() {
             None => {
                 return Err(OptionsError::BinaryNotFound {
                     name: Some("Not supplied".to_string()),
@@ -210,13 +288,85 @@ impl OptionsLike for CommandLineOptions {
         }
     }
 
+    /// Function to get the settings to pass to the kernel
+    ///
+    /// The kernel checks these against the policy, but some are checked here
+    /// too so the user gets a useful error. For instance, like with `sudo`,
+    /// `-C` must be at least 3, and `-T` can't be zero. The kernel doesn't run
+    /// in the user's directory, so `-D` is made absolute here. The program for
+    /// `-A` is looked up in the user's `PATH` here too.
+    fn settings(&self) -> Result<Vec<(&'static str, String)>, OptionsError> {
+        let mut ret = Vec::new();
+        if let Some(n) = self.closefrom {
+            if n < 3 {
+                return Err(OptionsError::BadParse {
+                    string: Some(format!("-C {} must be at least 3", n)),
+                });
+            }
+            ret.push(("closefrom", n.to_string()));
+        }
+        if let Some(t) = self.command_timeout {
+            if t == 0 {
+                return Err(OptionsError::BadParse {
+                    string: Some("-T must be more than 0".to_string()),
+                });
+            }
+            ret.push(("command_timeout", t.to_string()));
+        }
+        if let Some(d) = &self.chdir {
+            let d = std::env::current_dir()
+                .map(|c| c.join(d))
+                .ok()
+                .and_then(|d| d.to_str().map(String::from))
+                .ok_or_else(|| OptionsError::BadParse {
+                    string: Some(format!("-D {} can't be used", d)),
+                })?;
+            ret.push(("cwd", d));
+        }
+        if self.background {
+            ret.push(("background", true.to_string()));
+        }
+        if self.non_interactive {
+            ret.push(("non_interactive", true.to_string()));
+        }
+        if self.stdin {
+            ret.push(("stdin", true.to_string()));
+        }
+        if let Some(p) = &self.prompt {
+            ret.push(("prompt", p.clone()));
+        }
+        if self.askpass {
+            let name = std::env::var_os("SUDO_ASKPASS").ok_or_else(|| OptionsError::BadParse {
+                string: Some("-A needs SUDO_ASKPASS to be set".to_string()),
+            })?;
+            let p = which::which(&name)
+                .ok()
+                .and_then(|p| p.to_str().map(String::from))
+                .ok_or_else(|| OptionsError::BinaryNotFound {
+                    name: Some(name.to_string_lossy().into_owned()),
+                })?;
+            ret.push(("askpass", p));
+        }
+        Ok(ret)
+    }
+
     /// Function to get the arguments to the binary
     ///
     /// This function returns the arguments to pass to the binary, including
     /// argument zero. If the user doesn't want a shell, this essentially passes
     /// the arguments the user gave. If they do, it concatenates all of them
-    /// together with spaces.
+    /// together with spaces. If they want to edit files, the files are made
+    /// absolute, since the kernel doesn't run in the user's directory.
     fn args(&self) -> Result<Vec<CString>, OptionsError> {
+        if self.edit {
+            let mut ret = vec![CString::new(config::KERNEL_SUDOEDIT_PATH)
+                .map_err(|_| OptionsError::BadParse { string: None })?];
+            for f in &self.command {
+                ret.push(absolute(f)?);
+            }
+            return Ok(ret);
+        }
+
         // Handle the easier case
         if !self.shell && !self.shell_login {
             return Ok(self.command.clone());
@@ -250,3 +400,26 @@ impl OptionsLike for CommandLineOptions {
         Ok(ret)
     }
 }
+
+/// Function to make a path absolute, relative to the current directory
+///
+/// `.` and `..` are resolved without looking at the filesystem. The kernel
+/// refuses to go through symbolic links anyway, so this gives the same file.
+fn absolute(path: &CString) -> Result<CString, OptionsError> {
+    let bad = || OptionsError::BadParse {
+        string: Some(path.to_string_lossy().into_owned()),
+    };
+    let path = Path::new(OsStr::from_bytes(path.as_bytes()));
+    let full = std::env::current_dir().map_err(|_| bad())?.join(path);
+    let mut ret = PathBuf::from("/");
+    for c in full.components() {
+        match c {
+            Component::Normal(n) => ret.push(n),
+            Component::ParentDir => {
+                ret.pop();
+            }
+            _ => (),
+        }
+    }
+    CString::new(ret.into_os_string().into_vec()).map_err(|_| bad())
+}
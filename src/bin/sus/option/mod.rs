@@ -38,6 +38,9 @@ pub struct Options {
     binary: CString,
     /// The arguments to pass to the executable
     args: Vec<CString>,
+
+    /// Anything else to ask the kernel for, as names and values
+    settings: Vec<(&'static str, String)>,
 }
 
 /// Trait to define things that can be parsed into [Options]
@@ -58,6 +61,8 @@ pub trait OptionsLike {
     fn binary(&self) -> Result<CString, OptionsError>;
     /// Function to get the arguments to the binary
     fn args(&self) -> Result<Vec<CString>, OptionsError>;
+    /// Function to get the settings to pass to the kernel
+    fn settings(&self) -> Result<Vec<(&'static str, String)>, OptionsError>;
 }
 
 impl Options {
@@ -76,6 +81,7 @@ impl Options {
             secondary_gids: ol.secondary_gids()?,
             binary: ol.binary()?,
             args: ol.args()?,
+            settings: ol.settings()?,
         })
     }
 
@@ -122,6 +128,14 @@ impl Options {
                 .collect::<Vec<String>>()
                 .join(","),
         )?;
+        // Escape the settings, since values could have commas in them
+        ret[config::KERNEL_COMMANDLINE_SETTINGS_IDX] = make_cstring(
+            self.settings
+                .iter()
+                .map(|(n, v)| format!("{}={}", n, v.replace('\\', "\\\\").replace(',', "\\,")))
+                .collect::<Vec<String>>()
+                .join(","),
+        )?;
         ret[config::KERNEL_COMMANDLINE_BINARY_IDX] = self.binary.clone();
 
         // Push arguments
@@ -199,15 +213,13 @@ impl Display for OptionsError {
                     write!(f, " - ")?;
                 }
                 // Write the name
-                match name {
-                    Some(n) => write!(f, "`{}`", n)?,
-                    None => (),
-                };
+                if let Some(n) = name {
+                    write!(f, "`{}`", n)?;
+                }
                 // Write the error
-                match err {
-                    Some(e) => write!(f, " {}", e)?,
-                    None => (),
-                };
+                if let Some(e) = err {
+                    write!(f, " {}", e)?;
+                }
             }
         };
 